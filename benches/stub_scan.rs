@@ -0,0 +1,49 @@
+use std::io::{self, Cursor};
+use std::time::SystemTime;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use phar::{read, Compression, Reader, Signature};
+
+fn build_phar(stub_size: usize) -> io::Result<Vec<u8>> {
+    let mut stub = b"<?php\n".to_vec();
+    stub.resize(stub_size, b'#');
+
+    let mut cursor = Cursor::new(Vec::new());
+    let mut writer = phar::create(&mut cursor, Signature::sha256())
+        .stub(&stub[..])?
+        .metadata(&b""[..])?;
+    writer.entry(
+        &b"foo"[..],
+        &b""[..],
+        SystemTime::now(),
+        0o664,
+        Compression::None,
+    )?;
+    let mut contents = writer.contents()?;
+    contents.feed(&b"bar"[..])?;
+    Ok(cursor.into_inner())
+}
+
+fn stub_scan(c: &mut Criterion) {
+    let mut group = c.benchmark_group("stub_scan");
+    for &size in &[1 << 10, 1 << 20, 16 << 20] {
+        let phar = build_phar(size).expect("cannot build phar");
+        let _ = group.throughput(Throughput::Bytes(phar.len() as u64));
+        let _ = group.bench_with_input(BenchmarkId::from_parameter(size), &phar, |b, phar| {
+            b.iter(|| {
+                Reader::<_, read::index::NoIndex>::read(
+                    Cursor::new(&phar[..]),
+                    read::Options::builder()
+                        .cache_stub(false)
+                        .verify_signature(false)
+                        .build(),
+                )
+                .expect("cannot read phar")
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, stub_scan);
+criterion_main!(benches);
@@ -1,10 +1,20 @@
-use std::path::PathBuf;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, IsTerminal, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::UNIX_EPOCH;
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use phar::progress::{EntryEvent, Observer, Totals};
+use phar::read::{self, index, Glob, Reader};
+use phar::write::{walk_directory, BuildOptions, PharDir, Plan, Source, Symlinks};
+use phar::{merge, repack, ArchiveCompression, Compression, Level, Signature};
 
 use structopt::StructOpt;
 
+/// The stub of phar files created by `add`
+const DEFAULT_STUB: &[u8] = b"<?php ";
+
 #[derive(StructOpt)]
 enum Args {
     /// Reads or sets phar stub
@@ -65,7 +75,10 @@ enum Args {
         /// Path to the phar file
         #[structopt(parse(from_os_str))]
         dest: PathBuf,
-        /// Paths of files to add
+        /// Paths of files or directories to add.
+        /// Each source is added under its file name, e.g. `../lib` as `lib`,
+        /// except that the contents of `.` are added directly.
+        #[structopt(required = true, min_values = 1)]
         sources: Vec<PathBuf>,
         /// Base directory in phar to add the sources.
         /// Incompatible with `--rename`.
@@ -76,6 +89,81 @@ enum Args {
         /// Incompatible with `--base`.
         #[structopt(long)]
         rename: Option<String>,
+        /// The compression of the added files
+        /// (none, zlib, bzip2, optionally followed by `:LEVEL`, or zopfli)
+        #[structopt(long, default_value = "none")]
+        compression: CompressionArg,
+        /// Compresses files matching a glob pattern differently, in the form `GLOB=COMPRESSION`.
+        /// The first matching rule is used.
+        #[structopt(long = "rule", number_of_values = 1)]
+        rules: Vec<RuleArg>,
+        /// Skips files whose names in the phar match a glob pattern
+        #[structopt(long, number_of_values = 1)]
+        exclude: Vec<GlobArg>,
+        /// How symbolic links in source directories are handled
+        /// (follow, contained, skip, error).
+        /// `contained` follows links and refuses links pointing outside the source directory.
+        #[structopt(long, default_value = "contained")]
+        symlinks: SymlinksArg,
+        /// The signature algorithm of the phar file (md5, sha1, sha256, sha512)
+        #[structopt(long, default_value = "sha256")]
+        signature: SignatureAlgo,
+        /// Lists the files that would be added with their estimated compressed sizes
+        /// without writing the phar file
+        #[structopt(long)]
+        dry_run: bool,
+    },
+    /// Merges multiple phar files into one
+    Merge {
+        /// Path to the output phar file
+        #[structopt(parse(from_os_str))]
+        dest: PathBuf,
+        /// Paths of the phar files to merge.
+        /// Use `PREFIX=PATH` to place the entries of a file under the directory PREFIX.
+        #[structopt(required = true, min_values = 1)]
+        sources: Vec<String>,
+        /// What to do if multiple sources contain a file with the same name
+        /// (error, first, last)
+        #[structopt(long, default_value = "error")]
+        conflicts: ConflictPolicy,
+        /// The zero-based index of the source to take the stub from
+        #[structopt(long, default_value = "0")]
+        stub_from: usize,
+        /// The zero-based index of the source to take the alias from
+        #[structopt(long, default_value = "0")]
+        alias_from: usize,
+        /// The zero-based index of the source to take the metadata from
+        #[structopt(long, default_value = "0")]
+        metadata_from: usize,
+        /// The signature algorithm of the output file (md5, sha1, sha256, sha512)
+        #[structopt(long, default_value = "sha256")]
+        signature: SignatureAlgo,
+    },
+    /// Rewrites a phar file with a different compression, signature algorithm or format
+    Repack {
+        /// Path to the phar file, which may be a native, tar or zip based phar
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        /// Path to the output phar file
+        #[structopt(parse(from_os_str))]
+        dest: PathBuf,
+        /// The compression of files not matched by any `--rule`
+        /// (none, zlib, bzip2, optionally followed by `:LEVEL`, or zopfli).
+        /// If not set, files keep their original compression.
+        #[structopt(long)]
+        compression: Option<CompressionArg>,
+        /// Compresses files matching a glob pattern differently, in the form `GLOB=COMPRESSION`.
+        /// The first matching rule is used.
+        #[structopt(long = "rule", number_of_values = 1)]
+        rules: Vec<RuleArg>,
+        /// The signature algorithm of the output file (md5, sha1, sha256, sha512).
+        /// If not set, the original algorithm is kept.
+        #[structopt(long)]
+        signature: Option<SignatureAlgo>,
+        /// The format of the output file (phar, tar, zip).
+        /// Tar based phars cannot compress individual files.
+        #[structopt(long, default_value = "phar")]
+        format: FormatArg,
     },
 }
 
@@ -102,7 +190,548 @@ impl FromStr for SignatureAlgo {
     }
 }
 
+impl SignatureAlgo {
+    fn signature(&self) -> Signature {
+        match self {
+            Self::Md5 => Signature::md5(),
+            Self::Sha1 => Signature::sha1(),
+            Self::Sha256 => Signature::sha256(),
+            Self::Sha512 => Signature::sha512(),
+        }
+    }
+}
+
+struct ConflictPolicy(merge::Conflicts);
+
+impl FromStr for ConflictPolicy {
+    type Err = &'static str;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        Ok(Self(match str {
+            "error" => merge::Conflicts::Error,
+            "first" => merge::Conflicts::KeepFirst,
+            "last" => merge::Conflicts::KeepLast,
+            _ => return Err("unknown conflict policy"),
+        }))
+    }
+}
+
+struct CompressionArg(Compression);
+
+impl FromStr for CompressionArg {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        let (method, level) =
+            match str.split_once(':') {
+                Some((method, level)) => {
+                    (
+                        method,
+                        Some(level.parse().ok().and_then(Level::new).ok_or_else(|| {
+                            format!("invalid compression level {:?} (1-9)", level)
+                        })?),
+                    )
+                }
+                None => (str, None),
+            };
+        Ok(Self(match method {
+            "none" => Compression::None,
+            "zlib" | "gzip" => Compression::Zlib(level.unwrap_or(Level::DEFAULT)),
+            "bzip" | "bzip2" => Compression::Bzip(level.unwrap_or(Level::BEST)),
+            "zopfli" if level.is_none() => Compression::Zopfli,
+            _ => return Err(format!("unknown compression method {:?}", method)),
+        }))
+    }
+}
+
+struct FormatArg(repack::Format);
+
+impl FromStr for FormatArg {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        Ok(Self(match str {
+            "phar" => repack::Format::Phar,
+            #[cfg(feature = "tar")]
+            "tar" => repack::Format::Tar,
+            "zip" => repack::Format::Zip,
+            _ => return Err(format!("unknown format {:?}", str)),
+        }))
+    }
+}
+
+struct RuleArg(Glob, Compression);
+
+impl FromStr for RuleArg {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        let (glob, compression) = str
+            .rsplit_once('=')
+            .ok_or("expected a rule in the form GLOB=COMPRESSION")?;
+        let glob: GlobArg = glob.parse()?;
+        let compression: CompressionArg = compression.parse()?;
+        Ok(Self(glob.0, compression.0))
+    }
+}
+
+struct SymlinksArg(Symlinks);
+
+impl FromStr for SymlinksArg {
+    type Err = &'static str;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        Ok(Self(match str {
+            "follow" => Symlinks::Follow,
+            "contained" => Symlinks::Contained,
+            "skip" => Symlinks::Skip,
+            "error" => Symlinks::Error,
+            _ => return Err("unknown symlink policy"),
+        }))
+    }
+}
+
+struct GlobArg(Glob);
+
+impl FromStr for GlobArg {
+    type Err = String;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        Glob::new(str.as_bytes())
+            .map(Self)
+            .map_err(|err| err.to_string())
+    }
+}
+
 fn main() -> Result<()> {
     let args = Args::from_args();
+    match args {
+        Args::Verify { file } => verify(&file),
+        Args::Add {
+            dest,
+            sources,
+            base,
+            rename,
+            compression,
+            rules,
+            exclude,
+            symlinks,
+            signature,
+            dry_run,
+        } => add(
+            &dest,
+            &sources,
+            base.as_deref(),
+            rename.as_deref(),
+            compression.0,
+            &rules,
+            &exclude,
+            symlinks.0,
+            signature.signature(),
+            dry_run,
+        ),
+        Args::Merge {
+            dest,
+            sources,
+            conflicts,
+            stub_from,
+            alias_from,
+            metadata_from,
+            signature,
+        } => merge(
+            &dest,
+            &sources,
+            merge::Options::builder()
+                .conflicts(conflicts.0)
+                .stub_from(stub_from)
+                .alias_from(alias_from)
+                .metadata_from(metadata_from)
+                .build(),
+            signature.signature(),
+        ),
+        Args::Repack {
+            file,
+            dest,
+            compression,
+            rules,
+            signature,
+            format,
+        } => {
+            let rules = rules
+                .into_iter()
+                .map(|RuleArg(glob, compression)| repack::Rule::new(glob, compression))
+                .collect();
+            let options = match compression {
+                Some(compression) => repack::Options::builder()
+                    .compression(compression.0)
+                    .rules(rules)
+                    .format(format.0)
+                    .build(),
+                None => repack::Options::builder()
+                    .rules(rules)
+                    .format(format.0)
+                    .build(),
+            };
+            repack(
+                &file,
+                &dest,
+                options,
+                signature.map(|signature| signature.signature()),
+            )
+        }
+        _ => bail!("this command is not implemented yet"),
+    }
+}
+
+fn verify(file: &Path) -> Result<()> {
+    let read = BufReader::new(
+        File::open(file).with_context(|| format!("failed to open {}", file.display()))?,
+    );
+    let mut phar = Reader::<_, index::NoIndex>::open(read, read::Options::builder().build())
+        .context("failed to read phar file")?;
+
+    match phar.archive_compression() {
+        ArchiveCompression::None => {}
+        ArchiveCompression::Gzip(_) => println!("Compressed with gzip"),
+        ArchiveCompression::Bzip(_) => println!("Compressed with bzip2"),
+    }
+    println!("API version: {}", phar.api_version());
+    println!("Entries: {}", phar.entry_count());
+    match phar.signature() {
+        Some(signature) => println!(
+            "Signature: {} {}",
+            signature.algorithm(),
+            signature.hex_digest()
+        ),
+        None => println!("Signature: none"),
+    }
+
+    let diagnostics = phar.validate().context("failed to validate phar file")?;
+    for diagnostic in &diagnostics {
+        eprintln!("{}", diagnostic);
+    }
+    if !diagnostics.is_empty() {
+        bail!("found {} structural inconsistencies", diagnostics.len());
+    }
+
+    println!("OK");
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn add(
+    dest: &Path,
+    sources: &[PathBuf],
+    base: Option<&str>,
+    rename: Option<&str>,
+    compression: Compression,
+    rules: &[RuleArg],
+    exclude: &[GlobArg],
+    symlinks: Symlinks,
+    signature: Signature,
+    dry_run: bool,
+) -> Result<()> {
+    if base.is_some() && rename.is_some() {
+        bail!("--base and --rename cannot be used together");
+    }
+    if rename.is_some() && sources.len() != 1 {
+        bail!("--rename requires exactly one source");
+    }
+    if dest.exists() {
+        bail!(
+            "{} already exists; adding files to an existing phar is not implemented yet",
+            dest.display()
+        );
+    }
+
+    let mut files = Vec::new();
+    for source in sources {
+        // sources are added under their own names,
+        // so `../lib` and `/abs/lib` are both added as `lib`
+        let prefix = match (rename, source.file_name()) {
+            (Some(rename), _) => PathBuf::from(rename),
+            (None, Some(name)) => Path::new(base.unwrap_or("")).join(name),
+            (None, None) => PathBuf::from(base.unwrap_or("")),
+        };
+        for entry in walk_directory(source, symlinks) {
+            let (relative, path) =
+                entry.with_context(|| format!("failed to read {}", source.display()))?;
+            let name = if relative.as_os_str().is_empty() {
+                prefix.clone()
+            } else {
+                prefix.join(relative)
+            };
+            files.push((name, path));
+        }
+    }
+
+    let mut plan = Plan::from_path_iter(
+        files.iter().map(|(name, path)| Ok((name, path))),
+        BuildOptions::builder()
+            .compression(compression)
+            .symlinks(symlinks)
+            .build(),
+    )
+    .context("failed to list files")?;
+    plan.retain(|entry| !exclude.iter().any(|glob| glob.0.is_match(&entry.name)));
+    for entry in plan.entries_mut() {
+        if let Some(rule) = rules.iter().find(|rule| rule.0.is_match(&entry.name)) {
+            entry.compression = rule.1;
+        }
+    }
+
+    if dry_run {
+        return print_plan(&plan, &signature);
+    }
+
+    let output = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(dest)
+        .with_context(|| format!("failed to create {}", dest.display()))?;
+    phar::create(output, signature)
+        .stub(DEFAULT_STUB)?
+        .metadata(&b""[..])?
+        .with_observer(Progress::new())
+        .build_from_plan(&plan)
+        .context("failed to build phar file")?;
+
+    println!("Created {}", dest.display());
+    Ok(())
+}
+
+fn print_plan(plan: &Plan, signature: &Signature) -> Result<()> {
+    println!(
+        "{:>4} {:>10} {:>10} {:>10}  NAME",
+        "MODE", "MTIME", "SIZE", "STORED"
+    );
+    let mut contents_size = 0;
+    for entry in plan.entries() {
+        let stored = entry
+            .estimate_size()
+            .with_context(|| format!("failed to read {}", entry.path.display()))?;
+        contents_size += stored;
+        let mtime = entry
+            .modified
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+        println!(
+            "{:>4o} {:>10} {:>10} {:>10}  {}",
+            entry.mode & 0o777,
+            mtime,
+            entry.size,
+            stored,
+            String::from_utf8_lossy(&entry.name)
+        );
+    }
+
+    let size = plan.size_with_contents(DEFAULT_STUB, b"", b"", signature, contents_size);
+    println!(
+        "{} entries, estimated phar size {} bytes",
+        plan.entries().len(),
+        size
+    );
+    Ok(())
+}
+
+/// Renders a progress bar on stderr if it is a terminal, and prints the totals at the end.
+struct Progress {
+    terminal: bool,
+}
+
+impl Progress {
+    const WIDTH: usize = 30;
+
+    fn new() -> Self {
+        Self {
+            terminal: io::stderr().is_terminal(),
+        }
+    }
+}
+
+impl Observer for Progress {
+    fn entry_done(&mut self, event: &EntryEvent<'_>) {
+        if !self.terminal {
+            return;
+        }
+        let done = event.index + 1;
+        let filled = done * Self::WIDTH / event.total;
+        let mut stderr = io::stderr();
+        let _ = write!(
+            stderr,
+            "\r\x1b[K[{}{}] {}/{} {}",
+            "=".repeat(filled),
+            " ".repeat(Self::WIDTH - filled),
+            done,
+            event.total,
+            String::from_utf8_lossy(event.name),
+        );
+        let _ = stderr.flush();
+    }
+
+    fn finished(&mut self, totals: &Totals) {
+        if self.terminal {
+            eprint!("\r\x1b[K");
+        }
+        match totals.ratio() {
+            Some(ratio) => println!(
+                "Added {} files, {} bytes stored as {} bytes ({:.1}%) in {:.2?}",
+                totals.entries,
+                totals.bytes_read,
+                totals.bytes_written,
+                ratio * 100.0,
+                totals.elapsed
+            ),
+            None => println!("Added {} files in {:.2?}", totals.entries, totals.elapsed),
+        }
+    }
+}
+
+fn merge(
+    dest: &Path,
+    sources: &[String],
+    options: merge::Options,
+    signature: Signature,
+) -> Result<()> {
+    let mut inputs = Vec::new();
+    for source in sources {
+        let (prefix, file) = match source.split_once('=') {
+            Some((prefix, file)) => (prefix, file),
+            None => ("", source.as_str()),
+        };
+        let read =
+            BufReader::new(File::open(file).with_context(|| format!("failed to open {}", file))?);
+        let phar = Reader::<_, index::NameIndexMap>::read(read, read::Options::builder().build())
+            .with_context(|| format!("failed to read phar file {}", file))?;
+        inputs.push(merge::Input::new(phar).with_prefix(prefix));
+    }
+
+    let output = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(dest)
+        .with_context(|| format!("failed to create {}", dest.display()))?;
+    merge::merge(&mut inputs, output, signature, options).context("failed to merge phar files")?;
+
+    println!("Merged {} files into {}", inputs.len(), dest.display());
     Ok(())
 }
+
+fn repack(
+    file: &Path,
+    dest: &Path,
+    options: repack::Options,
+    signature: Option<Signature>,
+) -> Result<()> {
+    let mut read = BufReader::new(
+        File::open(file).with_context(|| format!("failed to open {}", file.display()))?,
+    );
+    match detect_format(&mut read)? {
+        InputFormat::Phar => {
+            let mut phar =
+                Reader::<_, index::NameIndexMap>::read(read, read::Options::builder().build())
+                    .context("failed to read phar file")?;
+            repack_phar(&mut phar, dest, options, signature)?;
+        }
+        #[cfg(feature = "tar")]
+        InputFormat::Tar => {
+            let mut source = phar::write::TarSource::new(read);
+            let dir = source.phar_dir().context("failed to read tar file")?;
+            let mut phar = to_native(&mut source, &dir, signature.as_ref())
+                .context("failed to read tar based phar")?;
+            repack_phar(&mut phar, dest, options, signature)?;
+        }
+        #[cfg(feature = "zip")]
+        InputFormat::Zip => {
+            let mut source =
+                phar::write::ZipSource::new(read).context("failed to read zip file")?;
+            let dir = source.phar_dir().context("failed to read zip file")?;
+            let mut phar = to_native(&mut source, &dir, signature.as_ref())
+                .context("failed to read zip based phar")?;
+            repack_phar(&mut phar, dest, options, signature)?;
+        }
+    }
+
+    println!("Repacked {} into {}", file.display(), dest.display());
+    Ok(())
+}
+
+fn repack_phar<R: Read + Seek>(
+    phar: &mut Reader<R, index::NameIndexMap>,
+    dest: &Path,
+    options: repack::Options,
+    signature: Option<Signature>,
+) -> Result<()> {
+    let signature = match signature {
+        Some(signature) => signature,
+        None => phar
+            .signature()
+            .and_then(|signature| Signature::new(signature.algorithm()))
+            .context("the phar file has no signature; use --signature to add one")?,
+    };
+
+    let output = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(dest)
+        .with_context(|| format!("failed to create {}", dest.display()))?;
+    repack::repack(phar, output, signature, options).context("failed to repack phar file")
+}
+
+/// The format of a phar file passed to `repack`
+enum InputFormat {
+    Phar,
+    #[cfg(feature = "tar")]
+    Tar,
+    #[cfg(feature = "zip")]
+    Zip,
+}
+
+/// Detects tar and zip based phars by the magic numbers of tar and zip files.
+fn detect_format(read: &mut (impl Read + Seek)) -> Result<InputFormat> {
+    let mut header = Vec::new();
+    let _ = read.by_ref().take(262).read_to_end(&mut header)?;
+    let _ = read.seek(SeekFrom::Start(0))?;
+
+    #[cfg(feature = "zip")]
+    if header.starts_with(b"PK\x03\x04") {
+        return Ok(InputFormat::Zip);
+    }
+    #[cfg(feature = "tar")]
+    if header.get(257..262) == Some(&b"ustar"[..]) {
+        return Ok(InputFormat::Tar);
+    }
+    Ok(InputFormat::Phar)
+}
+
+/// Converts a tar or zip based phar into a native phar in memory,
+/// signed with the algorithm of `signature` or the original signature.
+#[cfg(any(feature = "tar", feature = "zip"))]
+fn to_native(
+    source: &mut impl Source,
+    dir: &PharDir,
+    signature: Option<&Signature>,
+) -> Result<Reader<io::Cursor<Vec<u8>>, index::NameIndexMap>> {
+    let algorithm = signature
+        .map(Signature::algorithm)
+        .or(dir.signature)
+        .context("the phar file has no signature; use --signature to add one")?;
+    let signature = Signature::new(algorithm)
+        .with_context(|| format!("{} signatures are not supported", algorithm))?;
+
+    let mut file = io::Cursor::new(Vec::new());
+    phar::create(&mut file, signature)
+        .stub(dir.stub.as_deref().unwrap_or(DEFAULT_STUB))?
+        .alias(&dir.alias[..])?
+        .metadata(&dir.metadata[..])?
+        .build_from_source(source, Compression::None)?;
+    Ok(Reader::read(
+        io::Cursor::new(file.into_inner()),
+        read::Options::builder().build(),
+    )?)
+}
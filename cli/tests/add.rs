@@ -0,0 +1,82 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use phar::read::{self, index::NameIndexMap};
+use phar::Reader;
+
+/// Returns an empty directory for test output.
+fn output_dir(name: &str) -> Result<PathBuf> {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+/// Runs the `phar` command in `dir` and checks that it succeeds.
+fn run<S: AsRef<OsStr>>(dir: &Path, args: &[S]) -> Result<()> {
+    let output = Command::new(env!("CARGO_BIN_EXE_phar"))
+        .args(args)
+        .current_dir(dir)
+        .output()?;
+    assert!(
+        output.status.success(),
+        "phar failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Ok(())
+}
+
+/// Returns the sorted entry names in the phar file at `path`.
+fn names(path: &Path) -> Result<Vec<String>> {
+    let mut phar = Reader::<_, NameIndexMap>::read(
+        io::BufReader::new(fs::File::open(path)?),
+        read::Options::builder().build(),
+    )?;
+    let mut names = Vec::new();
+    phar.for_each_entry(|entry| {
+        names.push(String::from_utf8_lossy(entry.name()).into_owned());
+        Ok(())
+    })?;
+    names.sort();
+    Ok(names)
+}
+
+#[test]
+fn test_add_source_names() -> Result<()> {
+    let dir = output_dir("test_add_source_names")?;
+    fs::create_dir_all(dir.join("lib/sub"))?;
+    fs::write(dir.join("lib/sub/a.php"), "<?php echo 'a';")?;
+    fs::create_dir_all(dir.join("vendor/dep"))?;
+    fs::write(dir.join("vendor/dep/b.php"), "<?php echo 'b';")?;
+    fs::create_dir(dir.join("app"))?;
+    let app = dir.join("app");
+
+    // relative paths outside the working directory and absolute paths
+    // are added under their file names
+    let absolute = dir.join("vendor/dep");
+    run(
+        &app,
+        &[
+            OsStr::new("add"),
+            OsStr::new("parent.phar"),
+            OsStr::new("../lib"),
+            absolute.as_os_str(),
+        ],
+    )?;
+    assert_eq!(
+        names(&app.join("parent.phar"))?,
+        vec!["dep/b.php", "lib/sub/a.php"]
+    );
+
+    run(&app, &["add", "base.phar", "../lib/sub", "--base", "src"])?;
+    assert_eq!(names(&app.join("base.phar"))?, vec!["src/sub/a.php"]);
+
+    run(&dir.join("lib"), &["add", "../app/dot.phar", "."])?;
+    assert_eq!(names(&app.join("dot.phar"))?, vec!["sub/a.php"]);
+    Ok(())
+}
@@ -0,0 +1,40 @@
+#![no_main]
+
+use std::io::{self, Cursor};
+
+use libfuzzer_sys::fuzz_target;
+use phar::read::{self, index, SliceReader};
+use phar::Reader;
+
+fn options() -> read::Options {
+    read::Options::builder()
+        .max_manifest_size(1 << 20)
+        .max_entries(1 << 10)
+        .max_name_len(1 << 10)
+        .max_metadata_len(1 << 16)
+        .max_entry_size(1 << 20)
+        .max_compression_ratio(1 << 10)
+        .build()
+}
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(mut reader) = Reader::<_, index::OffsetOnly>::read(Cursor::new(data), options()) {
+        let _ = reader.stub_bytes().map(|stub| stub.as_ref().len());
+        let _ = reader.metadata_bytes().map(|metadata| metadata.as_ref().len());
+        let _ = reader.for_each_file(|_, contents| io::copy(contents, &mut io::sink()).map(|_| ()));
+    }
+
+    if let Ok(mut reader) = Reader::<_, index::MetadataBTreeMap>::read(Cursor::new(data), options())
+    {
+        let _ = reader.for_each_file(|_, contents| io::copy(contents, &mut io::sink()).map(|_| ()));
+    }
+
+    if let Ok(mut reader) = Reader::<_, index::Tree>::read(Cursor::new(data), options()) {
+        let _ = reader.validate();
+        let _ = reader.index().read_dir(b"").map(|entries| entries.count());
+    }
+
+    if let Ok(reader) = SliceReader::<index::NameHashMap>::read(data, options()) {
+        let _ = reader.for_each_file(|_, entry| entry.contents().map(|_| ()));
+    }
+});
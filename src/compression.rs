@@ -1,52 +1,235 @@
 use std::io::{Error, ErrorKind, Read, Result, Write};
 
-/// A file compression method.
+#[cfg(feature = "async")]
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncWrite};
+
+#[cfg(all(feature = "reader", feature = "comp-zlib"))]
+use crate::read::{InflateCheckpoint, Inflater};
+#[cfg(all(
+    feature = "writer",
+    any(
+        feature = "comp-zlib-c",
+        feature = "comp-zlib-rs",
+        feature = "comp-bzip",
+        feature = "comp-bzip-rs"
+    )
+))]
+use crate::write::encoder::Encoder;
+
+/// A file compression method as stored in the entry flags.
 ///
 /// `Zlib` and `Bzip` are available even without their corresponding features,
 /// because this is used in file encoding flags.
-#[derive(Debug, Clone, Copy)]
-pub enum Compression {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionMethod {
     /// No compression
     None,
-    /// zlib (gzip) compression
-    ///
-    /// The inner `u32` is the deflate compression level.
-    /// This value is *always* zero when passed from the library;
-    /// it is only used in the `write` module.
-    ///
-    /// See [`flate2::Compression`](https://docs.rs/flate2/1/flate2/struct.Compression.html) for
-    /// details.
-    Zlib(u32),
-    /// bzip compression
-    ///
-    /// The inner `u32` is the bzip2 compression level.
-    /// This value is *always* zero when passed from the library;
-    /// it is only used in the `write` module.
-    ///
-    /// See [`bzip2::Compression`](https://docs.rs/bzip2/0.4/bzip2/struct.Compression.html) for
-    /// details.
-    Bzip(u32),
+    /// zlib (raw deflate) compression
+    Zlib,
+    /// bzip2 compression
+    Bzip,
 }
 
-impl Compression {
+impl CompressionMethod {
     pub(crate) fn bit(self) -> u32 {
         match self {
             Self::None => 0,
-            Self::Zlib(_) => 0x00001000,
-            Self::Bzip(_) => 0x00002000,
+            Self::Zlib => 0x00001000,
+            Self::Bzip => 0x00002000,
         }
     }
 
     pub(crate) fn from_bit(bit: u32) -> Self {
         if bit & 0x00001000 > 0 {
-            return Self::Zlib(0);
+            return Self::Zlib;
         }
         if bit & 0x00002000 > 0 {
-            return Self::Bzip(0);
+            return Self::Bzip;
         }
         Self::None
     }
 
+    /// Estimates the level used to compress a stream starting with `header`.
+    ///
+    /// bzip2 streams record their block size, which is the same as the level.
+    /// Raw deflate streams, as written by PHP, do not record the level at all.
+    ///
+    /// Returns `None` for uncompressed entries, deflate streams and streams too short to tell.
+    pub(crate) fn estimate_level(self, header: &[u8]) -> Option<Level> {
+        match self {
+            Self::None | Self::Zlib => None,
+            Self::Bzip => match *header {
+                [b'B', b'Z', b'h', size, ..] => Level::new(u32::from(size.wrapping_sub(b'0'))),
+                _ => None,
+            },
+        }
+    }
+
+    #[cfg(feature = "reader")]
+    pub(crate) fn make_read<'t>(self, read: impl Read + 't) -> Result<Box<dyn Read + 't>> {
+        match self {
+            Self::None => Ok(Box::new(read)),
+            #[cfg(feature = "comp-zlib")]
+            Self::Zlib => Ok(Box::new(flate2::read::DeflateDecoder::new(read))),
+            #[cfg(any(feature = "comp-bzip", feature = "comp-bzip-rs"))]
+            Self::Bzip => Ok(Box::new(bzip2::read::BzDecoder::new(read))),
+            #[allow(unreachable_patterns)] // unreachable when all features enabled
+            _ => Err(Error::new(
+                ErrorKind::Other,
+                "unsupported compression algorithm (not compiled with comp-zlib/comp-bzip feature)",
+            )),
+        }
+    }
+
+    /// Creates a decompressor that can be converted back into `read`.
+    #[cfg(feature = "reader")]
+    pub(crate) fn make_decoder<R: Read>(self, read: R) -> Result<Decoder<R>> {
+        match self {
+            Self::None => Ok(Decoder::None(read)),
+            #[cfg(feature = "comp-zlib")]
+            Self::Zlib => Ok(Decoder::Zlib(Inflater::new(read))),
+            #[cfg(any(feature = "comp-bzip", feature = "comp-bzip-rs"))]
+            Self::Bzip => Ok(Decoder::Bzip(bzip2::read::BzDecoder::new(read))),
+            #[allow(unreachable_patterns)] // unreachable when all features enabled
+            _ => Err(Error::new(
+                ErrorKind::Other,
+                "unsupported compression algorithm (not compiled with comp-zlib/comp-bzip feature)",
+            )),
+        }
+    }
+
+    #[cfg(all(feature = "reader", feature = "async"))]
+    pub(crate) fn make_async_read<'t>(
+        self,
+        read: impl AsyncBufRead + Unpin + Send + 't,
+    ) -> Result<Box<dyn AsyncRead + Unpin + Send + 't>> {
+        match self {
+            Self::None => Ok(Box::new(read)),
+            #[cfg(feature = "comp-zlib")]
+            Self::Zlib => Ok(Box::new(
+                async_compression::tokio::bufread::DeflateDecoder::new(read),
+            )),
+            #[cfg(any(feature = "comp-bzip", feature = "comp-bzip-rs"))]
+            Self::Bzip => Ok(Box::new(async_compression::tokio::bufread::BzDecoder::new(
+                read,
+            ))),
+            #[allow(unreachable_patterns)] // unreachable when all features enabled
+            _ => Err(Error::new(
+                ErrorKind::Other,
+                "unsupported compression algorithm (not compiled with comp-zlib/comp-bzip feature)",
+            )),
+        }
+    }
+}
+
+/// A compression level from 1 (fastest) to 9 (smallest output).
+///
+/// Level 0, which stores data without compression in both zlib and bzip2,
+/// cannot be represented; use `Compression::None` instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Level(u32);
+
+impl Level {
+    /// The fastest level
+    pub const FASTEST: Self = Self(1);
+    /// The default level of zlib
+    pub const DEFAULT: Self = Self(6);
+    /// The level producing the smallest output
+    pub const BEST: Self = Self(9);
+
+    /// Creates a level, returning `None` if `level` is not in `1..=9`.
+    pub fn new(level: u32) -> Option<Self> {
+        if (1..=9).contains(&level) {
+            Some(Self(level))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the numeric level.
+    pub fn get(self) -> u32 {
+        self.0
+    }
+}
+
+impl Default for Level {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Compression settings for writing file contents.
+///
+/// `Zlib` and `Bzip` use the default window size and work factor of the encoders;
+/// use `ZlibWindow` and `BzipWorkFactor` to change them.
+///
+/// The deflate backend of `flate2` is selected with the `comp-zlib-rs` (`zlib-rs`)
+/// and `comp-zlib-c` (system zlib) features, defaulting to `miniz_oxide`.
+/// bzip2 uses the C library with the `comp-bzip` feature
+/// or a pure-Rust port with the `comp-bzip-rs` feature, e.g. for WebAssembly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// No compression
+    None,
+    /// zlib (raw deflate) compression at the given level
+    ///
+    /// See [`flate2::Compression`](https://docs.rs/flate2/1/flate2/struct.Compression.html) for
+    /// details.
+    Zlib(Level),
+    /// bzip2 compression at the given level
+    ///
+    /// See [`bzip2::Compression`](https://docs.rs/bzip2/0.6/bzip2/struct.Compression.html) for
+    /// details.
+    Bzip(Level),
+    /// zlib (raw deflate) compression with a window of `2^window_bits` bytes
+    ///
+    /// `window_bits` must be in `9..=15`, where 15 is the default used by `Zlib`.
+    /// Smaller windows use less memory but compress worse.
+    /// PHP decompresses streams of any window size.
+    /// Requires the `comp-zlib-c` or `comp-zlib-rs` feature,
+    /// since `miniz_oxide` only supports 15-bit windows,
+    /// and is not supported by the async writer.
+    ZlibWindow {
+        /// The compression level
+        level: Level,
+        /// The base-two logarithm of the window size
+        window_bits: u8,
+    },
+    /// bzip2 compression with a custom work factor
+    ///
+    /// The work factor, at most 250, controls how much effort the encoder spends
+    /// on highly repetitive input before falling back to a slower sorting algorithm.
+    /// 0 selects the default of 30 used by `Bzip`.
+    /// Not supported by the async writer.
+    BzipWorkFactor {
+        /// The compression level
+        level: Level,
+        /// The work factor from 0 to 250
+        work_factor: u8,
+    },
+    /// zlib (raw deflate) compression with the zopfli encoder
+    ///
+    /// This produces smaller output than `Zlib(Level::BEST)` that PHP decompresses as usual,
+    /// but is about a hundred times slower.
+    /// Requires the `comp-zopfli` feature, and is not supported by the async writer.
+    Zopfli,
+}
+
+impl Compression {
+    /// Returns the compression method written in the entry flags.
+    pub fn method(self) -> CompressionMethod {
+        match self {
+            Self::None => CompressionMethod::None,
+            Self::Zlib(_) | Self::ZlibWindow { .. } => CompressionMethod::Zlib,
+            Self::Bzip(_) | Self::BzipWorkFactor { .. } => CompressionMethod::Bzip,
+            Self::Zopfli => CompressionMethod::Zlib,
+        }
+    }
+
+    pub(crate) fn bit(self) -> u32 {
+        self.method().bit()
+    }
+
     #[cfg(feature = "writer")]
     pub(crate) fn make_write<'t>(self, write: impl Write + 't) -> Result<Box<dyn Write + 't>> {
         match self {
@@ -54,29 +237,150 @@ impl Compression {
             #[cfg(feature = "comp-zlib")]
             Self::Zlib(level) => Ok(Box::new(flate2::write::DeflateEncoder::new(
                 write,
-                flate2::Compression::new(level),
+                flate2::Compression::new(level.get()),
             ))),
-            #[cfg(feature = "comp-bzip")]
+            #[cfg(any(feature = "comp-bzip", feature = "comp-bzip-rs"))]
             Self::Bzip(level) => Ok(Box::new(bzip2::write::BzEncoder::new(
                 write,
-                bzip2::Compression::new(level),
+                bzip2::Compression::new(level.get()),
+            ))),
+            #[cfg(any(feature = "comp-zlib-c", feature = "comp-zlib-rs"))]
+            Self::ZlibWindow { level, window_bits } => {
+                if !(9..=15).contains(&window_bits) {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        "the deflate window bits must be in 9..=15",
+                    ));
+                }
+                Ok(Box::new(Encoder::new(
+                    write,
+                    flate2::Compress::new_with_window_bits(
+                        flate2::Compression::new(level.get()),
+                        false,
+                        window_bits,
+                    ),
+                )))
+            }
+            #[cfg(not(any(feature = "comp-zlib-c", feature = "comp-zlib-rs")))]
+            Self::ZlibWindow { .. } => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "custom window sizes require the comp-zlib-c or comp-zlib-rs feature",
+            )),
+            #[cfg(any(feature = "comp-bzip", feature = "comp-bzip-rs"))]
+            Self::BzipWorkFactor { level, work_factor } => {
+                if work_factor > 250 {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        "the bzip2 work factor must be at most 250",
+                    ));
+                }
+                Ok(Box::new(Encoder::new(
+                    write,
+                    bzip2::Compress::new(
+                        bzip2::Compression::new(level.get()),
+                        u32::from(work_factor),
+                    ),
+                )))
+            }
+            #[cfg(feature = "comp-zopfli")]
+            Self::Zopfli => Ok(Box::new(zopfli::DeflateEncoder::new(
+                zopfli::Options::default(),
+                zopfli::BlockType::Dynamic,
+                write,
             ))),
             #[allow(unreachable_patterns)] // unreachable when all features enabled
+            _ => Err(Error::new(
+                ErrorKind::Other,
+                "unsupported compression algorithm (not compiled with comp-zlib/comp-bzip/comp-zopfli feature)",
+            )),
+        }
+    }
+
+    #[cfg(all(feature = "writer", feature = "async"))]
+    pub(crate) fn make_async_write<'t>(
+        self,
+        write: impl AsyncWrite + Unpin + Send + 't,
+    ) -> Result<Box<dyn AsyncWrite + Unpin + Send + 't>> {
+        #[cfg(any(feature = "comp-zlib", feature = "comp-bzip", feature = "comp-bzip-rs"))]
+        fn precise(level: Level) -> async_compression::Level {
+            use std::convert::TryFrom;
+            async_compression::Level::Precise(i32::try_from(level.get()).unwrap_or(i32::MAX))
+        }
+
+        match self {
+            Self::None => Ok(Box::new(write)),
+            #[cfg(feature = "comp-zlib")]
+            Self::Zlib(level) => Ok(Box::new(
+                async_compression::tokio::write::DeflateEncoder::with_quality(
+                    write,
+                    precise(level),
+                ),
+            )),
+            #[cfg(any(feature = "comp-bzip", feature = "comp-bzip-rs"))]
+            Self::Bzip(level) => Ok(Box::new(
+                async_compression::tokio::write::BzEncoder::with_quality(write, precise(level)),
+            )),
+            Self::Zopfli => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "zopfli compression is not supported by the async writer",
+            )),
+            Self::ZlibWindow { .. } | Self::BzipWorkFactor { .. } => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "custom window sizes and work factors are not supported by the async writer",
+            )),
+            #[allow(unreachable_patterns)] // unreachable when all features enabled
             _ => Err(Error::new(
                 ErrorKind::Other,
                 "unsupported compression algorithm (not compiled with comp-zlib/comp-bzip feature)",
             )),
         }
     }
+}
+
+/// Compression of a phar file as a whole, as produced by `Phar::compress` in PHP.
+///
+/// PHP runs `.phar.gz` and `.phar.bz2` files directly.
+/// The signature is computed over the uncompressed phar file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveCompression {
+    /// Not compressed as a whole
+    None,
+    /// gzip compression at the given level
+    Gzip(Level),
+    /// bzip2 compression at the given level
+    Bzip(Level),
+}
+
+impl ArchiveCompression {
+    /// Detects the compression of a file starting with `header`.
+    ///
+    /// The level of bzip2 files is read from the header exactly,
+    /// while the level of gzip files is estimated from the `XFL` field,
+    /// which only distinguishes the fastest and the best levels from the others.
+    pub(crate) fn detect(header: &[u8]) -> Self {
+        match *header {
+            [0x1f, 0x8b, _, _, _, _, _, _, xfl, ..] => Self::Gzip(match xfl {
+                2 => Level::BEST,
+                4 => Level::FASTEST,
+                _ => Level::DEFAULT,
+            }),
+            [b'B', b'Z', b'h', ..] => Self::Bzip(
+                CompressionMethod::Bzip
+                    .estimate_level(header)
+                    .unwrap_or_default(),
+            ),
+            _ => Self::None,
+        }
+    }
 
     #[cfg(feature = "reader")]
     pub(crate) fn make_read<'t>(self, read: impl Read + 't) -> Result<Box<dyn Read + 't>> {
         match self {
             Self::None => Ok(Box::new(read)),
             #[cfg(feature = "comp-zlib")]
-            Self::Zlib(_) => Ok(Box::new(flate2::read::DeflateDecoder::new(read))),
-            #[cfg(feature = "comp-bzip")]
-            Self::Bzip(_) => Ok(Box::new(bzip2::read::BzDecoder::new(read))),
+            Self::Gzip(_) => Ok(Box::new(flate2::read::MultiGzDecoder::new(read))),
+            #[cfg(any(feature = "comp-bzip", feature = "comp-bzip-rs"))]
+            Self::Bzip(_) => Ok(Box::new(bzip2::read::MultiBzDecoder::new(read))),
             #[allow(unreachable_patterns)] // unreachable when all features enabled
             _ => Err(Error::new(
                 ErrorKind::Other,
@@ -84,4 +388,116 @@ impl Compression {
             )),
         }
     }
+
+    /// Compresses all of `read` into `write`, returning the number of bytes read.
+    #[cfg(feature = "writer")]
+    pub(crate) fn compress(self, mut read: impl Read, mut write: impl Write) -> Result<u64> {
+        match self {
+            Self::None => std::io::copy(&mut read, &mut write),
+            #[cfg(feature = "comp-zlib")]
+            Self::Gzip(level) => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(write, flate2::Compression::new(level.get()));
+                let size = std::io::copy(&mut read, &mut encoder)?;
+                let _ = encoder.finish()?;
+                Ok(size)
+            }
+            #[cfg(any(feature = "comp-bzip", feature = "comp-bzip-rs"))]
+            Self::Bzip(level) => {
+                let mut encoder =
+                    bzip2::write::BzEncoder::new(write, bzip2::Compression::new(level.get()));
+                let size = std::io::copy(&mut read, &mut encoder)?;
+                let _ = encoder.finish()?;
+                Ok(size)
+            }
+            #[allow(unreachable_patterns)] // unreachable when all features enabled
+            _ => Err(Error::new(
+                ErrorKind::Other,
+                "unsupported compression algorithm (not compiled with comp-zlib/comp-bzip feature)",
+            )),
+        }
+    }
+}
+
+/// A decompressor over `R` created by `CompressionMethod::make_decoder`.
+///
+/// Raw deflate streams are decompressed with `Inflater`,
+/// so that the decompressor state can be saved for seeking.
+#[cfg(feature = "reader")]
+pub(crate) enum Decoder<R: Read> {
+    None(R),
+    #[cfg(feature = "comp-zlib")]
+    Zlib(Inflater<R>),
+    #[cfg(any(feature = "comp-bzip", feature = "comp-bzip-rs"))]
+    Bzip(bzip2::read::BzDecoder<R>),
+}
+
+#[cfg(feature = "reader")]
+impl<R: Read> Decoder<R> {
+    /// Returns the underlying compressed stream.
+    pub(crate) fn into_inner(self) -> R {
+        match self {
+            Self::None(read) => read,
+            #[cfg(feature = "comp-zlib")]
+            Self::Zlib(decoder) => decoder.into_inner(),
+            #[cfg(any(feature = "comp-bzip", feature = "comp-bzip-rs"))]
+            Self::Bzip(decoder) => decoder.into_inner(),
+        }
+    }
+
+    /// Saves the decompressor state,
+    /// or returns `None` if the compression method does not support it.
+    ///
+    /// Only raw deflate streams support checkpoints.
+    pub(crate) fn checkpoint(&self) -> Option<DecoderCheckpoint> {
+        match self {
+            #[cfg(feature = "comp-zlib")]
+            Self::Zlib(decoder) => Some(DecoderCheckpoint::Zlib(decoder.checkpoint())),
+            #[allow(unreachable_patterns)] // unreachable when all features disabled
+            _ => None,
+        }
+    }
+
+    /// Resumes decompression from `checkpoint`.
+    ///
+    /// `read` must start at `checkpoint.consumed()` bytes into the compressed stream.
+    pub(crate) fn resume(read: R, checkpoint: &DecoderCheckpoint) -> Self {
+        match *checkpoint {
+            #[cfg(feature = "comp-zlib")]
+            DecoderCheckpoint::Zlib(ref checkpoint) => {
+                Self::Zlib(Inflater::resume(read, checkpoint))
+            }
+        }
+    }
+}
+
+/// A decompressor state saved by `Decoder::checkpoint`.
+#[cfg(feature = "reader")]
+pub(crate) enum DecoderCheckpoint {
+    #[cfg(feature = "comp-zlib")]
+    Zlib(InflateCheckpoint),
+}
+
+#[cfg(feature = "reader")]
+impl DecoderCheckpoint {
+    /// Returns the number of compressed bytes consumed before the checkpoint.
+    pub(crate) fn consumed(&self) -> u64 {
+        match *self {
+            #[cfg(feature = "comp-zlib")]
+            Self::Zlib(ref checkpoint) => checkpoint.consumed(),
+        }
+    }
+}
+
+#[cfg(feature = "reader")]
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        match self {
+            Self::None(read) => read.read(buf),
+            #[cfg(feature = "comp-zlib")]
+            Self::Zlib(decoder) => decoder.read(buf),
+            #[cfg(any(feature = "comp-bzip", feature = "comp-bzip-rs"))]
+            Self::Bzip(decoder) => decoder.read(buf),
+        }
+    }
 }
@@ -0,0 +1,75 @@
+use std::fmt;
+
+use bitflags::bitflags;
+
+/// The manifest API version of a phar archive.
+///
+/// The version is stored as three 4-bit numbers in big endian,
+/// e.g. `[0x11, 0x10]` for version 1.1.1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ApiVersion {
+    /// The major version, which is 1 for all versions supported by PHP
+    pub major: u8,
+    /// The minor version
+    pub minor: u8,
+    /// The release version
+    pub release: u8,
+}
+
+impl ApiVersion {
+    /// The version written by this library, i.e. 1.1.0
+    pub const CURRENT: Self = Self {
+        major: 1,
+        minor: 1,
+        release: 0,
+    };
+
+    /// Decodes the API version from the two bytes stored in the manifest.
+    ///
+    /// The lowest 4 bits are unused and ignored.
+    pub fn from_bytes(bytes: [u8; 2]) -> Self {
+        let [high, low] = bytes;
+        Self {
+            major: high >> 4,
+            minor: high & 0xF,
+            release: low >> 4,
+        }
+    }
+
+    /// Encodes the API version into the two bytes stored in the manifest.
+    ///
+    /// Only the lowest 4 bits of each number are used.
+    pub const fn to_bytes(self) -> [u8; 2] {
+        [
+            ((self.major & 0xF) << 4) | (self.minor & 0xF),
+            (self.release & 0xF) << 4,
+        ]
+    }
+
+    /// Whether archives of this API version can be read by this library.
+    pub fn is_supported(self) -> bool {
+        self.major == 1
+    }
+}
+
+impl fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.release)
+    }
+}
+
+bitflags! {
+    /// The global flags of a phar archive.
+    ///
+    /// Bits not known to this library are discarded.
+    pub struct GlobalFlags: u32 {
+        /// At least one entry is compressed with zlib (`Phar::GZ`)
+        const COMPRESSED_GZ = 0x0000_1000;
+        /// At least one entry is compressed with bzip2 (`Phar::BZ2`)
+        const COMPRESSED_BZ2 = 0x0000_2000;
+        /// The bits used to indicate compressed entries
+        const COMPRESSION_MASK = 0x0000_F000;
+        /// The archive has a signature (`PHAR_HDR_SIGNATURE`)
+        const SIGNATURE = 0x0001_0000;
+    }
+}
@@ -37,6 +37,13 @@
 #[cfg_attr(feature = "docsrs", doc(cfg(feature = "reader")))]
 pub use read::Reader;
 
+#[cfg(all(feature = "reader", feature = "async"))]
+#[cfg_attr(
+    feature = "docsrs",
+    doc(cfg(all(feature = "reader", feature = "async")))
+)]
+pub use read::AsyncReader;
+
 #[cfg(feature = "reader")]
 #[cfg_attr(feature = "docsrs", doc(cfg(feature = "reader")))]
 pub mod read;
@@ -45,14 +52,42 @@ pub mod read;
 #[cfg_attr(feature = "docsrs", doc(cfg(feature = "writer")))]
 pub use write::create;
 
+#[cfg(all(feature = "writer", feature = "async"))]
+#[cfg_attr(
+    feature = "docsrs",
+    doc(cfg(all(feature = "writer", feature = "async")))
+)]
+pub use write::create_async;
+
 #[cfg(feature = "writer")]
 #[cfg_attr(feature = "docsrs", doc(cfg(feature = "writer")))]
 pub mod write;
 
 mod signature;
-pub use signature::Signature;
+pub use signature::{Signature, SignatureAlgorithm};
 
 mod compression;
-pub use compression::Compression;
+pub use compression::{ArchiveCompression, Compression, CompressionMethod, Level};
+
+mod header;
+pub use header::{ApiVersion, GlobalFlags};
+
+pub mod path;
+
+pub mod progress;
+
+#[cfg(all(feature = "reader", feature = "writer"))]
+#[cfg_attr(
+    feature = "docsrs",
+    doc(cfg(all(feature = "reader", feature = "writer")))
+)]
+pub mod merge;
+
+#[cfg(all(feature = "reader", feature = "writer"))]
+#[cfg_attr(
+    feature = "docsrs",
+    doc(cfg(all(feature = "reader", feature = "writer")))
+)]
+pub mod repack;
 
 mod util;
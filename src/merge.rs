@@ -0,0 +1,243 @@
+//! Merging multiple phar archives into one
+//!
+//! Entries are copied with `write::NeedEntries::raw_entry`,
+//! so compressed contents are not decompressed and recompressed.
+
+use std::collections::hash_map::{Entry, HashMap};
+use std::collections::HashSet;
+use std::io::{Error, ErrorKind, Read, Result, Seek, Write};
+
+use typed_builder::TypedBuilder;
+
+use crate::read::{index, FileIndex};
+use crate::util::STUB_TERMINATOR;
+use crate::{path, Reader, Signature};
+
+/// An archive to be merged.
+#[derive(Debug)]
+pub struct Input<R: Read + Seek, I: FileIndex> {
+    reader: Reader<R, I>,
+    prefix: Vec<u8>,
+}
+
+impl<R: Read + Seek, I: FileIndex> Input<R, I> {
+    /// Creates an input whose entries keep their names.
+    pub fn new(reader: Reader<R, I>) -> Self {
+        Self {
+            reader,
+            prefix: Vec::new(),
+        }
+    }
+
+    /// Places the entries of this input under the directory `prefix`,
+    /// e.g. `b"libs/foo"` merges `src/a.php` as `libs/foo/src/a.php`.
+    pub fn with_prefix(mut self, prefix: impl Into<Vec<u8>>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+}
+
+/// How to handle files with the same name in multiple inputs.
+///
+/// Directory entries with the same name are never conflicts;
+/// the first one is kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Conflicts {
+    /// Fail with an `InvalidInput` error.
+    #[default]
+    Error,
+    /// Keep the file from the earliest input.
+    KeepFirst,
+    /// Keep the file from the latest input.
+    KeepLast,
+}
+
+/// Options for merging phar archives
+#[derive(Debug, TypedBuilder)]
+pub struct Options {
+    /// How to handle files with the same name in multiple inputs
+    ///
+    /// Default `Conflicts::Error`.
+    #[builder(default)]
+    conflicts: Conflicts,
+
+    /// The index of the input whose stub is used
+    ///
+    /// Default 0.
+    #[builder(default)]
+    stub_from: usize,
+
+    /// The index of the input whose alias is used
+    ///
+    /// Default 0.
+    #[builder(default)]
+    alias_from: usize,
+
+    /// The index of the input whose global metadata is used
+    ///
+    /// Default 0.
+    #[builder(default)]
+    metadata_from: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+/// An entry to be copied into the merged archive.
+struct Planned {
+    input: usize,
+    position: u32,
+    name: Vec<u8>,
+    metadata: Vec<u8>,
+    raw: crate::write::RawEntry,
+    is_dir: bool,
+}
+
+/// Merges `inputs` into a new phar archive written to `output`, signed with `signature`.
+///
+/// Entries are written in the order of `inputs`,
+/// and within each input in the iteration order of its index,
+/// so use an index that preserves manifest order, such as `index::NameIndexMap`,
+/// to keep the order of the original archives.
+/// Entry names are normalized with `path::normalize` after applying the input prefix,
+/// and names that cannot be normalized fail with an `InvalidData` error.
+///
+/// Returns an `InvalidInput` error if a `*_from` option is out of range.
+pub fn merge<R, I, W>(
+    inputs: &mut [Input<R, I>],
+    output: W,
+    signature: Signature,
+    options: Options,
+) -> Result<()>
+where
+    R: Read + Seek,
+    I: index::Iterable,
+    W: Read + Write + Seek,
+{
+    let mut planned: Vec<Option<Planned>> = Vec::new();
+    let mut names = HashMap::new();
+    for (input_index, input) in inputs.iter_mut().enumerate() {
+        let prefix = &input.prefix;
+        input.reader.for_each_entry(|mut entry| {
+            let mut name = prefix.clone();
+            if !name.is_empty() && name.last() != Some(&b'/') {
+                name.push(b'/');
+            }
+            name.extend_from_slice(entry.name());
+            let name = path::normalize(&name)
+                .map_err(|err| {
+                    Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "cannot merge entry {:?}: {}",
+                            String::from_utf8_lossy(&name),
+                            err
+                        ),
+                    )
+                })?
+                .into_owned();
+
+            let index = planned.len();
+            match names.entry(name.clone()) {
+                Entry::Vacant(vacant) => {
+                    let _ = vacant.insert(index);
+                }
+                Entry::Occupied(_) if entry.is_dir() => return Ok(()),
+                Entry::Occupied(mut occupied) => match options.conflicts {
+                    Conflicts::Error => {
+                        let previous = planned
+                            .get(*occupied.get())
+                            .and_then(Option::as_ref)
+                            .expect("entries are only removed with KeepLast")
+                            .input;
+                        return Err(Error::new(
+                            ErrorKind::InvalidInput,
+                            format!(
+                                "duplicate entry name {:?} in inputs {} and {}",
+                                String::from_utf8_lossy(&name),
+                                previous,
+                                input_index
+                            ),
+                        ));
+                    }
+                    Conflicts::KeepFirst => return Ok(()),
+                    Conflicts::KeepLast => {
+                        let previous = occupied.insert(index);
+                        if let Some(previous) = planned.get_mut(previous) {
+                            *previous = None;
+                        }
+                    }
+                },
+            }
+
+            planned.push(Some(Planned {
+                input: input_index,
+                position: entry.info().position(),
+                name,
+                metadata: entry.metadata()?,
+                raw: entry.info().into(),
+                is_dir: entry.is_dir(),
+            }));
+            Ok(())
+        })?;
+    }
+    let planned: Vec<Planned> = planned.into_iter().flatten().collect();
+
+    let stub = header_input(inputs, options.stub_from, "stub")?
+        .stub_bytes()?
+        .as_ref()
+        .to_vec();
+    let stub = stub.strip_suffix(STUB_TERMINATOR).unwrap_or(&stub);
+    let alias = header_input(inputs, options.alias_from, "alias")?
+        .alias_bytes()?
+        .as_ref()
+        .to_vec();
+    let metadata = header_input(inputs, options.metadata_from, "metadata")?
+        .metadata_bytes()?
+        .as_ref()
+        .to_vec();
+
+    let mut writer = crate::write::create(output, signature)
+        .stub(stub)?
+        .alias(&alias[..])?
+        .metadata(&metadata[..])?;
+    for entry in &planned {
+        writer.raw_entry(&entry.name[..], &entry.metadata[..], entry.raw)?;
+    }
+
+    let mut contents = writer.contents()?;
+    for (input_index, input) in inputs.iter_mut().enumerate() {
+        let positions: HashSet<u32> = planned
+            .iter()
+            .filter(|entry| entry.input == input_index && !entry.is_dir)
+            .map(|entry| entry.position)
+            .collect();
+        // the index is iterated in the same order as when planning
+        input.reader.for_each_entry(|mut entry| {
+            if positions.contains(&entry.info().position()) {
+                contents.feed_raw(entry.open_raw()?)?;
+            }
+            Ok(())
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Returns the reader of the input to take `what` from.
+fn header_input<'t, R: Read + Seek, I: FileIndex>(
+    inputs: &'t mut [Input<R, I>],
+    index: usize,
+    what: &str,
+) -> Result<&'t mut Reader<R, I>> {
+    match inputs.get_mut(index) {
+        Some(input) => Ok(&mut input.reader),
+        None => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("no input #{} to take the {} from", index, what),
+        )),
+    }
+}
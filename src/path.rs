@@ -0,0 +1,99 @@
+//! Normalization of entry names
+//!
+//! PHP normalizes paths before looking up entries in a phar archive,
+//! so entries whose stored names are not normalized cannot be accessed from PHP.
+
+use std::borrow::Cow;
+use std::error;
+use std::fmt;
+use std::io::{self, ErrorKind};
+
+/// The reason an entry name cannot be normalized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum NameError {
+    /// The name is empty, or only consists of slashes and `.` components.
+    Empty,
+    /// The name contains a NUL byte.
+    Nul,
+    /// The name contains a `..` component.
+    ParentDirectory,
+}
+
+impl fmt::Display for NameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "entry name is empty"),
+            Self::Nul => write!(f, "entry name contains a NUL byte"),
+            Self::ParentDirectory => write!(f, "entry name contains a `..` component"),
+        }
+    }
+}
+
+impl error::Error for NameError {}
+
+impl From<NameError> for io::Error {
+    fn from(err: NameError) -> Self {
+        io::Error::new(ErrorKind::InvalidInput, err)
+    }
+}
+
+/// Normalizes an entry name the way PHP does.
+///
+/// Leading slashes are stripped,
+/// and empty and `.` components are removed,
+/// e.g. `/src//./a.php` is normalized to `src/a.php`.
+/// A trailing slash, which indicates a directory entry, is kept.
+///
+/// The name is borrowed if it is already normalized.
+pub fn normalize(name: &[u8]) -> Result<Cow<'_, [u8]>, NameError> {
+    if name.contains(&0) {
+        return Err(NameError::Nul);
+    }
+
+    let mut components = Vec::new();
+    for component in name.split(|&byte| byte == b'/') {
+        match component {
+            b"" | b"." => {}
+            b".." => return Err(NameError::ParentDirectory),
+            component => components.push(component),
+        }
+    }
+    if components.is_empty() {
+        return Err(NameError::Empty);
+    }
+
+    let mut normalized = components.join(&b'/');
+    if name.last() == Some(&b'/') {
+        normalized.push(b'/');
+    }
+    if normalized == name {
+        Ok(Cow::Borrowed(name))
+    } else {
+        Ok(Cow::Owned(normalized))
+    }
+}
+
+/// Whether an entry name is already normalized,
+/// i.e. PHP can access the entry by this name.
+pub fn is_normalized(name: &[u8]) -> bool {
+    matches!(normalize(name), Ok(Cow::Borrowed(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_normalized, normalize, NameError};
+
+    #[test]
+    fn normalize_names() {
+        let normalized = |name: &str| normalize(name.as_bytes()).map(|name| name.into_owned());
+        assert_eq!(normalized("src/a.php"), Ok(b"src/a.php".to_vec()));
+        assert_eq!(normalized("/src//./a.php"), Ok(b"src/a.php".to_vec()));
+        assert_eq!(normalized("./src/lib/"), Ok(b"src/lib/".to_vec()));
+        assert_eq!(normalized("src/../a.php"), Err(NameError::ParentDirectory));
+        assert_eq!(normalized("a\0.php"), Err(NameError::Nul));
+        assert_eq!(normalized("/./"), Err(NameError::Empty));
+        assert!(is_normalized(b"src/lib/"));
+        assert!(!is_normalized(b"/src"));
+    }
+}
@@ -0,0 +1,132 @@
+//! Progress reporting for writing and extracting phar archives
+//!
+//! Implement `Observer` and pass it to `write::NeedEntries::with_observer`
+//! or `read::Reader::extract_to_with` to receive per-entry events and totals,
+//! e.g. to render a progress bar or log structured events.
+
+use std::time::Duration;
+
+/// Receives progress events while an archive is written or extracted.
+///
+/// All methods do nothing by default.
+pub trait Observer {
+    /// Called when an entry is declared in the manifest,
+    /// or before extraction starts for each entry in the archive.
+    ///
+    /// This is also called for directory entries,
+    /// which have no contents and are not reported to `entry_done`.
+    fn entry_declared(&mut self, name: &[u8]) {
+        let _ = name;
+    }
+
+    /// Called after the contents of a file entry have been written.
+    fn entry_done(&mut self, event: &EntryEvent<'_>) {
+        let _ = event;
+    }
+
+    /// Called after the contents of the last file entry have been written,
+    /// or when the contents section starts if there are no file entries.
+    fn finished(&mut self, totals: &Totals) {
+        let _ = totals;
+    }
+}
+
+/// Ignores all events.
+impl Observer for () {}
+
+impl<O: Observer + ?Sized> Observer for &mut O {
+    fn entry_declared(&mut self, name: &[u8]) {
+        (**self).entry_declared(name);
+    }
+
+    fn entry_done(&mut self, event: &EntryEvent<'_>) {
+        (**self).entry_done(event);
+    }
+
+    fn finished(&mut self, totals: &Totals) {
+        (**self).finished(totals);
+    }
+}
+
+impl<O: Observer + ?Sized> Observer for Box<O> {
+    fn entry_declared(&mut self, name: &[u8]) {
+        (**self).entry_declared(name);
+    }
+
+    fn entry_done(&mut self, event: &EntryEvent<'_>) {
+        (**self).entry_done(event);
+    }
+
+    fn finished(&mut self, totals: &Totals) {
+        (**self).finished(totals);
+    }
+}
+
+/// The contents of a file entry have been processed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct EntryEvent<'t> {
+    /// The normalized name of the entry
+    pub name: &'t [u8],
+    /// The zero-based index of the entry among file entries
+    pub index: usize,
+    /// The number of file entries, excluding directories
+    pub total: usize,
+    /// The number of bytes read from the source
+    ///
+    /// When writing, this is the size of the contents before compression,
+    /// or the stored size for entries copied with `feed_raw`.
+    /// When extracting, this is the size of the contents as stored in the archive.
+    pub bytes_read: u64,
+    /// The number of bytes written to the destination
+    ///
+    /// When writing, this is the size of the contents as stored in the archive.
+    /// When extracting, this is the size of the decompressed file.
+    pub bytes_written: u64,
+    /// The time spent on this entry
+    pub elapsed: Duration,
+}
+
+impl EntryEvent<'_> {
+    /// Returns `bytes_written / bytes_read`, or `None` if no bytes were read.
+    pub fn ratio(&self) -> Option<f64> {
+        ratio(self.bytes_written, self.bytes_read)
+    }
+}
+
+/// The totals of all file entries processed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub struct Totals {
+    /// The number of file entries processed
+    pub entries: usize,
+    /// The sum of `EntryEvent::bytes_read`
+    pub bytes_read: u64,
+    /// The sum of `EntryEvent::bytes_written`
+    pub bytes_written: u64,
+    /// The time since writing contents or extraction started
+    pub elapsed: Duration,
+}
+
+impl Totals {
+    /// Returns `bytes_written / bytes_read`, or `None` if no bytes were read.
+    pub fn ratio(&self) -> Option<f64> {
+        ratio(self.bytes_written, self.bytes_read)
+    }
+
+    /// Adds an entry event to the totals.
+    pub(crate) fn add(&mut self, event: &EntryEvent<'_>) {
+        self.entries += 1;
+        self.bytes_read += event.bytes_read;
+        self.bytes_written += event.bytes_written;
+    }
+}
+
+#[allow(clippy::as_conversions)]
+fn ratio(written: u64, read: u64) -> Option<f64> {
+    if read == 0 {
+        None
+    } else {
+        Some(written as f64 / read as f64)
+    }
+}
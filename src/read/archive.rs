@@ -0,0 +1,140 @@
+use std::convert::TryFrom;
+use std::io::{Cursor, Read, Result, Seek, SeekFrom};
+
+use super::limits::LimitExceeded;
+use super::Options;
+use crate::ArchiveCompression;
+
+/// A phar file that is possibly compressed as a whole,
+/// such as `.phar.gz` and `.phar.bz2` files.
+///
+/// Compressed files are decompressed when this is created,
+/// since compressed streams cannot be seeked.
+/// The decompressed file is kept in memory,
+/// or in a temporary file if it is larger than `Options::spill_threshold`
+/// (requires the `tempfile` feature).
+/// Uncompressed files are read directly from the input.
+pub struct Decompressed<R: Read + Seek> {
+    inner: Inner<R>,
+    compression: ArchiveCompression,
+}
+
+enum Inner<R> {
+    Plain(R),
+    Memory(Cursor<Vec<u8>>),
+    #[cfg(feature = "tempfile")]
+    File(std::fs::File),
+}
+
+impl<R: Read + Seek> Decompressed<R> {
+    /// Detects whether `read` is compressed and decompresses it if necessary.
+    ///
+    /// The decompressed size is limited by `Options::max_archive_size`.
+    pub fn new(mut read: R, options: &Options) -> Result<Self> {
+        let mut header = Vec::with_capacity(10);
+        let _ = (&mut read).take(10).read_to_end(&mut header)?;
+        let _ = read.seek(SeekFrom::Start(0))?;
+
+        let compression = ArchiveCompression::detect(&header);
+        if compression == ArchiveCompression::None {
+            return Ok(Self {
+                inner: Inner::Plain(read),
+                compression,
+            });
+        }
+
+        let mut decoder = compression.make_read(read)?.take(
+            options
+                .max_archive_size
+                .map_or(u64::MAX, |limit| limit.saturating_add(1)),
+        );
+        #[cfg(feature = "tempfile")]
+        let (inner, size) = match options.spill_threshold {
+            Some(threshold) => spill_over(&mut decoder, threshold)?,
+            None => in_memory(&mut decoder)?,
+        };
+        #[cfg(not(feature = "tempfile"))]
+        let (inner, size) = {
+            let _ = options.spill_threshold; // spilling requires the tempfile feature
+            in_memory(&mut decoder)?
+        };
+
+        if let Some(limit) = options.max_archive_size {
+            if size > limit {
+                return Err(LimitExceeded::ArchiveSize { limit }.into());
+            }
+        }
+
+        Ok(Self { inner, compression })
+    }
+
+    /// Returns the detected compression of the file.
+    pub fn compression(&self) -> ArchiveCompression {
+        self.compression
+    }
+}
+
+/// Decompresses the whole file into memory.
+///
+/// Returns the decompressed size.
+fn in_memory<D: Read, R>(decoder: &mut D) -> Result<(Inner<R>, u64)> {
+    let mut memory = Vec::new();
+    let _ = decoder.read_to_end(&mut memory)?;
+    let size = u64::try_from(memory.len()).expect("usize <= u64");
+    Ok((Inner::Memory(Cursor::new(memory)), size))
+}
+
+/// Decompresses the file into memory,
+/// or into a temporary file if it is larger than `threshold`.
+///
+/// Returns the decompressed size.
+#[cfg(feature = "tempfile")]
+fn spill_over<D: Read, R>(decoder: &mut D, threshold: u64) -> Result<(Inner<R>, u64)> {
+    use std::io::Write;
+
+    let mut memory = Vec::new();
+    let _ = (&mut *decoder)
+        .take(threshold.saturating_add(1))
+        .read_to_end(&mut memory)?;
+    let mut size = u64::try_from(memory.len()).expect("usize <= u64");
+    if size <= threshold {
+        return Ok((Inner::Memory(Cursor::new(memory)), size));
+    }
+
+    let mut file = tempfile::tempfile()?;
+    file.write_all(&memory)?;
+    drop(memory);
+    size += std::io::copy(decoder, &mut file)?;
+    let _ = file.seek(SeekFrom::Start(0))?;
+    Ok((Inner::File(file), size))
+}
+
+impl<R: Read + Seek> Read for Decompressed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        match &mut self.inner {
+            Inner::Plain(read) => read.read(buf),
+            Inner::Memory(cursor) => cursor.read(buf),
+            #[cfg(feature = "tempfile")]
+            Inner::File(file) => file.read(buf),
+        }
+    }
+}
+
+impl<R: Read + Seek> Seek for Decompressed<R> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        match &mut self.inner {
+            Inner::Plain(read) => read.seek(pos),
+            Inner::Memory(cursor) => cursor.seek(pos),
+            #[cfg(feature = "tempfile")]
+            Inner::File(file) => file.seek(pos),
+        }
+    }
+}
+
+impl<R: Read + Seek> std::fmt::Debug for Decompressed<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Decompressed")
+            .field("compression", &self.compression)
+            .finish()
+    }
+}
@@ -0,0 +1,333 @@
+use std::borrow::Cow;
+use std::future::Future;
+use std::io::{Error, ErrorKind, Result, SeekFrom, Write};
+use std::pin::Pin;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, BufReader};
+
+use super::limits::EntryLimits;
+use super::reader::Manifest;
+use super::section::SectionRef;
+use super::trailer::{self, parse_signature_trailer, SignatureInfo};
+use super::util::{BstrScanner, ShiftedCursor};
+use super::validate::{self, Diagnostic};
+use super::{index, FileIndex, Options, Section};
+use crate::signature::Signature;
+use crate::util::STUB_TERMINATOR;
+use crate::{ApiVersion, GlobalFlags};
+
+/// The asynchronous counterpart of `Reader`.
+///
+/// The manifest is parsed with the same logic as `Reader`,
+/// after it is read into memory from the async stream.
+/// Unlike `Reader`, the signature is verified in a second pass over the file.
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "docsrs",
+    doc(cfg(all(feature = "reader", feature = "async")))
+)]
+pub struct AsyncReader<R, FileIndexT: FileIndex = index::NameHashMap> {
+    stream: R,
+    stub: Section,
+    num_files: u32,
+    api: ApiVersion,
+    flags: u32,
+    alias: Section,
+    metadata: Section,
+    file_index: FileIndexT,
+    limits: EntryLimits,
+    signature: Option<SignatureInfo>,
+}
+
+impl<R, FileIndexT> AsyncReader<R, FileIndexT>
+where
+    R: AsyncRead + AsyncSeek + Unpin + Send,
+    FileIndexT: FileIndex,
+{
+    /// Parses the phar file.
+    ///
+    /// See `Reader::read` for details.
+    pub async fn read(mut stream: R, options: Options) -> Result<Self> {
+        let mut sig_check = None;
+        if options.verify_signature {
+            let _ = stream.seek(SeekFrom::End(-8)).await?;
+            let mut trailer = [0u8; 8];
+            let _ = stream.read_exact(&mut trailer[..]).await?;
+            let sig = parse_signature_trailer(trailer)?;
+
+            let mut digest = vec![0u8; sig.size().into()];
+            let offset = stream
+                .seek(SeekFrom::End(-8i64 - i64::from(sig.size())))
+                .await?;
+            let _ = stream.read_exact(&mut digest[..]).await?;
+            let info = SignatureInfo {
+                algorithm: sig.algorithm(),
+                digest,
+                offset,
+                verified: false,
+            };
+            sig_check = Some((sig, info));
+        }
+
+        let _ = stream.seek(SeekFrom::Start(0)).await?;
+
+        let mut stub = Section::create(options.cache_stub, 0);
+        let scan_limit = sig_check.as_ref().map_or(u64::MAX, |(_, info)| info.offset);
+        let mut scanner = BstrScanner::with_limit(STUB_TERMINATOR, scan_limit);
+        loop {
+            let read = stream.read(scanner.free()).await?;
+            if read == 0 {
+                return Err(ErrorKind::UnexpectedEof.into());
+            }
+            if let Some(unread) = scanner.filled(read, &mut stub)? {
+                if unread > 0 {
+                    let _ = stream.seek(SeekFrom::Current(-unread)).await?;
+                }
+                break;
+            }
+        }
+
+        let manifest_size = stream.read_u32_le().await?;
+        options.check_manifest_size(manifest_size)?;
+        let manifest_start = stream.stream_position().await?;
+        let mut manifest = Vec::new();
+        let _ = (&mut stream)
+            .take(manifest_size.into())
+            .read_to_end(&mut manifest)
+            .await?;
+
+        let Manifest {
+            num_files,
+            api,
+            flags,
+            alias,
+            metadata,
+            file_index,
+        } = Manifest::parse(&mut ShiftedCursor::new(manifest, manifest_start), &options)?;
+
+        let signature = if let Some((sig, mut info)) = sig_check {
+            let _ = stream.seek(SeekFrom::Start(0)).await?;
+            let actual_sig = digest(&mut stream, sig, info.offset).await?;
+            if actual_sig.as_ref() != &info.digest[..] {
+                return Err(Error::new(ErrorKind::Other, "signature mismatch"));
+            }
+            info.verified = true;
+            Some(info)
+        } else {
+            read_unverified(&mut stream).await?
+        };
+
+        let mut reader = Self {
+            stream,
+            stub,
+            num_files,
+            api,
+            flags,
+            alias,
+            metadata,
+            file_index,
+            limits: options.entry_limits(),
+            signature,
+        };
+        if options.strict {
+            validate::strict(reader.validate().await?)?;
+        }
+        Ok(reader)
+    }
+
+    /// Checks the phar file for structural inconsistencies.
+    ///
+    /// See `Reader::validate` for details.
+    pub async fn validate(&mut self) -> Result<Vec<Diagnostic>> {
+        let mut diagnostics = Vec::new();
+
+        let manifest_offset = self.stub.len();
+        let _ = self.stream.seek(SeekFrom::Start(manifest_offset)).await?;
+        let manifest_size = self.stream.read_u32_le().await?;
+        let mut manifest = manifest_size.to_le_bytes().to_vec();
+        let _ = (&mut self.stream)
+            .take(manifest_size.into())
+            .read_to_end(&mut manifest)
+            .await?;
+        let summary = validate::validate_manifest(
+            &mut ShiftedCursor::new(manifest, manifest_offset),
+            &mut diagnostics,
+        )?;
+
+        let file_len = self.stream.seek(SeekFrom::End(0)).await?;
+        let trailer = if file_len >= 8 {
+            let _ = self.stream.seek(SeekFrom::End(-8)).await?;
+            let mut trailer = [0u8; 8];
+            let _ = self.stream.read_exact(&mut trailer[..]).await?;
+            Some(trailer)
+        } else {
+            None
+        };
+        validate::validate_trailer(&summary, file_len, trailer, &mut diagnostics);
+
+        Ok(diagnostics)
+    }
+
+    /// Returns the number of file entries declared in the manifest.
+    pub fn entry_count(&self) -> u32 {
+        self.num_files
+    }
+
+    /// Returns the manifest API version.
+    pub fn api_version(&self) -> ApiVersion {
+        self.api
+    }
+
+    /// Returns the global flags.
+    pub fn global_flags(&self) -> GlobalFlags {
+        GlobalFlags::from_bits_truncate(self.flags)
+    }
+
+    /// Returns the signature of the phar file.
+    ///
+    /// See `Reader::signature` for details.
+    pub fn signature(&self) -> Option<&SignatureInfo> {
+        self.signature.as_ref()
+    }
+
+    /// Returns the file index.
+    pub fn index(&self) -> &FileIndexT {
+        &self.file_index
+    }
+
+    /// Returns the stub as a slice.
+    ///
+    /// If the stub was previously not stored in memory, it is read into a new Vec.
+    pub async fn stub_bytes(&mut self) -> Result<Cow<'_, [u8]>> {
+        section_bytes(&mut self.stream, self.stub.as_ref()).await
+    }
+
+    /// Returns the metadata as a slice.
+    ///
+    /// If the metadata was previously not stored in memory, it is read into a new Vec.
+    pub async fn metadata_bytes(&mut self) -> Result<Cow<'_, [u8]>> {
+        section_bytes(&mut self.stream, self.metadata.as_ref()).await
+    }
+}
+
+impl<R, FileIndexT> AsyncReader<R, FileIndexT>
+where
+    R: AsyncRead + AsyncSeek + Unpin + Send,
+    FileIndexT: index::Iterable,
+{
+    /// Iterates over the files in this archive.
+    ///
+    /// Directory entries are skipped.
+    /// File contents are decompressed asynchronously.
+    ///
+    /// `f` is called with `state`, the file name and the file contents,
+    /// and returns a boxed future that may borrow all of them,
+    /// e.g. `|state, name, read| Box::pin(async move { ... })`.
+    /// Pass the variables updated by the future in `state`,
+    /// since the future cannot borrow from the closure itself.
+    pub async fn for_each_file<S, F>(&mut self, state: &mut S, mut f: F) -> Result<()>
+    where
+        S: Send,
+        F: for<'a> FnMut(
+            &'a mut S,
+            &'a [u8],
+            &'a mut (dyn AsyncRead + Unpin + Send),
+        ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>,
+    {
+        let mut entries = Vec::new();
+        self.file_index.for_each_entry_range(|name, info, range| {
+            entries.push((name, info, range));
+            Ok(())
+        })?;
+
+        for (name, info, range) in entries {
+            let name = section_bytes(&mut self.stream, name).await?;
+            if index::is_dir_name(&name) {
+                continue;
+            }
+
+            let _ = self.stream.seek(SeekFrom::Start(range.start)).await?;
+            let contents = BufReader::new((&mut self.stream).take(range.end - range.start));
+            let compression = info.compression();
+            let decompressed = compression.make_async_read(contents)?;
+            let mut decompressed = self.limits.wrap(
+                decompressed,
+                compression.bit() != 0,
+                range.end - range.start,
+            );
+            f(state, &name, &mut decompressed).await?;
+        }
+
+        Ok(())
+    }
+}
+
+async fn section_bytes<'t>(
+    stream: &mut (impl AsyncRead + AsyncSeek + Unpin),
+    section: SectionRef<'t>,
+) -> Result<Cow<'t, [u8]>> {
+    Ok(match section {
+        SectionRef::Cached(slice) => Cow::Borrowed(slice),
+        SectionRef::Offset(start, end) => {
+            let mut vec = Vec::new();
+            let _ = stream.seek(SeekFrom::Start(start)).await?;
+            let _ = stream.take(end - start).read_to_end(&mut vec).await?;
+            Cow::Owned(vec)
+        }
+    })
+}
+
+/// The asynchronous counterpart of `trailer::read_unverified`.
+async fn read_unverified(
+    stream: &mut (impl AsyncRead + AsyncSeek + Unpin),
+) -> Result<Option<SignatureInfo>> {
+    let file_len = stream.seek(SeekFrom::End(0)).await?;
+    if file_len < 8 {
+        return Ok(None);
+    }
+
+    let _ = stream.seek(SeekFrom::End(-8)).await?;
+    let mut trailer = [0u8; 8];
+    let _ = stream.read_exact(&mut trailer[..]).await?;
+    let algorithm = match trailer::parse_trailer(trailer) {
+        Ok(algorithm) => algorithm,
+        Err(_) => return Ok(None),
+    };
+    let offset = match trailer::signature_offset(file_len, algorithm) {
+        Some(offset) => offset,
+        None => return Ok(None),
+    };
+
+    let mut digest = vec![0u8; algorithm.size().into()];
+    let _ = stream.seek(SeekFrom::Start(offset)).await?;
+    let _ = stream.read_exact(&mut digest[..]).await?;
+
+    Ok(Some(SignatureInfo {
+        algorithm,
+        digest,
+        offset,
+        verified: false,
+    }))
+}
+
+/// Computes the signature of the first `len` bytes from the current position of `stream`.
+async fn digest(
+    stream: &mut (impl AsyncRead + Unpin),
+    mut sig: Signature,
+    len: u64,
+) -> Result<impl AsRef<[u8]>> {
+    let mut stream = stream.take(len);
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let read = stream.read(&mut buf[..]).await?;
+        if read == 0 {
+            break;
+        }
+        sig.write()
+            .write_all(buf.get(..read).expect("read <= buf.len()"))?;
+    }
+    if stream.limit() > 0 {
+        return Err(ErrorKind::UnexpectedEof.into());
+    }
+    Ok(sig.finalize())
+}
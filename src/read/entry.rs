@@ -1,8 +1,10 @@
+use std::convert::TryFrom;
 use std::io::{Read, Result, Seek};
+use std::ops::Range;
 
 use byteorder::{LittleEndian, ReadBytesExt};
 
-use super::Section;
+use super::{EntryInfo, Options, Section};
 use crate::util::tell;
 
 pub struct Entry {
@@ -13,15 +15,41 @@ pub struct Entry {
     pub original_crc32: u32,
     pub flags: u32,
     pub metadata: Section,
+    /// The absolute offset of the metadata in the phar file
+    pub metadata_offset: u64,
+    /// The index of this entry in the manifest
+    pub position: u32,
+    /// The offset of the contents relative to the end of the manifest
+    pub contents_start: u64,
 }
 
 impl Entry {
+    /// Returns the range of the contents relative to the end of the manifest.
+    pub fn contents_range(&self) -> Range<u64> {
+        self.contents_start..(self.contents_start + u64::from(self.compressed_file_size))
+    }
+
+    pub fn info(&self) -> EntryInfo {
+        EntryInfo {
+            flags: self.flags,
+            original_size: self.original_file_size,
+            time: self.time,
+            crc32: self.original_crc32,
+            position: self.position,
+            metadata_offset: self.metadata_offset,
+            metadata_len: u32::try_from(self.metadata.len())
+                .expect("metadata length is read as u32"),
+        }
+    }
+
     pub fn parse(
         read: &mut (impl Read + Seek),
         cache_name: bool,
         cache_metadata: bool,
+        options: &Options,
     ) -> Result<Self> {
         let name_len = read.read_u32::<LittleEndian>()?;
+        options.check_name_len(name_len)?;
         let mut name = Section::create(cache_name, tell(&mut *read)?);
         name.from_read(read, name_len)?;
 
@@ -32,7 +60,9 @@ impl Entry {
         let flags = read.read_u32::<LittleEndian>()?;
 
         let metadata_len = read.read_u32::<LittleEndian>()?;
-        let mut metadata = Section::create(cache_metadata, tell(&mut *read)?);
+        options.check_metadata_len(metadata_len)?;
+        let metadata_offset = tell(&mut *read)?;
+        let mut metadata = Section::create(cache_metadata, metadata_offset);
         metadata.from_read(read, metadata_len)?;
 
         Ok(Entry {
@@ -43,6 +73,9 @@ impl Entry {
             original_crc32,
             flags,
             metadata,
+            metadata_offset,
+            position: 0,
+            contents_start: 0,
         })
     }
 }
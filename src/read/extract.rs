@@ -0,0 +1,249 @@
+use std::fs;
+use std::io::{self, Error, ErrorKind, Read, Result, Seek, Write};
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use std::time::Instant;
+
+use super::handle::EntryHandle;
+use super::index::{self, Iterable};
+use super::limits::EntryLimits;
+use super::EntryInfo;
+use crate::path;
+use crate::progress::{EntryEvent, Observer, Totals};
+use crate::util::{Crc32Read, PERMISSION_MASK};
+
+/// An entry to be verified or extracted,
+/// collected in manifest order so that errors are reported deterministically.
+pub(super) struct Job {
+    name: Vec<u8>,
+    info: EntryInfo,
+    range: Range<u64>,
+}
+
+impl Job {
+    /// Returns a handle to read this entry from `stream`.
+    pub(super) fn handle<'t, R: Read + Seek>(
+        &'t self,
+        stream: &'t mut R,
+        limits: EntryLimits,
+    ) -> EntryHandle<'t, R> {
+        EntryHandle::new(&self.name, self.info, self.range.clone(), stream, limits)
+    }
+}
+
+/// Collects all entries in `index`, including directories, sorted by manifest position.
+pub(super) fn jobs<I: Iterable, R: Read + Seek>(index: &I, stream: &mut R) -> Result<Vec<Job>> {
+    let mut jobs = Vec::new();
+    index.for_each_entry_range(|name, info, range| {
+        jobs.push(Job {
+            name: name.as_memory(&mut *stream)?.into_owned(),
+            info,
+            range,
+        });
+        Ok(())
+    })?;
+    jobs.sort_by_key(|job| job.info.position());
+    Ok(jobs)
+}
+
+/// Copies the decompressed contents of `entry` into `write`,
+/// failing if their CRC32 checksum differs from the manifest.
+///
+/// Returns the number of bytes copied.
+fn copy_verified<R: Read + Seek>(
+    entry: &mut EntryHandle<'_, R>,
+    mut write: impl Write,
+) -> Result<u64> {
+    let name = entry.name();
+    let expected = entry.info().crc32();
+    io::copy(
+        &mut Crc32Read::new(entry.open()?, name, expected),
+        &mut write,
+    )
+}
+
+/// Verifies the CRC32 checksum of a file entry.
+///
+/// Directory entries have no contents and always pass.
+pub(super) fn verify<R: Read + Seek>(entry: &mut EntryHandle<'_, R>) -> Result<()> {
+    if entry.is_dir() {
+        return Ok(());
+    }
+    copy_verified(entry, io::sink()).map(|_| ())
+}
+
+/// Extracts an entry into `dir`, creating parent directories as needed.
+#[cfg(feature = "rayon")]
+pub(super) fn extract<R: Read + Seek>(entry: &mut EntryHandle<'_, R>, dir: &Path) -> Result<()> {
+    extract_file(entry, dir).map(|_| ())
+}
+
+/// Extracts an entry like `extract` and returns the size of the extracted file.
+fn extract_file<R: Read + Seek>(entry: &mut EntryHandle<'_, R>, dir: &Path) -> Result<u64> {
+    let relative = entry_path(entry.name())?;
+    check_parents(dir, &relative)?;
+    let path = dir.join(relative);
+    if entry.is_dir() {
+        fs::create_dir_all(&path)?;
+        return Ok(0);
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // an existing file is replaced instead of opened,
+    // since opening a symbolic link would write to its target outside `dir`
+    if matches!(fs::symlink_metadata(&path), Ok(stat) if !stat.is_dir()) {
+        fs::remove_file(&path)?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    let size = copy_verified(entry, &mut file)?;
+    set_mode(&file, entry.info().flags() & PERMISSION_MASK)?;
+    Ok(size)
+}
+
+/// Extracts all `jobs` into `dir` in order, reporting progress to `observer`.
+pub(super) fn extract_all<R: Read + Seek>(
+    jobs: &[Job],
+    stream: &mut R,
+    limits: EntryLimits,
+    dir: &Path,
+    mut observer: impl Observer,
+) -> Result<()> {
+    let started = Instant::now();
+    for job in jobs {
+        observer.entry_declared(&job.name);
+    }
+
+    let total = jobs
+        .iter()
+        .filter(|job| !index::is_dir_name(&job.name))
+        .count();
+    let mut totals = Totals::default();
+    for job in jobs {
+        let entry_started = Instant::now();
+        let mut entry = job.handle(&mut *stream, limits);
+        let bytes_written = extract_file(&mut entry, dir)?;
+        if entry.is_dir() {
+            continue;
+        }
+        let event = EntryEvent {
+            name: &job.name,
+            index: totals.entries,
+            total,
+            bytes_read: entry.compressed_size(),
+            bytes_written,
+            elapsed: entry_started.elapsed(),
+        };
+        observer.entry_done(&event);
+        totals.add(&event);
+    }
+    totals.elapsed = started.elapsed();
+    observer.finished(&totals);
+    Ok(())
+}
+
+/// Converts an entry name into a relative path that cannot escape the output directory.
+///
+/// Backslashes are rejected because they are path separators on Windows,
+/// and the converted path may only contain normal components,
+/// which excludes prefixes such as `C:` on Windows.
+fn entry_path(name: &[u8]) -> Result<PathBuf> {
+    let invalid = |err: &dyn std::fmt::Display| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "cannot extract entry {:?}: {}",
+                String::from_utf8_lossy(name),
+                err
+            ),
+        )
+    };
+    if name.contains(&b'\\') {
+        return Err(invalid(&"backslashes are not allowed in names"));
+    }
+    let normalized = path::normalize(name).map_err(|err| invalid(&err))?;
+    let normalized = normalized.strip_suffix(b"/").unwrap_or(normalized.as_ref());
+
+    #[cfg(unix)]
+    let path = {
+        use std::os::unix::ffi::OsStrExt;
+        PathBuf::from(std::ffi::OsStr::from_bytes(normalized))
+    };
+    #[cfg(not(unix))]
+    let path = PathBuf::from(std::str::from_utf8(normalized).map_err(|err| invalid(&err))?);
+
+    if path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        Ok(path)
+    } else {
+        Err(invalid(&"the name is not a relative path"))
+    }
+}
+
+/// Fails if a directory that already exists between `dir` and the entry at `relative`
+/// is a symbolic link, through which the entry would be extracted outside `dir`.
+fn check_parents(dir: &Path, relative: &Path) -> Result<()> {
+    let mut current = dir.to_owned();
+    for component in relative.parent().into_iter().flat_map(Path::components) {
+        current.push(component);
+        match fs::symlink_metadata(&current) {
+            Ok(stat) if stat.file_type().is_symlink() => {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "cannot extract {} through symbolic link {}",
+                        relative.display(),
+                        current.display()
+                    ),
+                ))
+            }
+            Ok(_) => {}
+            // the rest of the path is created by the extraction
+            Err(err) if err.kind() == ErrorKind::NotFound => break,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_mode(file: &fs::File, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    // entries written by tools that do not record permissions would otherwise be unreadable
+    if mode != 0 {
+        file.set_permissions(fs::Permissions::from_mode(mode))?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_mode(_: &fs::File, _: u32) -> Result<()> {
+    Ok(())
+}
+
+/// Runs `f` on the handle of each job, each with its own stream from `open`,
+/// on the rayon thread pool.
+///
+/// All jobs are run even if some fail,
+/// and the error of the earliest failing job in manifest order is returned.
+#[cfg(feature = "rayon")]
+pub(super) fn par_run<R, O, F>(jobs: &[Job], limits: EntryLimits, open: O, f: F) -> Result<()>
+where
+    R: Read + Seek,
+    O: Fn() -> R + Sync,
+    F: Fn(&mut EntryHandle<'_, R>) -> Result<()> + Sync,
+{
+    use rayon::prelude::*;
+
+    let results: Vec<Result<()>> = jobs
+        .par_iter()
+        .map(|job| f(&mut job.handle(&mut open(), limits)))
+        .collect();
+    results.into_iter().collect()
+}
@@ -0,0 +1,254 @@
+use std::io::{Error, ErrorKind, Result};
+
+/// A glob pattern for matching entry names.
+///
+/// The following syntax is supported:
+///
+/// - `?` matches any byte except `/`
+/// - `*` matches any sequence of bytes not containing `/`
+/// - `**` matches any sequence of bytes;
+///   `**/` matches zero or more whole directories
+/// - `[abc]`, `[a-z]` matches any of the listed bytes or ranges,
+///   and `[!abc]` matches any byte not listed, except `/`
+/// - all other bytes match themselves
+///
+/// Patterns are matched against the whole entry name,
+/// e.g. `*.php` only matches files at the archive root,
+/// while `**/*.php` matches PHP files in all directories.
+///
+/// Matching runs in `O(nm)` time for names of length `n` and patterns of length `m`,
+/// so untrusted patterns cannot cause excessive backtracking.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Glob {
+    tokens: Vec<Token>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Literal(u8),
+    Any,
+    Class {
+        ranges: Vec<(u8, u8)>,
+        negated: bool,
+    },
+    Star,
+    DoubleStar,
+    DoubleStarSlash,
+}
+
+impl Glob {
+    /// Parses a glob pattern.
+    ///
+    /// Returns an error if a `[` is not closed.
+    pub fn new(pattern: &[u8]) -> Result<Self> {
+        let mut tokens = Vec::new();
+        let mut rest = pattern;
+        while let Some((&byte, after_byte)) = rest.split_first() {
+            rest = after_byte;
+            let token = match byte {
+                b'?' => Token::Any,
+                b'*' => match rest {
+                    [b'*', b'/', after_slash @ ..] => {
+                        rest = after_slash;
+                        Token::DoubleStarSlash
+                    }
+                    [b'*', after_star @ ..] => {
+                        rest = after_star;
+                        Token::DoubleStar
+                    }
+                    _ => Token::Star,
+                },
+                b'[' => {
+                    let (token, after_class) = parse_class(rest).ok_or_else(|| {
+                        Error::new(ErrorKind::InvalidInput, "unclosed [ in glob pattern")
+                    })?;
+                    rest = after_class;
+                    token
+                }
+                byte => Token::Literal(byte),
+            };
+            tokens.push(token);
+        }
+        Ok(Self { tokens })
+    }
+
+    /// Returns the literal bytes at the start of the pattern.
+    ///
+    /// All names matching the pattern start with this prefix.
+    pub fn literal_prefix(&self) -> Vec<u8> {
+        self.tokens
+            .iter()
+            .map_while(|token| match token {
+                Token::Literal(byte) => Some(*byte),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Checks whether `name` matches the whole pattern.
+    pub fn is_match(&self, name: &[u8]) -> bool {
+        // states[i] is true if the first i tokens can match the bytes consumed so far;
+        // inside[i] is true if token i is `**/` and is in the middle of a directory name,
+        // where it cannot match the rest of the pattern yet
+        let len = self.tokens.len() + 1;
+        let mut states = vec![false; len];
+        let mut inside = vec![false; len];
+        let mut next = states.clone();
+        let mut next_inside = inside.clone();
+        set(&mut states, 0);
+        self.close(&mut states);
+
+        for &byte in name {
+            next.iter_mut().for_each(|state| *state = false);
+            next_inside.iter_mut().for_each(|state| *state = false);
+            for (i, token) in self.tokens.iter().enumerate() {
+                let active = states.get(i) == Some(&true);
+                if let Token::DoubleStarSlash = token {
+                    if active || inside.get(i) == Some(&true) {
+                        // a slash ends a directory, so the rest of the pattern may follow
+                        if byte == b'/' {
+                            set(&mut next, i);
+                        }
+                        set(&mut next_inside, i);
+                    }
+                    continue;
+                }
+                if !active {
+                    continue;
+                }
+                let (stay, advance) = token.step(byte);
+                if stay {
+                    set(&mut next, i);
+                }
+                if advance {
+                    set(&mut next, i + 1);
+                }
+            }
+            self.close(&mut next);
+            std::mem::swap(&mut states, &mut next);
+            std::mem::swap(&mut inside, &mut next_inside);
+            if !states.contains(&true) && !inside.contains(&true) {
+                return false;
+            }
+        }
+
+        states.last() == Some(&true)
+    }
+
+    /// Activates the states reachable by matching stars with zero bytes.
+    fn close(&self, states: &mut [bool]) {
+        for (i, token) in self.tokens.iter().enumerate() {
+            if states.get(i) == Some(&true)
+                && matches!(
+                    token,
+                    Token::Star | Token::DoubleStar | Token::DoubleStarSlash
+                )
+            {
+                set(states, i + 1);
+            }
+        }
+    }
+}
+
+impl Token {
+    /// Returns whether matching `byte` stays at this token
+    /// and whether it advances to the next token.
+    fn step(&self, byte: u8) -> (bool, bool) {
+        match self {
+            Self::Literal(literal) => (false, *literal == byte),
+            Self::Any => (false, byte != b'/'),
+            Self::Class { ranges, negated } => {
+                let listed = ranges
+                    .iter()
+                    .any(|&(start, end)| (start..=end).contains(&byte));
+                (false, byte != b'/' && listed != *negated)
+            }
+            Self::Star => (byte != b'/', false),
+            Self::DoubleStar => (true, false),
+            Self::DoubleStarSlash => unreachable!("`**/` is handled by Glob::is_match"),
+        }
+    }
+}
+
+fn set(states: &mut [bool], i: usize) {
+    if let Some(state) = states.get_mut(i) {
+        *state = true;
+    }
+}
+
+/// Parses a character class after the opening `[`,
+/// returning the class and the rest of the pattern after the closing `]`.
+fn parse_class(pattern: &[u8]) -> Option<(Token, &[u8])> {
+    let (negated, mut rest) = match pattern {
+        [b'!', rest @ ..] => (true, rest),
+        rest => (false, rest),
+    };
+
+    let mut ranges = Vec::new();
+    let mut first = true;
+    loop {
+        match rest {
+            [] => return None,
+            // a `]` at the start of the class is a literal
+            [b']', tail @ ..] if !first => {
+                return Some((Token::Class { ranges, negated }, tail));
+            }
+            [start, b'-', end, tail @ ..] if *end != b']' => {
+                ranges.push((*start, *end));
+                rest = tail;
+            }
+            [byte, tail @ ..] => {
+                ranges.push((*byte, *byte));
+                rest = tail;
+            }
+        }
+        first = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Glob;
+
+    fn is_match(pattern: &str, name: &str) -> bool {
+        Glob::new(pattern.as_bytes())
+            .expect("pattern is valid")
+            .is_match(name.as_bytes())
+    }
+
+    #[test]
+    fn glob_match() {
+        assert!(is_match("*.php", "a.php"));
+        assert!(!is_match("*.php", "src/a.php"));
+        assert!(is_match("**/*.php", "a.php"));
+        assert!(is_match("**/*.php", "src/lib/a.php"));
+        assert!(!is_match("**/*.php", "src/lib/a.phpx"));
+        assert!(is_match("src/**", "src/lib/a.php"));
+        assert!(!is_match("src/**", "srcx/a.php"));
+        assert!(is_match("src/**/test/*.php", "src/test/a.php"));
+        assert!(is_match("src/**/test/*.php", "src/a/b/test/a.php"));
+        assert!(!is_match("src/**/test/*.php", "src/a/btest/a.php"));
+        assert!(!is_match("src/**/test/*.php", "src/atest/a.php"));
+        assert!(is_match("a?c", "abc"));
+        assert!(!is_match("a?c", "a/c"));
+        assert!(is_match("[a-c]x[!0-9]", "bxy"));
+        assert!(!is_match("[a-c]x[!0-9]", "bx1"));
+        assert!(is_match("[]]", "]"));
+        assert!(is_match("", ""));
+        assert!(!is_match("", "a"));
+    }
+
+    #[test]
+    fn glob_literal_prefix() {
+        let glob = Glob::new(b"src/lib/*.php").expect("pattern is valid");
+        assert_eq!(glob.literal_prefix(), b"src/lib/".to_vec());
+        assert!(Glob::new(b"src/[ab").is_err());
+    }
+
+    #[test]
+    fn glob_pathological() {
+        let pattern = "*a".repeat(64);
+        let name = "a".repeat(4096) + "b";
+        assert!(!is_match(&pattern, &name));
+    }
+}
@@ -0,0 +1,260 @@
+use std::io::{Read, Result, Seek, SeekFrom};
+use std::ops::Range;
+use std::time::{Duration, SystemTime};
+
+use super::index::{self, adapted_reader, Iterable};
+use super::limits::EntryLimits;
+use super::EntryReader;
+use crate::{Compression, CompressionMethod};
+
+/// The fields of a file entry stored in the manifest.
+///
+/// These are available without reading the entry contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntryInfo {
+    pub(super) flags: u32,
+    pub(super) original_size: u32,
+    pub(super) time: i32,
+    pub(super) crc32: u32,
+    pub(super) position: u32,
+    pub(super) metadata_offset: u64,
+    pub(super) metadata_len: u32,
+}
+
+impl EntryInfo {
+    /// Returns the compression method of this entry.
+    ///
+    /// The compression level is not stored in the manifest;
+    /// use `EntryHandle::estimate_compression` to estimate it.
+    pub fn compression(&self) -> CompressionMethod {
+        CompressionMethod::from_bit(self.flags)
+    }
+
+    /// Estimates the compression settings from the first bytes of the compressed contents.
+    ///
+    /// Returns `None` if the level cannot be determined.
+    pub(super) fn estimate_compression(&self, header: &[u8]) -> Option<Compression> {
+        let method = self.compression();
+        match method {
+            CompressionMethod::None => Some(Compression::None),
+            CompressionMethod::Zlib => method.estimate_level(header).map(Compression::Zlib),
+            CompressionMethod::Bzip => method.estimate_level(header).map(Compression::Bzip),
+        }
+    }
+
+    /// Returns the entry flags, including the permission bits.
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    /// Returns the decompressed size declared in the manifest.
+    pub fn original_size(&self) -> u32 {
+        self.original_size
+    }
+
+    /// Returns the modification time as a Unix timestamp.
+    pub fn timestamp(&self) -> i32 {
+        self.time
+    }
+
+    /// Returns the modification time.
+    pub fn modified(&self) -> SystemTime {
+        let secs = Duration::from_secs(self.time.unsigned_abs().into());
+        if self.time >= 0 {
+            SystemTime::UNIX_EPOCH + secs
+        } else {
+            SystemTime::UNIX_EPOCH - secs
+        }
+    }
+
+    /// Returns the CRC32 checksum of the decompressed contents declared in the manifest.
+    pub fn crc32(&self) -> u32 {
+        self.crc32
+    }
+
+    /// Returns the zero-based position of the entry in the manifest.
+    ///
+    /// Indices other than `OffsetOnly`, `NameIndexMap` and `MetadataIndexMap`
+    /// may iterate entries in a different order,
+    /// so sort by this to restore the manifest order.
+    pub fn position(&self) -> u32 {
+        self.position
+    }
+
+    /// Returns the length of the entry metadata.
+    pub fn metadata_len(&self) -> u32 {
+        self.metadata_len
+    }
+}
+
+#[cfg(feature = "writer")]
+impl From<EntryInfo> for crate::write::RawEntry {
+    fn from(info: EntryInfo) -> Self {
+        Self {
+            original_size: info.original_size,
+            timestamp: info.time,
+            crc32: info.crc32,
+            flags: info.flags,
+        }
+    }
+}
+
+/// An entry whose contents have not been opened yet.
+///
+/// This is passed to the query methods of `Reader`,
+/// such as `Reader::for_each_entry` and `Reader::for_each_glob`.
+/// The contents are only read from the underlying stream when `open` is called.
+#[derive(Debug)]
+pub struct EntryHandle<'t, R: Read + Seek> {
+    name: &'t [u8],
+    info: EntryInfo,
+    range: Range<u64>,
+    stream: &'t mut R,
+    limits: EntryLimits,
+}
+
+impl<'t, R: Read + Seek> EntryHandle<'t, R> {
+    pub(super) fn new(
+        name: &'t [u8],
+        info: EntryInfo,
+        range: Range<u64>,
+        stream: &'t mut R,
+        limits: EntryLimits,
+    ) -> Self {
+        Self {
+            name,
+            info,
+            range,
+            stream,
+            limits,
+        }
+    }
+
+    /// Returns the name of the entry.
+    pub fn name(&self) -> &'t [u8] {
+        self.name
+    }
+
+    /// Whether the entry is a directory, i.e. its name ends with a slash.
+    pub fn is_dir(&self) -> bool {
+        index::is_dir_name(self.name)
+    }
+
+    /// Returns the fields of the entry stored in the manifest.
+    pub fn info(&self) -> EntryInfo {
+        self.info
+    }
+
+    /// Returns the position of the contents in the stream.
+    pub(super) fn range(&self) -> Range<u64> {
+        self.range.clone()
+    }
+
+    /// Returns the size of the contents as stored in the archive, i.e. possibly compressed.
+    pub fn compressed_size(&self) -> u64 {
+        self.range.end - self.range.start
+    }
+
+    /// Returns an `io::Read` over the decompressed contents.
+    ///
+    /// The decompression limits in `Options` are applied on the returned reader.
+    pub fn open(&mut self) -> Result<impl Read + '_> {
+        EntryHandle::new(
+            self.name,
+            self.info,
+            self.range.clone(),
+            &mut *self.stream,
+            self.limits,
+        )
+        .into_reader()
+    }
+
+    /// Like `open`, but consumes the handle,
+    /// so that the returned reader can outlive it.
+    pub fn into_reader(self) -> Result<impl Read + 't> {
+        let size = self.compressed_size();
+        let compressed = self.info.compression().bit() != 0;
+        let _ = self.stream.seek(SeekFrom::Start(self.range.start))?;
+        let decompressed = adapted_reader(self.info.flags, self.stream.take(size))?;
+        Ok(self.limits.wrap(decompressed, compressed, size))
+    }
+
+    /// Returns an `io::Read` over the contents as stored in the archive, i.e. possibly compressed.
+    ///
+    /// Decompression limits in `Options` do not apply to the returned reader.
+    /// Together with `info()` and `metadata()`,
+    /// this can be passed to `write::NeedEntries::raw_entry`
+    /// to copy the entry into another archive without recompression.
+    pub fn open_raw(&mut self) -> Result<impl Read + '_> {
+        let size = self.compressed_size();
+        let _ = self.stream.seek(SeekFrom::Start(self.range.start))?;
+        Ok((&mut *self.stream).take(size))
+    }
+
+    /// Estimates the compression settings of this entry
+    /// by reading the first bytes of its compressed contents.
+    ///
+    /// bzip2 streams record the level exactly.
+    /// Raw deflate streams, as written by PHP, do not record the level,
+    /// so `None` is returned for zlib entries,
+    /// as well as for bzip2 streams with a corrupt header;
+    /// `EntryInfo::compression` still returns the compression method of such entries.
+    pub fn estimate_compression(&mut self) -> Result<Option<Compression>> {
+        let mut header = Vec::with_capacity(4);
+        let _ = self.open_raw()?.take(4).read_to_end(&mut header)?;
+        Ok(self.info.estimate_compression(&header))
+    }
+
+    /// Reads the entry metadata from the stream.
+    pub fn metadata(&mut self) -> Result<Vec<u8>> {
+        let mut metadata = Vec::new();
+        let _ = self
+            .stream
+            .seek(SeekFrom::Start(self.info.metadata_offset))?;
+        let _ = (&mut *self.stream)
+            .take(self.info.metadata_len.into())
+            .read_to_end(&mut metadata)?;
+        Ok(metadata)
+    }
+
+    /// Returns an `io::Read + io::Seek` over the decompressed contents.
+    ///
+    /// See `EntryReader` for the cost of seeking in compressed entries.
+    pub fn open_seekable(&mut self) -> Result<EntryReader<&mut R>> {
+        EntryReader::new(
+            &mut *self.stream,
+            self.info.compression(),
+            self.range.start,
+            self.range.end - self.range.start,
+            self.info.original_size.into(),
+            self.limits,
+        )
+    }
+}
+
+/// Calls `f` on each entry whose name starts with `prefix` and satisfies `filter`.
+///
+/// No contents are read unless `f` opens the handle.
+pub(super) fn for_each_matching<I, R, P, F>(
+    index: &I,
+    stream: &mut R,
+    limits: EntryLimits,
+    prefix: &[u8],
+    mut filter: P,
+    mut f: F,
+) -> Result<()>
+where
+    I: Iterable,
+    R: Read + Seek,
+    P: FnMut(&[u8]) -> bool,
+    F: FnMut(EntryHandle<'_, R>) -> Result<()>,
+{
+    index.for_each_entry_range_with_prefix(prefix, |name, info, range| {
+        let name = name.as_memory(&mut *stream)?;
+        if !name.starts_with(prefix) || !filter(&name) {
+            return Ok(());
+        }
+
+        f(EntryHandle::new(&name, info, range, &mut *stream, limits))
+    })
+}
@@ -1,12 +1,19 @@
 //! `FileIndex` implementations
 
+use std::borrow::Cow;
 use std::collections::{BTreeMap, HashMap};
-use std::io::{Read, Result, Seek, SeekFrom};
+use std::hash::BuildHasher;
+use std::io::{Read, Result, Seek};
 use std::iter::{self, Extend};
-use std::ops::Range;
+use std::ops::{Bound, Range};
 
+use indexmap::IndexMap;
+
+use super::handle::{for_each_matching, EntryInfo};
+use super::limits::EntryLimits;
+use super::section::SectionRef;
 use super::{Entry, Section};
-use crate::Compression;
+use crate::{path, CompressionMethod};
 
 /// The storage used to store file indices.
 ///
@@ -20,9 +27,13 @@ use crate::Compression;
 ///
 /// To access specific files only,
 /// use `index::RandomAccess` implementations.
+/// To list directories without scanning all file names,
+/// use `Tree`.
 /// Prefer using `NameMap` if individual entry metadata is not required.
 /// To also access their metadata,
 /// use `MetadataMap`.
+/// To iterate in the manifest order,
+/// use `NameIndexMap` or `MetadataIndexMap`.
 /// There are some type aliases for the respective HashMap/BTreeMap implementations.
 pub trait FileIndex: Default {
     /// Whether file metadata should be scanned on loading.
@@ -52,7 +63,31 @@ pub trait FileIndex: Default {
 /// The iteration order may not be the order in the phar archive,
 /// and may not even be stable.
 pub trait Iterable: FileIndex {
+    /// Iterates over the entries in this index without reading their contents.
+    ///
+    /// `f` receives the entry name, the entry fields
+    /// and the absolute offset range of the (compressed) contents in the stream.
+    #[doc(hidden)]
+    fn for_each_entry_range<'t, F>(&'t self, f: F) -> Result<()>
+    where
+        F: FnMut(SectionRef<'t>, EntryInfo, Range<u64>) -> Result<()>;
+
+    /// Iterates over the entries whose names may start with `prefix`.
+    ///
+    /// Indices sorted by name only visit the matching entries,
+    /// but other indices may visit all entries,
+    /// so callers must still check the names.
+    #[doc(hidden)]
+    fn for_each_entry_range_with_prefix<'t, F>(&'t self, _prefix: &[u8], f: F) -> Result<()>
+    where
+        F: FnMut(SectionRef<'t>, EntryInfo, Range<u64>) -> Result<()>,
+    {
+        self.for_each_entry_range(f)
+    }
+
     /// Iterates over the files in this index.
+    ///
+    /// Directory entries are skipped.
     fn for_each_file<'t, R, F>(&self, read: R, f: F) -> Result<()>
     where
         R: Read + Seek + 't,
@@ -66,13 +101,46 @@ pub trait Iterable: FileIndex {
     where
         R: Read + Seek + 't,
         F: FnMut(&[u8], &mut (dyn Read)) -> Result<U>,
-        G: FnMut(Option<T>, U) -> T;
+        G: FnMut(Option<T>, U) -> T,
+    {
+        fold_files(self, read, f, fold, &EntryLimits::default())
+    }
+}
+
+/// Implements `Iterable::for_each_file_fold`, applying `limits` on decompressed contents.
+pub(crate) fn fold_files<I, R, F, G, T, U>(
+    index: &I,
+    mut read: R,
+    mut f: F,
+    mut fold: G,
+    limits: &EntryLimits,
+) -> Result<Option<T>>
+where
+    I: Iterable,
+    R: Read + Seek,
+    F: FnMut(&[u8], &mut (dyn Read)) -> Result<U>,
+    G: FnMut(Option<T>, U) -> T,
+{
+    let mut reduced = None;
+
+    let is_file = |name: &[u8]| !is_dir_name(name);
+    for_each_matching(index, &mut read, *limits, b"", is_file, |mut entry| {
+        let name = entry.name();
+        let mapped = f(name, &mut entry.open()?)?;
+        reduced = Some(fold(reduced.take(), mapped));
+        Ok(())
+    })?;
+
+    Ok(reduced)
 }
 
 /// A subfamily of file indices for random access of files by name.
 pub trait RandomAccess: FileIndex {
     /// Returns the file contents range of the file of the required name.
     ///
+    /// If no entry has exactly the specified name,
+    /// the name is normalized with `path::normalize` and looked up again,
+    /// so `/src/a.php` finds `src/a.php`.
     /// Returns `None` if there are no files with the specified name.
     fn read_file(&self, name: &[u8]) -> Option<Range<u64>>;
 }
@@ -100,6 +168,11 @@ impl FileIndex for NoIndex {
 /// where `n` is the number of files,
 /// and `m` is either `1` or the length of filenames
 /// depending on whether files are cached.
+///
+/// Entries with duplicate names are not collapsed by default,
+/// so all of them are iterated in the manifest order.
+/// Setting `Options::duplicate_names` collapses or rejects them,
+/// at the cost of keeping all names in memory while parsing the manifest.
 #[derive(Debug, Default)]
 pub struct OffsetOnly {
     content_offset: u64,
@@ -109,21 +182,16 @@ pub struct OffsetOnly {
 #[derive(Debug)]
 struct OffsetOnlyEntry {
     name: Section,
-    flags: u32,
-    end_offset_from_co: u64,
+    info: EntryInfo,
+    range_from_co: Range<u64>,
 }
 
 impl FileIndex for OffsetOnly {
     fn feed_entry(&mut self, _: u64, entry: Entry) -> Result<()> {
-        let prev = match self.entries.last() {
-            Some(ooe) => ooe.end_offset_from_co,
-            None => 0,
-        };
-        let size: u64 = entry.compressed_file_size.into();
         self.entries.push(OffsetOnlyEntry {
+            info: entry.info(),
+            range_from_co: entry.contents_range(),
             name: entry.name,
-            flags: entry.flags,
-            end_offset_from_co: prev + size,
         });
         Ok(())
     }
@@ -134,40 +202,21 @@ impl FileIndex for OffsetOnly {
 }
 
 impl Iterable for OffsetOnly {
-    fn for_each_file_fold<'t, R, F, G, T, U>(
-        &self,
-        mut read: R,
-        mut f: F,
-        mut fold: G,
-    ) -> Result<Option<T>>
+    fn for_each_entry_range<'t, F>(&'t self, mut f: F) -> Result<()>
     where
-        R: Read + Seek + 't,
-        F: FnMut(&[u8], &mut (dyn Read)) -> Result<U>,
-        G: FnMut(Option<T>, U) -> T,
+        F: FnMut(SectionRef<'t>, EntryInfo, Range<u64>) -> Result<()>,
     {
-        let mut start_offset = self.content_offset;
-        let mut reduced = None;
-
         for OffsetOnlyEntry {
             name,
-            flags,
-            end_offset_from_co,
+            info,
+            range_from_co: Range { start, end },
         } in &self.entries
         {
-            let name = name.as_memory(&mut read)?;
-            let name = name.as_ref();
-            let end_offset = *end_offset_from_co + self.content_offset;
-
-            let _ = read.seek(SeekFrom::Start(start_offset))?;
-            let mut decompressed =
-                adapted_reader(*flags, (&mut read).take(end_offset - start_offset))?;
-            let mapped = f(name, &mut decompressed)?;
-            reduced = Some(fold(reduced, mapped));
-
-            start_offset = end_offset;
+            let range = (*start + self.content_offset)..(*end + self.content_offset);
+            f(name.as_ref(), *info, range)?;
         }
 
-        Ok(reduced)
+        Ok(())
     }
 }
 
@@ -175,13 +224,12 @@ impl Iterable for OffsetOnly {
 #[derive(Debug, Default)]
 pub struct NameMap<M> {
     map: M,
-    last_offset: u64,
     content_offset: u64,
 }
 
-impl<M: Default + Extend<(Vec<u8>, (u32, Range<u64>))>> FileIndex for NameMap<M>
+impl<M: Default + Extend<(Vec<u8>, (EntryInfo, Range<u64>))>> FileIndex for NameMap<M>
 where
-    for<'t> &'t M: IntoIterator<Item = (&'t Vec<u8>, &'t (u32, Range<u64>))>,
+    for<'t> &'t M: IntoIterator<Item = (&'t Vec<u8>, &'t (EntryInfo, Range<u64>))>,
 {
     fn requires_name() -> bool {
         true
@@ -192,59 +240,71 @@ where
     }
 
     fn feed_entry(&mut self, _: u64, entry: Entry) -> Result<()> {
-        let len: u64 = entry.compressed_file_size.into();
+        let info = entry.info();
+        let range = entry.contents_range();
 
         let name = match entry.name {
             Section::Cached(cache) => cache,
             _ => unreachable!("requires_name is set to true"),
         };
-        let start = self.last_offset;
-        let end = start + len;
-        self.last_offset = end;
-        self.map
-            .extend(iter::once((name, (entry.flags, start..end))));
+        self.map.extend(iter::once((name, (info, range))));
         Ok(())
     }
 }
 
-impl<M: Default + Extend<(Vec<u8>, (u32, Range<u64>))>> Iterable for NameMap<M>
+impl<M> Iterable for NameMap<M>
 where
-    for<'t> &'t M: IntoIterator<Item = (&'t Vec<u8>, &'t (u32, Range<u64>))>,
+    M: Default + Extend<(Vec<u8>, (EntryInfo, Range<u64>))> + EntryMap<(EntryInfo, Range<u64>)>,
+    for<'t> &'t M: IntoIterator<Item = (&'t Vec<u8>, &'t (EntryInfo, Range<u64>))>,
 {
-    fn for_each_file_fold<'t, R, F, G, T, U>(
-        &self,
-        mut read: R,
-        mut f: F,
-        mut fold: G,
-    ) -> Result<Option<T>>
+    fn for_each_entry_range<'t, F>(&'t self, mut f: F) -> Result<()>
     where
-        R: Read + Seek + 't,
-        F: FnMut(&[u8], &mut (dyn Read)) -> Result<U>,
-        G: FnMut(Option<T>, U) -> T,
+        F: FnMut(SectionRef<'t>, EntryInfo, Range<u64>) -> Result<()>,
     {
-        let mut reduced = None;
-
-        for (name, (flags, Range { start, end })) in &self.map {
-            let _ = read.seek(SeekFrom::Start(*start + self.content_offset))?;
-            let mut decompressed = adapted_reader(*flags, (&mut read).take(end - start))?;
-            let mapped = f(name, &mut decompressed)?;
-            reduced = Some(fold(reduced, mapped));
+        for (name, (info, Range { start, end })) in &self.map {
+            let range = (*start + self.content_offset)..(*end + self.content_offset);
+            f(SectionRef::Cached(name), *info, range)?;
         }
 
-        Ok(reduced)
+        Ok(())
+    }
+
+    fn for_each_entry_range_with_prefix<'t, F>(&'t self, prefix: &[u8], mut f: F) -> Result<()>
+    where
+        F: FnMut(SectionRef<'t>, EntryInfo, Range<u64>) -> Result<()>,
+    {
+        self.map
+            .for_each_with_prefix(prefix, |name, (info, Range { start, end })| {
+                let range = (*start + self.content_offset)..(*end + self.content_offset);
+                f(SectionRef::Cached(name), *info, range)
+            })
     }
 }
 
 /// Indexes files by name with a HashMap.
-pub type NameHashMap = NameMap<HashMap<Vec<u8>, (u32, Range<u64>)>>;
+pub type NameHashMap = NameMap<HashMap<Vec<u8>, (EntryInfo, Range<u64>)>>;
 /// Indexes files by name with a BTreeMap.
-pub type NameBTreeMap = NameMap<BTreeMap<Vec<u8>, (u32, Range<u64>)>>;
+pub type NameBTreeMap = NameMap<BTreeMap<Vec<u8>, (EntryInfo, Range<u64>)>>;
+/// Indexes files by name with an IndexMap.
+///
+/// Iteration follows the manifest order.
+pub type NameIndexMap = NameMap<IndexMap<Vec<u8>, (EntryInfo, Range<u64>)>>;
+
+impl<M> RandomAccess for NameMap<M>
+where
+    M: Default + Extend<(Vec<u8>, (EntryInfo, Range<u64>))> + EntryMap<(EntryInfo, Range<u64>)>,
+    for<'t> &'t M: IntoIterator<Item = (&'t Vec<u8>, &'t (EntryInfo, Range<u64>))>,
+{
+    fn read_file(&self, name: &[u8]) -> Option<Range<u64>> {
+        let (_, Range { start, end }) = lookup(name, |name| self.map.get_entry(name))?;
+        Some((*start + self.content_offset)..(*end + self.content_offset))
+    }
+}
 
 /// Indexes files by name for random access, and stores file metadata.
 #[derive(Debug, Default)]
 pub struct MetadataMap<M> {
     pub(crate) map: M,
-    last_offset: u64,
     content_offset: u64,
 }
 
@@ -269,41 +329,110 @@ where
             Section::Cached(cache) => cache,
             _ => unreachable!("requires_name is set to true"),
         };
-        let start = self.last_offset;
-        let len: u64 = entry.compressed_file_size.into();
-        let end = start + len;
-        self.last_offset = end;
-        self.map
-            .extend(iter::once((name.clone(), (entry, start..end))));
+        let range = entry.contents_range();
+        self.map.extend(iter::once((name.clone(), (entry, range))));
         Ok(())
     }
 }
 
-impl<M: Default + Extend<(Vec<u8>, (Entry, Range<u64>))>> Iterable for MetadataMap<M>
+impl<M> Iterable for MetadataMap<M>
 where
+    M: Default + Extend<(Vec<u8>, (Entry, Range<u64>))> + EntryMap<(Entry, Range<u64>)>,
     for<'t> &'t M: IntoIterator<Item = (&'t Vec<u8>, &'t (Entry, Range<u64>))>,
 {
-    fn for_each_file_fold<'t, R, F, G, T, U>(
-        &self,
-        mut read: R,
-        mut f: F,
-        mut fold: G,
-    ) -> Result<Option<T>>
+    fn for_each_entry_range<'t, F>(&'t self, mut f: F) -> Result<()>
     where
-        R: Read + Seek + 't,
-        F: FnMut(&[u8], &mut (dyn Read)) -> Result<U>,
-        G: FnMut(Option<T>, U) -> T,
+        F: FnMut(SectionRef<'t>, EntryInfo, Range<u64>) -> Result<()>,
     {
-        let mut reduced = None;
-
         for (name, (entry, Range { start, end })) in &self.map {
-            let _ = read.seek(SeekFrom::Start(*start + self.content_offset))?;
-            let mut decompressed = adapted_reader(entry.flags, (&mut read).take(end - start))?;
-            let mapped = f(name, &mut decompressed)?;
-            reduced = Some(fold(reduced, mapped));
+            let range = (*start + self.content_offset)..(*end + self.content_offset);
+            f(SectionRef::Cached(name), entry.info(), range)?;
+        }
+
+        Ok(())
+    }
+
+    fn for_each_entry_range_with_prefix<'t, F>(&'t self, prefix: &[u8], mut f: F) -> Result<()>
+    where
+        F: FnMut(SectionRef<'t>, EntryInfo, Range<u64>) -> Result<()>,
+    {
+        self.map
+            .for_each_with_prefix(prefix, |name, (entry, Range { start, end })| {
+                let range = (*start + self.content_offset)..(*end + self.content_offset);
+                f(SectionRef::Cached(name), entry.info(), range)
+            })
+    }
+}
+
+/// Maps from entry names used as the storage of `NameMap` and `MetadataMap`.
+#[doc(hidden)]
+pub trait EntryMap<V> {
+    /// Returns the value for the entry named `name`.
+    fn get_entry(&self, name: &[u8]) -> Option<&V>;
+
+    /// Calls `f` on the entries whose names start with `prefix`.
+    ///
+    /// `BTreeMap` only visits the matching range,
+    /// while other maps scan all entries.
+    fn for_each_with_prefix<'t, F>(&'t self, prefix: &[u8], f: F) -> Result<()>
+    where
+        V: 't,
+        F: FnMut(&'t Vec<u8>, &'t V) -> Result<()>;
+}
+
+impl<V, S: BuildHasher> EntryMap<V> for HashMap<Vec<u8>, V, S> {
+    fn get_entry(&self, name: &[u8]) -> Option<&V> {
+        self.get(name)
+    }
+
+    fn for_each_with_prefix<'t, F>(&'t self, prefix: &[u8], mut f: F) -> Result<()>
+    where
+        V: 't,
+        F: FnMut(&'t Vec<u8>, &'t V) -> Result<()>,
+    {
+        for (name, value) in self {
+            if name.starts_with(prefix) {
+                f(name, value)?;
+            }
         }
+        Ok(())
+    }
+}
+
+impl<V> EntryMap<V> for BTreeMap<Vec<u8>, V> {
+    fn get_entry(&self, name: &[u8]) -> Option<&V> {
+        self.get(name)
+    }
+
+    fn for_each_with_prefix<'t, F>(&'t self, prefix: &[u8], mut f: F) -> Result<()>
+    where
+        V: 't,
+        F: FnMut(&'t Vec<u8>, &'t V) -> Result<()>,
+    {
+        let range = self.range::<[u8], _>((Bound::Included(prefix), Bound::Unbounded));
+        for (name, value) in range.take_while(|(name, _)| name.starts_with(prefix)) {
+            f(name, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl<V, S: BuildHasher> EntryMap<V> for IndexMap<Vec<u8>, V, S> {
+    fn get_entry(&self, name: &[u8]) -> Option<&V> {
+        self.get(name)
+    }
 
-        Ok(reduced)
+    fn for_each_with_prefix<'t, F>(&'t self, prefix: &[u8], mut f: F) -> Result<()>
+    where
+        V: 't,
+        F: FnMut(&'t Vec<u8>, &'t V) -> Result<()>,
+    {
+        for (name, value) in self {
+            if name.starts_with(prefix) {
+                f(name, value)?;
+            }
+        }
+        Ok(())
     }
 }
 
@@ -313,7 +442,198 @@ pub type MetadataHashMap = MetadataMap<HashMap<Vec<u8>, (Entry, Range<u64>)>>;
 /// Indexes files by name with a BTreeMap, and stores file metadata.
 pub type MetadataBTreeMap = MetadataMap<BTreeMap<Vec<u8>, (Entry, Range<u64>)>>;
 
-fn adapted_reader<'t>(flag: u32, read: impl Read + 't) -> Result<Box<(dyn Read + 't)>> {
-    let compression = Compression::from_bit(flag);
+/// Indexes files by name with an IndexMap, and stores file metadata.
+///
+/// Iteration follows the manifest order.
+pub type MetadataIndexMap = MetadataMap<IndexMap<Vec<u8>, (Entry, Range<u64>)>>;
+
+impl<M> RandomAccess for MetadataMap<M>
+where
+    M: Default + Extend<(Vec<u8>, (Entry, Range<u64>))> + EntryMap<(Entry, Range<u64>)>,
+    for<'t> &'t M: IntoIterator<Item = (&'t Vec<u8>, &'t (Entry, Range<u64>))>,
+{
+    fn read_file(&self, name: &[u8]) -> Option<Range<u64>> {
+        let (_, Range { start, end }) = lookup(name, |name| self.map.get_entry(name))?;
+        Some((*start + self.content_offset)..(*end + self.content_offset))
+    }
+}
+
+/// Indexes entries by name, and lists directories as a tree.
+///
+/// Directories include both explicit directory entries
+/// and the parent directories implied by file names,
+/// but only explicit directory entries are visited when iterating over entries.
+/// Empty path components (e.g. from leading slashes) are ignored.
+#[derive(Debug)]
+pub struct Tree {
+    /// All entries in the archive, including explicit directory entries
+    entries: BTreeMap<Vec<u8>, (EntryInfo, Range<u64>)>,
+    /// All directories, where `dirs[0]` is the root
+    dirs: Vec<TreeDir>,
+    content_offset: u64,
+}
+
+#[derive(Debug, Default)]
+struct TreeDir {
+    /// Maps child names to their directory IDs, or `None` for files
+    children: BTreeMap<Vec<u8>, Option<usize>>,
+}
+
+impl Default for Tree {
+    fn default() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            dirs: vec![TreeDir::default()],
+            content_offset: 0,
+        }
+    }
+}
+
+impl Tree {
+    /// Adds a child to the directory `parent`,
+    /// returning the ID of the child if it is a directory.
+    fn add_child(&mut self, parent: usize, name: &[u8], is_dir: bool) -> Option<usize> {
+        let next_id = self.dirs.len();
+        let child = self
+            .dirs
+            .get_mut(parent)
+            .expect("directory IDs are valid")
+            .children
+            .entry(name.to_vec())
+            .or_insert(None);
+        match *child {
+            Some(id) => Some(id),
+            None if is_dir => {
+                *child = Some(next_id);
+                self.dirs.push(TreeDir::default());
+                Some(next_id)
+            }
+            None => None,
+        }
+    }
+
+    /// Lists the direct children of the directory at `path`, sorted by name.
+    ///
+    /// `path` is relative to the archive root,
+    /// and leading and trailing slashes are optional.
+    /// Returns `None` if there is no directory at `path`.
+    pub fn read_dir(&self, path: &[u8]) -> Option<impl Iterator<Item = DirEntry<'_>> + '_> {
+        let mut dir = self.dirs.first()?;
+        for component in path_components(path) {
+            let id = (*dir.children.get(component)?)?;
+            dir = self.dirs.get(id)?;
+        }
+
+        Some(dir.children.iter().map(|(name, id)| DirEntry {
+            name,
+            is_dir: id.is_some(),
+        }))
+    }
+}
+
+/// An entry listed by `Tree::read_dir`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirEntry<'t> {
+    name: &'t [u8],
+    is_dir: bool,
+}
+
+impl<'t> DirEntry<'t> {
+    /// Returns the name of the entry, without the parent path and slashes.
+    pub fn name(&self) -> &'t [u8] {
+        self.name
+    }
+
+    /// Whether the entry is a directory.
+    pub fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+}
+
+impl FileIndex for Tree {
+    fn requires_name() -> bool {
+        true
+    }
+
+    fn end_of_header(&mut self, offset: u64) {
+        self.content_offset = offset;
+    }
+
+    fn feed_entry(&mut self, _: u64, entry: Entry) -> Result<()> {
+        let info = entry.info();
+        let range = entry.contents_range();
+        let name = match entry.name {
+            Section::Cached(cache) => cache,
+            _ => unreachable!("requires_name is set to true"),
+        };
+
+        let is_dir = is_dir_name(&name);
+        {
+            let mut parent = Some(0);
+            let mut components = path_components(&name).peekable();
+            while let (Some(dir), Some(component)) = (parent, components.next()) {
+                let child_is_dir = is_dir || components.peek().is_some();
+                parent = self.add_child(dir, component, child_is_dir);
+            }
+        }
+
+        let _ = self.entries.insert(name, (info, range));
+        Ok(())
+    }
+}
+
+impl Iterable for Tree {
+    fn for_each_entry_range<'t, F>(&'t self, mut f: F) -> Result<()>
+    where
+        F: FnMut(SectionRef<'t>, EntryInfo, Range<u64>) -> Result<()>,
+    {
+        for (name, (info, Range { start, end })) in &self.entries {
+            let range = (*start + self.content_offset)..(*end + self.content_offset);
+            f(SectionRef::Cached(name), *info, range)?;
+        }
+
+        Ok(())
+    }
+
+    fn for_each_entry_range_with_prefix<'t, F>(&'t self, prefix: &[u8], mut f: F) -> Result<()>
+    where
+        F: FnMut(SectionRef<'t>, EntryInfo, Range<u64>) -> Result<()>,
+    {
+        self.entries
+            .for_each_with_prefix(prefix, |name, (info, Range { start, end })| {
+                let range = (*start + self.content_offset)..(*end + self.content_offset);
+                f(SectionRef::Cached(name), *info, range)
+            })
+    }
+}
+
+impl RandomAccess for Tree {
+    fn read_file(&self, name: &[u8]) -> Option<Range<u64>> {
+        let (_, Range { start, end }) = lookup(name, |name| self.entries.get(name))?;
+        Some((*start + self.content_offset)..(*end + self.content_offset))
+    }
+}
+
+/// Looks up an entry with `get`, falling back to the normalized name.
+fn lookup<'t, V>(name: &[u8], get: impl Fn(&[u8]) -> Option<&'t V>) -> Option<&'t V> {
+    get(name).or_else(|| match path::normalize(name) {
+        Ok(Cow::Owned(normalized)) => get(&normalized),
+        _ => None,
+    })
+}
+
+/// Splits a path in the archive into its non-empty components.
+fn path_components(path: &[u8]) -> impl Iterator<Item = &[u8]> {
+    path.split(|&byte| byte == b'/')
+        .filter(|component| !component.is_empty())
+}
+
+/// Whether an entry name refers to a directory, i.e. ends with a slash.
+pub(crate) fn is_dir_name(name: &[u8]) -> bool {
+    name.last() == Some(&b'/')
+}
+
+pub(crate) fn adapted_reader<'t>(flag: u32, read: impl Read + 't) -> Result<Box<(dyn Read + 't)>> {
+    let compression = CompressionMethod::from_bit(flag);
     compression.make_read(read)
 }
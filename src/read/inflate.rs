@@ -0,0 +1,124 @@
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind, Read, Result};
+
+use miniz_oxide::inflate::stream::{inflate, InflateState};
+use miniz_oxide::{DataFormat, MZError, MZFlush, MZStatus};
+
+/// The size of the buffer for compressed input.
+const INPUT_BUFFER_SIZE: usize = 1 << 15;
+
+/// A raw deflate decoder whose state can be saved and restored.
+///
+/// `flate2` does not expose its decompressor state,
+/// so this drives `miniz_oxide` directly.
+pub(crate) struct Inflater<R> {
+    read: R,
+    state: Box<InflateState>,
+    buf: Box<[u8]>,
+    /// The range of `buf` not yet consumed by `state`
+    pos: usize,
+    filled: usize,
+    /// The number of compressed bytes consumed by `state`
+    consumed: u64,
+    done: bool,
+}
+
+/// A saved decompressor state.
+#[derive(Clone)]
+pub(crate) struct InflateCheckpoint {
+    state: Box<InflateState>,
+    /// The number of compressed bytes consumed before the checkpoint
+    consumed: u64,
+}
+
+impl InflateCheckpoint {
+    /// Returns the number of compressed bytes consumed before the checkpoint,
+    /// i.e. the offset to resume reading the compressed stream from.
+    pub(crate) fn consumed(&self) -> u64 {
+        self.consumed
+    }
+}
+
+impl<R: Read> Inflater<R> {
+    /// Decompresses `read` from the start of a deflate stream.
+    pub(crate) fn new(read: R) -> Self {
+        Self::with_state(read, InflateState::new_boxed(DataFormat::Raw), 0)
+    }
+
+    /// Resumes decompression from `checkpoint`.
+    ///
+    /// `read` must start at `checkpoint.consumed()` bytes into the compressed stream.
+    pub(crate) fn resume(read: R, checkpoint: &InflateCheckpoint) -> Self {
+        Self::with_state(read, checkpoint.state.clone(), checkpoint.consumed)
+    }
+
+    fn with_state(read: R, state: Box<InflateState>, consumed: u64) -> Self {
+        Self {
+            read,
+            state,
+            buf: vec![0; INPUT_BUFFER_SIZE].into_boxed_slice(),
+            pos: 0,
+            filled: 0,
+            consumed,
+            done: false,
+        }
+    }
+
+    /// Saves the current decompressor state, including the 32 KiB window.
+    pub(crate) fn checkpoint(&self) -> InflateCheckpoint {
+        InflateCheckpoint {
+            state: self.state.clone(),
+            consumed: self.consumed,
+        }
+    }
+
+    /// Returns the underlying compressed stream.
+    ///
+    /// Buffered input that has not been decompressed is discarded.
+    pub(crate) fn into_inner(self) -> R {
+        self.read
+    }
+}
+
+impl<R: Read> Read for Inflater<R> {
+    fn read(&mut self, out: &mut [u8]) -> Result<usize> {
+        if out.is_empty() || self.done {
+            return Ok(0);
+        }
+
+        loop {
+            let mut eof = false;
+            if self.pos == self.filled {
+                self.filled = self.read.read(&mut self.buf)?;
+                self.pos = 0;
+                eof = self.filled == 0;
+            }
+
+            let input = self
+                .buf
+                .get(self.pos..self.filled)
+                .expect("pos <= filled <= len");
+            let result = inflate(&mut self.state, input, out, MZFlush::None);
+            self.pos += result.bytes_consumed;
+            self.consumed += u64::try_from(result.bytes_consumed).expect("usize <= u64");
+
+            match result.status {
+                Ok(MZStatus::StreamEnd) => {
+                    self.done = true;
+                    return Ok(result.bytes_written);
+                }
+                Ok(_) | Err(MZError::Buf) if result.bytes_written > 0 => {
+                    return Ok(result.bytes_written)
+                }
+                Ok(_) | Err(MZError::Buf) if eof => {
+                    return Err(Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "deflate stream ends unexpectedly",
+                    ))
+                }
+                Ok(_) | Err(MZError::Buf) => {}
+                Err(_) => return Err(Error::new(ErrorKind::InvalidData, "corrupt deflate stream")),
+            }
+        }
+    }
+}
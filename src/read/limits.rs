@@ -0,0 +1,224 @@
+//! Limits against malicious phar files
+
+use std::convert::TryFrom;
+use std::error;
+use std::fmt;
+use std::io::{self, ErrorKind, Read, Result};
+
+/// Error returned when a phar file exceeds a limit configured in `Options`.
+///
+/// This is wrapped in an `io::Error` of kind `InvalidData`,
+/// and can be retrieved with `io::Error::get_ref` and `downcast_ref`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LimitExceeded {
+    /// The manifest is larger than `max_manifest_size`.
+    ManifestSize {
+        /// The manifest size declared in the file
+        size: u32,
+        /// The configured limit
+        limit: u32,
+    },
+    /// The manifest declares more entries than `max_entries`.
+    Entries {
+        /// The number of entries declared in the file
+        count: u32,
+        /// The configured limit
+        limit: u32,
+    },
+    /// A file name or the alias is longer than `max_name_len`.
+    NameLength {
+        /// The length declared in the file
+        len: u32,
+        /// The configured limit
+        limit: u32,
+    },
+    /// The phar metadata or a file metadata is longer than `max_metadata_len`.
+    MetadataLength {
+        /// The length declared in the file
+        len: u32,
+        /// The configured limit
+        limit: u32,
+    },
+    /// The decompressed contents of an entry are larger than `max_entry_size`.
+    EntrySize {
+        /// The configured limit
+        limit: u64,
+    },
+    /// The decompressed contents of an entry are more than `max_compression_ratio` times
+    /// as large as the compressed contents.
+    CompressionRatio {
+        /// The compressed size of the entry
+        compressed: u64,
+        /// The configured limit
+        limit: u32,
+    },
+    /// A phar file compressed as a whole decompresses to more than `max_archive_size` bytes.
+    ArchiveSize {
+        /// The configured limit
+        limit: u64,
+    },
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ManifestSize { size, limit } => {
+                write!(f, "manifest size {} exceeds limit {}", size, limit)
+            }
+            Self::Entries { count, limit } => {
+                write!(f, "number of entries {} exceeds limit {}", count, limit)
+            }
+            Self::NameLength { len, limit } => {
+                write!(f, "name length {} exceeds limit {}", len, limit)
+            }
+            Self::MetadataLength { len, limit } => {
+                write!(f, "metadata length {} exceeds limit {}", len, limit)
+            }
+            Self::EntrySize { limit } => {
+                write!(f, "decompressed entry size exceeds limit {}", limit)
+            }
+            Self::CompressionRatio { compressed, limit } => write!(
+                f,
+                "decompressed entry size exceeds {} times its compressed size {}",
+                limit, compressed
+            ),
+            Self::ArchiveSize { limit } => {
+                write!(f, "decompressed archive size exceeds limit {}", limit)
+            }
+        }
+    }
+}
+
+impl error::Error for LimitExceeded {}
+
+impl From<LimitExceeded> for io::Error {
+    fn from(err: LimitExceeded) -> Self {
+        io::Error::new(ErrorKind::InvalidData, err)
+    }
+}
+
+/// Checks a length prefix against an optional limit.
+pub(crate) fn check(
+    value: u32,
+    limit: Option<u32>,
+    err: impl FnOnce(u32, u32) -> LimitExceeded,
+) -> Result<()> {
+    match limit {
+        Some(limit) if value > limit => Err(err(value, limit).into()),
+        _ => Ok(()),
+    }
+}
+
+/// The limits applied when decompressing entries.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct EntryLimits {
+    pub(crate) max_entry_size: Option<u64>,
+    pub(crate) max_compression_ratio: Option<u32>,
+}
+
+impl EntryLimits {
+    /// Returns the maximum number of decompressed bytes
+    /// and the error to return if it is exceeded.
+    fn bound(&self, compressed: bool, compressed_size: u64) -> Option<(u64, LimitExceeded)> {
+        let size = self
+            .max_entry_size
+            .map(|limit| (limit, LimitExceeded::EntrySize { limit }));
+        let ratio = self
+            .max_compression_ratio
+            .filter(|_| compressed)
+            .map(|limit| {
+                (
+                    compressed_size.saturating_mul(limit.into()),
+                    LimitExceeded::CompressionRatio {
+                        compressed: compressed_size,
+                        limit,
+                    },
+                )
+            });
+        match (size, ratio) {
+            (Some(size), Some(ratio)) => Some(if ratio.0 < size.0 { ratio } else { size }),
+            (size, ratio) => size.or(ratio),
+        }
+    }
+
+    /// Fails if `len` decompressed bytes exceed these limits,
+    /// for readers that cannot be wrapped with `wrap`.
+    pub(crate) fn check_len(&self, compressed: bool, compressed_size: u64, len: u64) -> Result<()> {
+        match self.bound(compressed, compressed_size) {
+            Some((bound, err)) if len > bound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Wraps a decompressed entry reader with these limits.
+    pub(crate) fn wrap<R>(&self, read: R, compressed: bool, compressed_size: u64) -> Bounded<R> {
+        Bounded {
+            inner: read,
+            bound: self.bound(compressed, compressed_size),
+            read: 0,
+        }
+    }
+}
+
+/// A reader that fails when more bytes than the bound are read.
+pub(crate) struct Bounded<R> {
+    inner: R,
+    bound: Option<(u64, LimitExceeded)>,
+    read: u64,
+}
+
+impl<R> Bounded<R> {
+    /// Returns the wrapped reader.
+    pub(crate) fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Returns the wrapped reader by reference.
+    pub(crate) fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Counts `read` bytes as already read,
+    /// e.g. when the wrapped reader resumes in the middle of the contents.
+    pub(crate) fn set_read(&mut self, read: u64) -> Result<()> {
+        self.read = read;
+        self.advance(0)
+    }
+
+    fn advance(&mut self, read: usize) -> Result<()> {
+        self.read += u64::try_from(read).expect("usize <= u64");
+        match &self.bound {
+            Some((bound, err)) if self.read > *bound => Err(err.clone().into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<R: Read> Read for Bounded<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.advance(read)?;
+        Ok(read)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<R: tokio::io::AsyncRead + Unpin> tokio::io::AsyncRead for Bounded<R> {
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<Result<()>> {
+        use std::task::Poll;
+
+        let before = buf.filled().len();
+        match std::pin::Pin::new(&mut self.inner).poll_read(cx, buf) {
+            Poll::Ready(Ok(())) => {
+                let read = buf.filled().len() - before;
+                Poll::Ready(self.advance(read))
+            }
+            poll => poll,
+        }
+    }
+}
@@ -2,7 +2,53 @@
 
 #[allow(clippy::module_inception)]
 mod reader;
-pub use reader::{Options, Reader};
+pub use reader::{DuplicateNames, Options, Reader};
+
+#[cfg(feature = "async")]
+mod async_reader;
+#[cfg(feature = "async")]
+#[cfg_attr(feature = "docsrs", doc(cfg(feature = "async")))]
+pub use async_reader::AsyncReader;
+
+mod slice;
+#[cfg(feature = "mmap")]
+#[cfg_attr(feature = "docsrs", doc(cfg(feature = "mmap")))]
+pub use slice::map_file;
+pub use slice::{SliceEntry, SliceReader};
+
+mod shared;
+pub use shared::{ReadAt, ReadAtCursor, SharedReader};
+
+mod archive;
+pub use archive::Decompressed;
+
+mod extract;
+
+#[cfg(feature = "writer")]
+mod source;
+
+mod handle;
+pub use handle::{EntryHandle, EntryInfo};
+
+mod seek;
+pub use seek::EntryReader;
+
+#[cfg(feature = "comp-zlib")]
+mod inflate;
+#[cfg(feature = "comp-zlib")]
+pub(crate) use inflate::{InflateCheckpoint, Inflater};
+
+mod glob;
+pub use glob::Glob;
+
+mod limits;
+pub use limits::LimitExceeded;
+
+mod trailer;
+pub use trailer::SignatureInfo;
+
+mod validate;
+pub use validate::{Diagnostic, DiagnosticKind, ValidationError};
 
 mod section;
 use section::Section;
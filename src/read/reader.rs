@@ -1,27 +1,40 @@
+use std::collections::hash_map::{self, HashMap};
 use std::io::{Error, ErrorKind, Read, Result, Seek, SeekFrom};
+use std::mem;
+use std::path::Path;
 
 use byteorder::{LittleEndian, ReadBytesExt};
 use shallow_tees::ShallowTees;
 use takes::Ext;
 use typed_builder::TypedBuilder;
 
+use super::archive::Decompressed;
+use super::extract;
+use super::handle::{self, EntryHandle};
+use super::limits::{self, EntryLimits, LimitExceeded};
+use super::trailer::{self, parse_signature_trailer, SignatureInfo};
 use super::util::read_find_bstr;
-use super::{index, Entry, FileIndex, Section};
-use crate::signature::{self, Signature};
-use crate::util::{tell, PHAR_TERMINATOR, STUB_TERMINATOR};
+use super::validate::{self, Diagnostic};
+use super::{index, Entry, FileIndex, Glob, Section};
+use crate::progress::Observer;
+use crate::signature;
+use crate::util::{tell, STUB_TERMINATOR};
+use crate::{ApiVersion, ArchiveCompression, GlobalFlags};
 
 /// The metadata of a phar file.
 #[derive(Debug)]
 #[cfg_attr(feature = "docsrs", doc(cfg(feature = "reader")))]
 pub struct Reader<R: Read + Seek, FileIndexT: FileIndex = index::NameHashMap> {
-    stream: R,
-    stub: Section,
+    pub(super) stream: R,
+    pub(super) stub: Section,
     num_files: u32,
-    api: u16,
+    api: ApiVersion,
     flags: u32,
-    alias: Section,
-    metadata: Section,
-    file_index: FileIndexT,
+    pub(super) alias: Section,
+    pub(super) metadata: Section,
+    pub(super) file_index: FileIndexT,
+    pub(super) limits: EntryLimits,
+    signature: Option<SignatureInfo>,
 }
 
 impl<R: Read + Seek, FileIndexT: FileIndex> Reader<R, FileIndexT> {
@@ -30,32 +43,25 @@ impl<R: Read + Seek, FileIndexT: FileIndex> Reader<R, FileIndexT> {
     /// This optionally validates the signature.
     /// Stub, metadata and file metadata are not fully validated,
     /// and may not be saved in memory depending on `options`.
+    /// If `options.strict` is set, the file is also checked with `validate`.
     pub fn read(mut read: R, options: Options) -> Result<Self> {
         let mut expected_sig = None;
-        let mut sig_offset = None;
 
         let mut sig = if options.verify_signature {
-            let _ = read.seek(SeekFrom::End(-4))?;
-            let mut gbmb = [0u8; 4];
-            read.read_exact(&mut gbmb[..])?;
-            if gbmb != PHAR_TERMINATOR {
-                return Err(Error::new(ErrorKind::Other, "corrupted file"));
-            }
-
             let _ = read.seek(SeekFrom::End(-8))?;
-            let discrim = read.read_u32::<LittleEndian>()?;
-            let sig = Signature::from_u32(discrim).ok_or_else(|| {
-                Error::new(
-                    ErrorKind::Other,
-                    format!("unsupported signature type {:x}", discrim),
-                )
-            })?;
-
-            let mut expect = vec![0u8; sig.size().into()];
+            let mut trailer = [0u8; 8];
+            read.read_exact(&mut trailer[..])?;
+            let sig = parse_signature_trailer(trailer)?;
+
+            let mut digest = vec![0u8; sig.size().into()];
             let offset = read.seek(SeekFrom::End(-8i64 - i64::from(sig.size())))?;
-            sig_offset = Some(offset);
-            read.read_exact(&mut expect[..])?;
-            expected_sig = Some(expect);
+            read.read_exact(&mut digest[..])?;
+            expected_sig = Some(SignatureInfo {
+                algorithm: sig.algorithm(),
+                digest,
+                offset,
+                verified: false,
+            });
 
             signature::MaybeDummy::Real(sig)
         } else {
@@ -67,54 +73,45 @@ impl<R: Read + Seek, FileIndexT: FileIndex> Reader<R, FileIndexT> {
         let mut tee = ShallowTees::new(&mut read, sig.write());
 
         let mut stub = Section::create(options.cache_stub, 0);
-        read_find_bstr(&mut tee, &mut stub, STUB_TERMINATOR)?;
+        // do not let the scanner feed the signature itself into the digest
+        let scan_limit = expected_sig.as_ref().map_or(u64::MAX, |info| info.offset);
+        read_find_bstr(&mut tee, &mut stub, STUB_TERMINATOR, scan_limit)?;
 
         let manifest_size = tee.read_u32::<LittleEndian>()?;
+        options.check_manifest_size(manifest_size)?;
         let mut manifest = (&mut tee).takes(manifest_size.into())?;
 
-        let num_files = manifest.read_u32::<LittleEndian>()?;
-        let api = manifest.read_u16::<LittleEndian>()?;
-        let flags = manifest.read_u32::<LittleEndian>()?;
-
-        let alias_len = manifest.read_u32::<LittleEndian>()?;
-        let mut alias = Section::create(options.cache_alias, tell(&mut manifest)?);
-        alias.from_read(&mut manifest, alias_len)?;
-
-        let metadata_len = manifest.read_u32::<LittleEndian>()?;
-        let mut metadata = Section::create(options.cache_metadata, tell(&mut manifest)?);
-        metadata.from_read(&mut manifest, metadata_len)?;
-
-        let mut file_index = FileIndexT::default();
-        if FileIndexT::scan_files() {
-            for _ in 0..num_files {
-                let start = tell(&mut manifest)?;
-                let entry = Entry::parse(
-                    &mut manifest,
-                    FileIndexT::requires_name(),
-                    FileIndexT::requires_metadata(),
-                )?;
-                file_index.feed_entry(start, entry)?;
-            }
-        }
-        file_index.end_of_header(tell(&mut manifest)?);
+        let Manifest {
+            num_files,
+            api,
+            flags,
+            alias,
+            metadata,
+            file_index,
+        } = Manifest::parse(&mut manifest, &options)?;
 
-        if let (Some(expected_sig), Some(sig_offset)) = (expected_sig, sig_offset) {
-            let _ = tee.seek(SeekFrom::Start(sig_offset))?;
+        let signature = if let Some(mut expected_sig) = expected_sig {
+            let _ = tee.seek(SeekFrom::Start(expected_sig.offset))?;
             drop(tee);
             let sig = match sig {
                 signature::MaybeDummy::Real(sig) => sig,
                 signature::MaybeDummy::Dummy(_) => {
-                    unreachable!("expected_sig, sig_offset should be None")
+                    unreachable!("expected_sig should be None")
                 }
             };
             let ret = sig.finalize();
 
-            if ret[..] != expected_sig[..] {
+            if ret[..] != expected_sig.digest[..] {
                 return Err(Error::new(ErrorKind::Other, "signature mismatch"));
             }
-        }
+            expected_sig.verified = true;
+            Some(expected_sig)
+        } else {
+            drop(tee);
+            trailer::read_unverified(&mut read)?
+        };
 
-        Ok(Reader {
+        let mut reader = Reader {
             stream: read,
             stub,
             num_files,
@@ -123,7 +120,63 @@ impl<R: Read + Seek, FileIndexT: FileIndex> Reader<R, FileIndexT> {
             alias,
             metadata,
             file_index,
-        })
+            limits: options.entry_limits(),
+            signature,
+        };
+        if options.strict {
+            validate::strict(reader.validate()?)?;
+        }
+        Ok(reader)
+    }
+
+    /// Checks the phar file for structural inconsistencies.
+    ///
+    /// This parses the manifest again and checks that
+    /// the manifest size matches the parsed manifest,
+    /// the API version is supported,
+    /// the global flags agree with the entries and the signature,
+    /// all entry names are normalized so that PHP can access them,
+    /// and the file contents end right before the signature.
+    ///
+    /// Returns an empty list if no inconsistencies are found.
+    /// Errors are only returned if the file cannot be read at all.
+    pub fn validate(&mut self) -> Result<Vec<Diagnostic>> {
+        validate::validate(&mut self.stream, self.stub.len())
+    }
+
+    /// Returns the number of file entries declared in the manifest.
+    pub fn entry_count(&self) -> u32 {
+        self.num_files
+    }
+
+    /// Returns the manifest API version.
+    pub fn api_version(&self) -> ApiVersion {
+        self.api
+    }
+
+    /// Returns the global flags.
+    pub fn global_flags(&self) -> GlobalFlags {
+        GlobalFlags::from_bits_truncate(self.flags)
+    }
+
+    /// Returns the signature of the phar file.
+    ///
+    /// Returns `None` if the file does not end with a valid signature trailer.
+    /// The signature is only verified if `verify_signature` is set.
+    pub fn signature(&self) -> Option<&SignatureInfo> {
+        self.signature.as_ref()
+    }
+
+    /// Returns the file index.
+    ///
+    /// For example, use `index::Tree::read_dir` to list directories.
+    pub fn index(&self) -> &FileIndexT {
+        &self.file_index
+    }
+
+    /// Returns the underlying stream.
+    pub(super) fn get_ref(&self) -> &R {
+        &self.stream
     }
 
     /// Returns the stub as a slice.
@@ -140,6 +193,18 @@ impl<R: Read + Seek, FileIndexT: FileIndex> Reader<R, FileIndexT> {
         self.stub.as_read(&mut self.stream)
     }
 
+    /// Returns the alias as a slice.
+    ///
+    /// If the alias was previously not stored in memory, it is stored in a new Vec.
+    pub fn alias_bytes(&mut self) -> Result<impl AsRef<[u8]> + '_> {
+        self.alias.as_memory(&mut self.stream)
+    }
+
+    /// Returns the alias as an `io::Read`.
+    pub fn alias_read(&mut self) -> Result<impl Read + '_> {
+        self.alias.as_read(&mut self.stream)
+    }
+
     /// Returns the metadata as a slice.
     ///
     /// If the metadata was previously not stored in memory, it is stored in a new Vec.
@@ -155,13 +220,33 @@ impl<R: Read + Seek, FileIndexT: FileIndex> Reader<R, FileIndexT> {
     }
 }
 
+impl<R: Read + Seek, FileIndexT: FileIndex> Reader<Decompressed<R>, FileIndexT> {
+    /// Parses a phar file that may be compressed as a whole,
+    /// such as `.phar.gz` and `.phar.bz2` files.
+    ///
+    /// gzip and bzip2 files are detected from their magic bytes
+    /// and decompressed as described in `Decompressed`;
+    /// other files are read as in `read`.
+    pub fn open(read: R, options: Options) -> Result<Self> {
+        let decompressed = Decompressed::new(read, &options)?;
+        Self::read(decompressed, options)
+    }
+
+    /// Returns the compression of the whole file detected by `open`.
+    pub fn archive_compression(&self) -> ArchiveCompression {
+        self.stream.compression()
+    }
+}
+
 impl<R: Read + Seek, FileIndexT: index::Iterable> Reader<R, FileIndexT> {
     /// Iterates over the files in this archive.
+    ///
+    /// Directory entries are skipped.
     pub fn for_each_file<F>(&mut self, f: F) -> Result<()>
     where
         F: FnMut(&[u8], &mut (dyn Read)) -> Result<()>,
     {
-        self.file_index.for_each_file(&mut self.stream, f)
+        self.for_each_file_fold(f, |_, ()| ()).map(|_| ())
     }
 
     /// Iterates over the files in this archive and fold return values.
@@ -170,13 +255,295 @@ impl<R: Read + Seek, FileIndexT: index::Iterable> Reader<R, FileIndexT> {
         F: FnMut(&[u8], &mut (dyn Read)) -> Result<U>,
         G: FnMut(Option<T>, U) -> T,
     {
-        self.file_index
-            .for_each_file_fold(&mut self.stream, f, fold)
+        index::fold_files(&self.file_index, &mut self.stream, f, fold, &self.limits)
+    }
+
+    /// Iterates over all entries in this archive, including directories,
+    /// without reading their contents.
+    ///
+    /// Contents are only read if `f` calls `EntryHandle::open`.
+    pub fn for_each_entry<F>(&mut self, f: F) -> Result<()>
+    where
+        F: FnMut(EntryHandle<'_, R>) -> Result<()>,
+    {
+        handle::for_each_matching(
+            &self.file_index,
+            &mut self.stream,
+            self.limits,
+            b"",
+            |_| true,
+            f,
+        )
+    }
+
+    /// Returns the names of all entries in this archive, including directories.
+    ///
+    /// This reads names from the stream if the index does not cache them,
+    /// but never reads file contents.
+    pub fn names(&mut self) -> Result<Vec<Vec<u8>>> {
+        let mut names = Vec::new();
+        self.for_each_entry(|entry| {
+            names.push(entry.name().to_vec());
+            Ok(())
+        })?;
+        Ok(names)
+    }
+
+    /// Iterates over the files whose names start with `prefix`,
+    /// e.g. `b"src/"` for all files under the `src` directory.
+    ///
+    /// Directory entries are skipped.
+    /// File contents are only read if `f` calls `EntryHandle::open`.
+    /// Indices sorted by name, such as `NameBTreeMap`, `MetadataBTreeMap` and `Tree`,
+    /// only visit the matching entries;
+    /// other indices scan all entries.
+    pub fn for_each_prefix<F>(&mut self, prefix: &[u8], f: F) -> Result<()>
+    where
+        F: FnMut(EntryHandle<'_, R>) -> Result<()>,
+    {
+        handle::for_each_matching(
+            &self.file_index,
+            &mut self.stream,
+            self.limits,
+            prefix,
+            |name| !index::is_dir_name(name),
+            f,
+        )
+    }
+
+    /// Iterates over the files whose names match `glob`.
+    ///
+    /// This behaves like `for_each_prefix`,
+    /// using the literal prefix of the pattern to narrow down the entries visited.
+    pub fn for_each_glob<F>(&mut self, glob: &Glob, f: F) -> Result<()>
+    where
+        F: FnMut(EntryHandle<'_, R>) -> Result<()>,
+    {
+        handle::for_each_matching(
+            &self.file_index,
+            &mut self.stream,
+            self.limits,
+            &glob.literal_prefix(),
+            |name| !index::is_dir_name(name) && glob.is_match(name),
+            f,
+        )
+    }
+
+    /// Verifies the decompressed contents of all files against the CRC32 checksums in the manifest.
+    ///
+    /// Files are checked in manifest order,
+    /// and an `InvalidData` error is returned for the first mismatch.
+    pub fn verify_crc32(&mut self) -> Result<()> {
+        let jobs = extract::jobs(&self.file_index, &mut self.stream)?;
+        for job in &jobs {
+            extract::verify(&mut job.handle(&mut self.stream, self.limits))?;
+        }
+        Ok(())
+    }
+
+    /// Extracts all entries into `dir`, creating it if it does not exist.
+    ///
+    /// Entry names are normalized with `path::normalize`,
+    /// and entries that cannot be normalized fail with an `InvalidData` error,
+    /// so no files are written outside `dir`.
+    /// Existing files are overwritten.
+    /// Files are written in manifest order and their CRC32 checksums are verified;
+    /// a file whose checksum mismatches is left written before the error is returned.
+    pub fn extract_to(&mut self, dir: impl AsRef<Path>) -> Result<()> {
+        self.extract_to_with(dir, ())
+    }
+
+    /// Like `extract_to`, but reports the progress of extraction to `observer`.
+    ///
+    /// For each file, `EntryEvent::bytes_read` is the size stored in the archive
+    /// and `EntryEvent::bytes_written` is the size of the extracted file.
+    pub fn extract_to_with(
+        &mut self,
+        dir: impl AsRef<Path>,
+        observer: impl Observer,
+    ) -> Result<()> {
+        let jobs = extract::jobs(&self.file_index, &mut self.stream)?;
+        extract::extract_all(&jobs, &mut self.stream, self.limits, dir.as_ref(), observer)
     }
 }
 
+/// The fields in the phar manifest after the manifest size.
+///
+/// This is shared by all reader implementations,
+/// which pass a stream that reports offsets relative to the start of the phar file.
+pub(super) struct Manifest<FileIndexT: FileIndex> {
+    pub(super) num_files: u32,
+    pub(super) api: ApiVersion,
+    pub(super) flags: u32,
+    pub(super) alias: Section,
+    pub(super) metadata: Section,
+    pub(super) file_index: FileIndexT,
+}
+
+impl<FileIndexT: FileIndex> Manifest<FileIndexT> {
+    pub(super) fn parse(manifest: &mut (impl Read + Seek), options: &Options) -> Result<Self> {
+        let num_files = manifest.read_u32::<LittleEndian>()?;
+        options.check_entries(num_files)?;
+        let mut api = [0u8; 2];
+        manifest.read_exact(&mut api[..])?;
+        let api = ApiVersion::from_bytes(api);
+        let flags = manifest.read_u32::<LittleEndian>()?;
+
+        let alias_len = manifest.read_u32::<LittleEndian>()?;
+        options.check_name_len(alias_len)?;
+        let mut alias = Section::create(options.cache_alias, tell(&mut *manifest)?);
+        alias.from_read(manifest, alias_len)?;
+
+        let metadata_len = manifest.read_u32::<LittleEndian>()?;
+        options.check_metadata_len(metadata_len)?;
+        let mut metadata = Section::create(options.cache_metadata, tell(&mut *manifest)?);
+        metadata.from_read(manifest, metadata_len)?;
+
+        let mut file_index = FileIndexT::default();
+        if FileIndexT::scan_files() {
+            let mut entries = Entries::new(options.duplicate_names.unwrap_or(
+                if FileIndexT::requires_name() {
+                    DuplicateNames::KeepLast
+                } else {
+                    DuplicateNames::KeepAll
+                },
+            ));
+            let mut contents_start = 0;
+            for position in 0..num_files {
+                let start = tell(&mut *manifest)?;
+                let entry = Entry::parse(
+                    manifest,
+                    FileIndexT::requires_name() || entries.requires_name(),
+                    FileIndexT::requires_metadata(),
+                    options,
+                )?;
+                let entry = Entry {
+                    position,
+                    contents_start,
+                    ..entry
+                };
+                contents_start = entry.contents_range().end;
+                entries.push(start, entry, FileIndexT::requires_name())?;
+            }
+            for (start, entry) in entries.into_iter() {
+                file_index.feed_entry(start, entry)?;
+            }
+        }
+        file_index.end_of_header(tell(&mut *manifest)?);
+
+        Ok(Self {
+            num_files,
+            api,
+            flags,
+            alias,
+            metadata,
+            file_index,
+        })
+    }
+}
+
+/// How to handle multiple entries with the same name.
+///
+/// PHP resolves duplicate names differently from other tools,
+/// so duplicates can be used to hide files from reviewers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateNames {
+    /// Fail with an `InvalidData` error.
+    Error,
+    /// Only index the first entry with each name.
+    KeepFirst,
+    /// Only index the last entry with each name.
+    KeepLast,
+    /// Index all entries.
+    ///
+    /// Indices keyed by name still keep only one entry for each name,
+    /// but `OffsetOnly` iterates over all of them.
+    /// This does not need to keep all names in memory while parsing.
+    KeepAll,
+}
+
+/// Collects the entries parsed from the manifest, applying `DuplicateNames`.
+struct Entries {
+    policy: DuplicateNames,
+    /// The entries to be fed to the index with their manifest offsets,
+    /// where `None` indicates an entry superseded by a later duplicate
+    entries: Vec<Option<(u64, Entry)>>,
+    /// Maps each name to its index in `entries`
+    names: HashMap<Vec<u8>, usize>,
+}
+
+impl Entries {
+    fn new(policy: DuplicateNames) -> Self {
+        Self {
+            policy,
+            entries: Vec::new(),
+            names: HashMap::new(),
+        }
+    }
+
+    /// Whether names must be read into memory to detect duplicates.
+    fn requires_name(&self) -> bool {
+        self.policy != DuplicateNames::KeepAll
+    }
+
+    /// Adds an entry that starts at `start` in the manifest.
+    ///
+    /// If the index does not require names, `entry.name` is restored to an offset section.
+    fn push(&mut self, start: u64, mut entry: Entry, index_requires_name: bool) -> Result<()> {
+        if !self.requires_name() {
+            self.entries.push(Some((start, entry)));
+            return Ok(());
+        }
+
+        let name = match &entry.name {
+            Section::Cached(name) => name.clone(),
+            Section::Offset(..) => unreachable!("requires_name is set to true"),
+        };
+        if !index_requires_name {
+            // the name follows the 4-byte name length
+            let name_start = start + 4;
+            entry.name = Section::Offset(name_start, name_start + entry.name.len());
+        }
+
+        let index = self.entries.len();
+        match self.names.entry(name) {
+            hash_map::Entry::Vacant(vacant) => {
+                let _ = vacant.insert(index);
+            }
+            hash_map::Entry::Occupied(occupied) => match self.policy {
+                DuplicateNames::Error => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!(
+                            "duplicate entry name {:?}",
+                            String::from_utf8_lossy(occupied.key())
+                        ),
+                    ));
+                }
+                DuplicateNames::KeepFirst => return Ok(()),
+                DuplicateNames::KeepLast => {
+                    let prev = mem::replace(occupied.into_mut(), index);
+                    if let Some(superseded) = self.entries.get_mut(prev) {
+                        *superseded = None;
+                    }
+                }
+                DuplicateNames::KeepAll => unreachable!("names are not tracked"),
+            },
+        }
+        self.entries.push(Some((start, entry)));
+        Ok(())
+    }
+
+    fn into_iter(self) -> impl Iterator<Item = (u64, Entry)> {
+        self.entries.into_iter().flatten()
+    }
+}
+
+/// The default of `Options::max_archive_size`
+const DEFAULT_MAX_ARCHIVE_SIZE: u64 = 1 << 30;
+
 /// Options for reading phar archives
-#[derive(Default, TypedBuilder)]
+#[derive(TypedBuilder)]
 pub struct Options {
     /// Whether to cache the phar stub in memory
     ///
@@ -185,7 +552,7 @@ pub struct Options {
     /// when it is queried by the user.
     /// False is only recommended when stub is not going to be used.
     #[builder(default = true)]
-    cache_stub: bool,
+    pub(super) cache_stub: bool,
     /// Whether to cache the phar alias in memory
     ///
     /// Default true.
@@ -193,7 +560,7 @@ pub struct Options {
     /// when it is queried by the user.
     /// False is only recommended when stub is not going to be used.
     #[builder(default = true)]
-    cache_alias: bool,
+    pub(super) cache_alias: bool,
     /// Whether to cache the phar metadata string in memory
     ///
     /// Default true.
@@ -201,7 +568,7 @@ pub struct Options {
     /// when it is queried by the user.
     /// False is only recommended when stub is not going to be used.
     #[builder(default = true)]
-    cache_metadata: bool,
+    pub(super) cache_metadata: bool,
 
     /// Whether to verify the phar signature.
     ///
@@ -211,5 +578,134 @@ pub struct Options {
     /// When false, unused bytes would be skipped (with `fseek(3)`)
     /// instead of being read into buffer.
     #[builder(default = true)]
-    verify_signature: bool,
+    pub(super) verify_signature: bool,
+
+    /// Whether to reject files with structural inconsistencies.
+    ///
+    /// Default false.
+    /// When true, the file is checked with `Reader::validate` after parsing,
+    /// and a `ValidationError` listing all inconsistencies is returned if any is found.
+    #[builder(default)]
+    pub(super) strict: bool,
+
+    /// How to handle multiple entries with the same name.
+    ///
+    /// Default `DuplicateNames::KeepLast` for indices that cache names,
+    /// which keeps the later entry like earlier versions of this library,
+    /// and `DuplicateNames::KeepAll` for other indices, i.e. `OffsetOnly`,
+    /// so that they do not need to keep names in memory.
+    /// If this is set to anything other than `KeepAll`,
+    /// all entry names are kept in memory while parsing the manifest,
+    /// even if the index does not cache names.
+    /// Use `Error` to reject archives that may hide files behind duplicate names.
+    #[builder(default, setter(strip_option))]
+    pub(super) duplicate_names: Option<DuplicateNames>,
+
+    /// The maximum size of the manifest in bytes.
+    ///
+    /// Default unlimited.
+    /// The manifest contains the alias, the phar metadata and all file entry headers,
+    /// and it is buffered in memory by `AsyncReader`.
+    #[builder(default, setter(strip_option))]
+    pub(super) max_manifest_size: Option<u32>,
+    /// The maximum number of file entries.
+    ///
+    /// Default unlimited.
+    /// File indices allocate memory for each entry.
+    #[builder(default, setter(strip_option))]
+    pub(super) max_entries: Option<u32>,
+    /// The maximum length of each file name and the alias.
+    ///
+    /// Default unlimited.
+    #[builder(default, setter(strip_option))]
+    pub(super) max_name_len: Option<u32>,
+    /// The maximum length of the phar metadata and each file metadata.
+    ///
+    /// Default unlimited.
+    #[builder(default, setter(strip_option))]
+    pub(super) max_metadata_len: Option<u32>,
+    /// The maximum decompressed size of each file entry.
+    ///
+    /// Default unlimited.
+    /// This is checked while the entry is being decompressed,
+    /// so the entry reader fails after reading the first bytes over the limit.
+    #[builder(default, setter(strip_option))]
+    pub(super) max_entry_size: Option<u64>,
+    /// The maximum ratio of the decompressed size to the compressed size
+    /// of each compressed file entry.
+    ///
+    /// Default unlimited.
+    /// This protects against decompression bombs,
+    /// and is checked in the same way as `max_entry_size`.
+    #[builder(default, setter(strip_option))]
+    pub(super) max_compression_ratio: Option<u32>,
+    /// The maximum decompressed size of a phar file compressed as a whole,
+    /// when opened with `Reader::open`.
+    ///
+    /// Default 1 GiB.
+    /// Unlike the limits on the manifest and the entries, which are unlimited by default,
+    /// this has a finite default because the whole decompressed file is buffered,
+    /// in memory unless `spill_threshold` is set,
+    /// so a small compressed file could otherwise exhaust memory.
+    /// Set it to `u64::MAX` to accept compressed files of any size.
+    #[builder(default = Some(DEFAULT_MAX_ARCHIVE_SIZE), setter(strip_option))]
+    pub(super) max_archive_size: Option<u64>,
+    /// The decompressed size above which a phar file compressed as a whole
+    /// is decompressed into a temporary file instead of memory,
+    /// when opened with `Reader::open`.
+    ///
+    /// Default unlimited, i.e. always decompressed into memory.
+    /// This is ignored without the `tempfile` feature.
+    #[builder(default, setter(strip_option))]
+    pub(super) spill_threshold: Option<u64>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl Options {
+    pub(super) fn check_manifest_size(&self, size: u32) -> Result<()> {
+        limits::check(size, self.max_manifest_size, |size, limit| {
+            LimitExceeded::ManifestSize { size, limit }
+        })
+    }
+
+    pub(super) fn check_entries(&self, count: u32) -> Result<()> {
+        limits::check(count, self.max_entries, |count, limit| {
+            LimitExceeded::Entries { count, limit }
+        })
+    }
+
+    pub(super) fn check_name_len(&self, len: u32) -> Result<()> {
+        limits::check(len, self.max_name_len, |len, limit| {
+            LimitExceeded::NameLength { len, limit }
+        })
+    }
+
+    pub(super) fn check_metadata_len(&self, len: u32) -> Result<()> {
+        limits::check(len, self.max_metadata_len, |len, limit| {
+            LimitExceeded::MetadataLength { len, limit }
+        })
+    }
+
+    pub(super) fn entry_limits(&self) -> EntryLimits {
+        EntryLimits {
+            max_entry_size: self.max_entry_size,
+            max_compression_ratio: self.max_compression_ratio,
+        }
+    }
+
+    /// Disables caching of all sections,
+    /// used when the source is already in memory.
+    pub(super) fn without_cache(self) -> Self {
+        Self {
+            cache_stub: false,
+            cache_alias: false,
+            cache_metadata: false,
+            ..self
+        }
+    }
 }
@@ -1,6 +1,7 @@
 use std::borrow::Cow;
 use std::convert::{TryFrom, TryInto};
-use std::io::{self, Read, Result, Seek, SeekFrom, Write};
+use std::io::{self, Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
+use std::ops::Range;
 
 #[derive(Debug, Clone)]
 pub enum Section {
@@ -62,30 +63,74 @@ impl Section {
         Ok(())
     }
 
+    pub fn as_ref(&self) -> SectionRef<'_> {
+        match self {
+            Self::Cached(vec) => SectionRef::Cached(&vec[..]),
+            Self::Offset(start, end) => SectionRef::Offset(*start, *end),
+        }
+    }
+
     pub fn as_memory<'t>(&'t self, read: &mut (impl Read + Seek)) -> Result<impl AsRef<[u8]> + 't> {
+        self.as_ref().as_memory(read)
+    }
+
+    #[auto_enums::auto_enum(Transpose, Read)]
+    pub fn as_read<'t>(&'t self, read: &'t mut (impl Read + Seek)) -> Result<impl Read + 't> {
+        match self {
+            Self::Cached(vec) => Ok(io::Cursor::new(&vec[..])),
+            Self::Offset(start, end) => {
+                let _ = read.seek(SeekFrom::Start(*start))?;
+                Ok(read.take(*end - *start))
+            }
+        }
+        .transpose_ok()
+    }
+}
+
+/// A borrowed `Section`.
+#[derive(Debug, Clone, Copy)]
+pub enum SectionRef<'t> {
+    Cached(&'t [u8]),
+    Offset(u64, u64),
+}
+
+impl<'t> SectionRef<'t> {
+    pub fn as_memory(self, read: &mut (impl Read + Seek)) -> Result<Cow<'t, [u8]>> {
         Ok(match self {
-            Self::Cached(vec) => Cow::Borrowed(&vec[..]),
+            Self::Cached(slice) => Cow::Borrowed(slice),
             Self::Offset(start, end) => {
                 // overflow is impossible for filenames because they are u32
                 let size = (end - start)
                     .try_into()
                     .expect("section is too large to fit in memory");
                 let mut vec = Vec::with_capacity(size);
-                self.copy_value(read, &mut vec)?;
+                let _ = read.seek(SeekFrom::Start(start))?;
+                let _ = io::copy(&mut read.take(end - start), &mut vec)?;
                 Cow::Owned(vec)
             }
         })
     }
 
-    #[auto_enums::auto_enum(Transpose, Read)]
-    pub fn as_read<'t>(&'t self, read: &'t mut (impl Read + Seek)) -> Result<impl Read + 't> {
+    /// Returns the section as a subslice of `data`,
+    /// where `data` is the whole phar file.
+    pub fn as_slice<'u>(self, data: &'u [u8]) -> Result<&'u [u8]>
+    where
+        't: 'u,
+    {
         match self {
-            Self::Cached(vec) => Ok(io::Cursor::new(&vec[..])),
-            Self::Offset(start, end) => {
-                let _ = read.seek(SeekFrom::Start(*start))?;
-                Ok(read.take(*end - *start))
-            }
+            Self::Cached(slice) => Ok(slice),
+            Self::Offset(start, end) => slice_range(data, start..end),
         }
-        .transpose_ok()
     }
 }
+
+/// Returns `data[range]`, or an `UnexpectedEof` error if it is out of bounds.
+pub fn slice_range(data: &[u8], range: Range<u64>) -> Result<&[u8]> {
+    let start = usize::try_from(range.start);
+    let end = usize::try_from(range.end);
+    match (start, end) {
+        (Ok(start), Ok(end)) => data.get(start..end),
+        _ => None,
+    }
+    .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "section exceeds end of file"))
+}
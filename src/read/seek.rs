@@ -0,0 +1,328 @@
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind, Read, Result, Seek, SeekFrom, Take};
+
+use super::limits::{Bounded, EntryLimits};
+use crate::compression::{Decoder, DecoderCheckpoint};
+use crate::CompressionMethod;
+
+/// A seekable reader over the decompressed contents of an entry.
+///
+/// Uncompressed entries are read directly from the underlying stream,
+/// so seeking is cheap.
+/// Compressed entries cannot be decompressed from an arbitrary offset,
+/// so seeking backwards restarts decompression from the start of the entry
+/// and skips the bytes before the target.
+/// Use `with_cache_size` to keep the most recently decompressed bytes in memory,
+/// so that short backward seeks do not restart decompression,
+/// and `with_checkpoint_interval` to periodically save the decompressor state of zlib entries,
+/// so that other seeks resume from the nearest checkpoint.
+///
+/// `SeekFrom::End` on compressed entries is relative to the original size declared in the manifest.
+/// The decompression limits in `Options` are applied as in `EntryHandle::open`.
+pub struct EntryReader<S: Read + Seek> {
+    state: State<S>,
+    start: u64,
+    compressed_size: u64,
+    original_size: u64,
+    limits: EntryLimits,
+    /// The position in the decompressed contents
+    pos: u64,
+}
+
+enum State<S: Read + Seek> {
+    Raw(Take<S>),
+    Compressed {
+        compression: CompressionMethod,
+        /// This is only `None` while decompression is being restarted.
+        decoder: Option<Bounded<Decoder<Take<S>>>>,
+        /// The number of bytes decompressed since the last restart
+        decoded: u64,
+        /// The last decompressed bytes, ending at `decoded`
+        cache: VecDeque<u8>,
+        cache_size: usize,
+        /// Saved decompressor states with their positions in the decompressed contents,
+        /// in increasing order of position
+        checkpoints: Vec<(u64, DecoderCheckpoint)>,
+        /// The number of decompressed bytes between checkpoints, or 0 to disable checkpoints
+        checkpoint_interval: u64,
+    },
+}
+
+impl<S: Read + Seek> EntryReader<S> {
+    /// Creates a reader over the `compressed_size` bytes starting at `start` in `stream`.
+    pub(super) fn new(
+        mut stream: S,
+        compression: CompressionMethod,
+        start: u64,
+        compressed_size: u64,
+        original_size: u64,
+        limits: EntryLimits,
+    ) -> Result<Self> {
+        let _ = stream.seek(SeekFrom::Start(start))?;
+        let raw = stream.take(compressed_size);
+        let state = match compression {
+            CompressionMethod::None => State::Raw(raw),
+            _ => State::Compressed {
+                compression,
+                decoder: Some(limits.wrap(compression.make_decoder(raw)?, true, compressed_size)),
+                decoded: 0,
+                cache: VecDeque::new(),
+                cache_size: 0,
+                checkpoints: Vec::new(),
+                checkpoint_interval: 0,
+            },
+        };
+        Ok(Self {
+            state,
+            start,
+            compressed_size,
+            original_size,
+            limits,
+            pos: 0,
+        })
+    }
+
+    /// Keeps the last `size` decompressed bytes in memory,
+    /// so that seeking back by up to `size` bytes does not restart decompression.
+    ///
+    /// This has no effect on uncompressed entries.
+    pub fn with_cache_size(mut self, size: usize) -> Self {
+        if let State::Compressed { cache_size, .. } = &mut self.state {
+            *cache_size = size;
+        }
+        self
+    }
+
+    /// Saves the decompressor state every `interval` decompressed bytes,
+    /// so that seeking resumes from the last checkpoint before the target
+    /// instead of restarting decompression from the start of the entry.
+    ///
+    /// Each checkpoint keeps about 45 KiB in memory, including the 32 KiB deflate window,
+    /// so `interval` should be much larger than that.
+    /// Checkpoints are only saved the first time each position is decompressed.
+    ///
+    /// This has no effect on uncompressed entries.
+    /// The bzip2 decompressor state cannot be saved,
+    /// so bzip2 entries always restart from the start of the entry.
+    pub fn with_checkpoint_interval(mut self, interval: u64) -> Self {
+        if let State::Compressed {
+            checkpoint_interval,
+            ..
+        } = &mut self.state
+        {
+            *checkpoint_interval = interval;
+        }
+        self
+    }
+
+    /// Returns the size of the decompressed contents.
+    ///
+    /// For compressed entries, this is the original size declared in the manifest.
+    pub fn len(&self) -> u64 {
+        match self.state {
+            State::Raw(_) => self.compressed_size,
+            State::Compressed { .. } => self.original_size,
+        }
+    }
+
+    /// Whether the decompressed contents are empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Restarts decompression from the last checkpoint at or before `target`,
+    /// or from the start of the entry if there is none.
+    fn restart(&mut self, target: u64) -> Result<()> {
+        let limits = self.limits;
+        if let State::Compressed {
+            compression,
+            decoder,
+            decoded,
+            cache,
+            checkpoints,
+            ..
+        } = &mut self.state
+        {
+            let checkpoint = checkpoints.iter().rev().find(|(at, _)| *at <= target);
+            let (at, consumed) =
+                checkpoint.map_or((0, 0), |(at, checkpoint)| (*at, checkpoint.consumed()));
+
+            let mut raw = decoder
+                .take()
+                .expect("decoder is only taken during restart")
+                .into_inner()
+                .into_inner();
+            let _ = raw.get_mut().seek(SeekFrom::Start(self.start + consumed))?;
+            raw.set_limit(self.compressed_size - consumed);
+            let inner = match checkpoint {
+                Some((_, checkpoint)) => Decoder::resume(raw, checkpoint),
+                None => compression.make_decoder(raw)?,
+            };
+            let mut bounded = limits.wrap(inner, true, self.compressed_size);
+            bounded.set_read(at)?;
+            *decoder = Some(bounded);
+            *decoded = at;
+            cache.clear();
+        }
+        Ok(())
+    }
+}
+
+impl<S: Read + Seek> Read for EntryReader<S> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        match &mut self.state {
+            State::Raw(raw) => {
+                let read = raw.read(buf)?;
+                self.pos += u64::try_from(read).expect("usize <= u64");
+                self.limits
+                    .check_len(false, self.compressed_size, self.pos)?;
+                Ok(read)
+            }
+            State::Compressed {
+                decoder,
+                decoded,
+                cache,
+                cache_size,
+                checkpoints,
+                checkpoint_interval,
+                ..
+            } => {
+                let decoder = decoder
+                    .as_mut()
+                    .expect("decoder is only taken during restart");
+
+                // skip to the current position after seeking forwards
+                let mut scratch = [0u8; 4096];
+                while *decoded < self.pos {
+                    let want = usize::try_from(self.pos - *decoded)
+                        .map_or(scratch.len(), |want| want.min(scratch.len()));
+                    let skipped = decoder.read(scratch.get_mut(..want).expect("want <= len"))?;
+                    if skipped == 0 {
+                        return Ok(0);
+                    }
+                    let skipped = scratch.get(..skipped).expect("read <= want");
+                    push_cache(cache, *cache_size, skipped);
+                    *decoded += u64::try_from(skipped.len()).expect("usize <= u64");
+                    save_checkpoint(decoder, *decoded, checkpoints, *checkpoint_interval);
+                }
+
+                let read = if *decoded > self.pos {
+                    // serve from the cache after seeking backwards
+                    let behind = usize::try_from(*decoded - self.pos)
+                        .expect("seek only goes back within the cache");
+                    let offset = cache.len() - behind;
+                    let mut read = 0;
+                    for (dest, src) in buf.iter_mut().zip(cache.range(offset..)) {
+                        *dest = *src;
+                        read += 1;
+                    }
+                    read
+                } else {
+                    let read = decoder.read(buf)?;
+                    push_cache(cache, *cache_size, buf.get(..read).expect("read <= len"));
+                    *decoded += u64::try_from(read).expect("usize <= u64");
+                    save_checkpoint(decoder, *decoded, checkpoints, *checkpoint_interval);
+                    read
+                };
+                self.pos += u64::try_from(read).expect("usize <= u64");
+                Ok(read)
+            }
+        }
+    }
+}
+
+impl<S: Read + Seek> Seek for EntryReader<S> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => offset_by(self.pos, offset),
+            SeekFrom::End(offset) => offset_by(self.len(), offset),
+        }
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+
+        match &mut self.state {
+            State::Raw(raw) => {
+                let _ = raw
+                    .get_mut()
+                    .seek(SeekFrom::Start(self.start.saturating_add(target)))?;
+                raw.set_limit(self.compressed_size.saturating_sub(target));
+            }
+            State::Compressed {
+                decoded,
+                cache,
+                checkpoints,
+                ..
+            } => {
+                let cached = u64::try_from(cache.len()).expect("usize <= u64");
+                // a checkpoint after the current position saves decompressing up to it
+                let skips_ahead = checkpoints
+                    .iter()
+                    .rev()
+                    .find(|(at, _)| *at <= target)
+                    .is_some_and(|(at, _)| *at > *decoded);
+                if target < *decoded - cached || skips_ahead {
+                    self.restart(target)?;
+                }
+            }
+        }
+        self.pos = target;
+        Ok(target)
+    }
+}
+
+/// Saves the state of `decoder` if `decoded` is at least `interval` bytes
+/// after the last checkpoint.
+fn save_checkpoint<R: Read>(
+    decoder: &Bounded<Decoder<R>>,
+    decoded: u64,
+    checkpoints: &mut Vec<(u64, DecoderCheckpoint)>,
+    interval: u64,
+) {
+    if interval == 0 {
+        return;
+    }
+    let last = checkpoints.last().map_or(0, |(at, _)| *at);
+    if decoded >= last + interval {
+        if let Some(checkpoint) = decoder.get_ref().checkpoint() {
+            checkpoints.push((decoded, checkpoint));
+        }
+    }
+}
+
+/// Appends decompressed bytes to the cache, keeping at most `cache_size` bytes.
+fn push_cache(cache: &mut VecDeque<u8>, cache_size: usize, bytes: &[u8]) {
+    if cache_size == 0 {
+        return;
+    }
+    let bytes = bytes
+        .get(bytes.len().saturating_sub(cache_size)..)
+        .unwrap_or(bytes);
+    cache.extend(bytes);
+    let excess = cache.len().saturating_sub(cache_size);
+    let _ = cache.drain(..excess);
+}
+
+fn offset_by(base: u64, offset: i64) -> Option<u64> {
+    if offset >= 0 {
+        base.checked_add(u64::try_from(offset).ok()?)
+    } else {
+        base.checked_sub(offset.unsigned_abs())
+    }
+}
+
+impl<S: Read + Seek> std::fmt::Debug for EntryReader<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EntryReader")
+            .field("start", &self.start)
+            .field("compressed_size", &self.compressed_size)
+            .field("original_size", &self.original_size)
+            .field("pos", &self.pos)
+            .finish()
+    }
+}
@@ -0,0 +1,392 @@
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind, Read, Result, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+
+use super::extract;
+use super::handle::{self, EntryHandle};
+use super::trailer::SignatureInfo;
+use super::validate::{self, Diagnostic};
+use super::{index, FileIndex, Glob, Options, Reader, Section};
+use crate::progress::Observer;
+use crate::{ApiVersion, GlobalFlags};
+
+/// A source that can be read at arbitrary offsets through a shared reference.
+///
+/// Unlike `io::Read + io::Seek`, reading does not move a shared cursor,
+/// so multiple threads can read from the same source concurrently.
+pub trait ReadAt {
+    /// Reads bytes starting at `offset` into `buf`,
+    /// returning the number of bytes read.
+    ///
+    /// Returns `Ok(0)` if `offset` is at or beyond the end of the source.
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize>;
+
+    /// Returns the total size of the source.
+    fn size(&self) -> Result<u64>;
+}
+
+impl ReadAt for [u8] {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        let rest = usize::try_from(offset)
+            .ok()
+            .and_then(|offset| self.get(offset..))
+            .unwrap_or_default();
+        let len = rest.len().min(buf.len());
+        buf.get_mut(..len)
+            .expect("len <= buf.len()")
+            .copy_from_slice(rest.get(..len).expect("len <= rest.len()"));
+        Ok(len)
+    }
+
+    fn size(&self) -> Result<u64> {
+        Ok(u64::try_from(self.len()).expect("usize <= u64"))
+    }
+}
+
+impl ReadAt for Vec<u8> {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        self[..].read_at(buf, offset)
+    }
+
+    fn size(&self) -> Result<u64> {
+        self[..].size()
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl ReadAt for memmap2::Mmap {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        self[..].read_at(buf, offset)
+    }
+
+    fn size(&self) -> Result<u64> {
+        self[..].size()
+    }
+}
+
+#[cfg(any(unix, windows))]
+impl ReadAt for std::fs::File {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::FileExt::read_at(self, buf, offset)
+        }
+        #[cfg(windows)]
+        {
+            std::os::windows::fs::FileExt::seek_read(self, buf, offset)
+        }
+    }
+
+    fn size(&self) -> Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for &T {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        (**self).read_at(buf, offset)
+    }
+
+    fn size(&self) -> Result<u64> {
+        (**self).size()
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for Arc<T> {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        (**self).read_at(buf, offset)
+    }
+
+    fn size(&self) -> Result<u64> {
+        (**self).size()
+    }
+}
+
+/// An `io::Read + io::Seek` cursor over a `ReadAt` source.
+///
+/// Each cursor has its own position,
+/// so cursors over the same source do not interfere with each other.
+#[derive(Debug, Clone)]
+pub struct ReadAtCursor<A: ReadAt> {
+    source: A,
+    pos: u64,
+}
+
+impl<A: ReadAt> ReadAtCursor<A> {
+    /// Creates a cursor at the start of `source`.
+    pub fn new(source: A) -> Self {
+        Self { source, pos: 0 }
+    }
+
+    /// Returns the underlying source.
+    pub fn get_ref(&self) -> &A {
+        &self.source
+    }
+}
+
+impl<A: ReadAt> Read for ReadAtCursor<A> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let read = self.source.read_at(buf, self.pos)?;
+        self.pos += u64::try_from(read).expect("usize <= u64");
+        Ok(read)
+    }
+}
+
+impl<A: ReadAt> Seek for ReadAtCursor<A> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(offset) => {
+                self.pos = offset;
+                return Ok(offset);
+            }
+            SeekFrom::Current(offset) => (self.pos, offset),
+            SeekFrom::End(offset) => (self.source.size()?, offset),
+        };
+        let target = if offset >= 0 {
+            u64::try_from(offset)
+                .ok()
+                .and_then(|offset| base.checked_add(offset))
+        } else {
+            base.checked_sub(offset.unsigned_abs())
+        };
+        self.pos = target.ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(self.pos)
+    }
+}
+
+/// A phar reader whose accessors only require `&self`,
+/// so that it can be shared across threads, e.g. in an `Arc`.
+///
+/// The parsed index is immutable after `read`.
+/// Each accessor reads from `source` through its own `ReadAtCursor`,
+/// so entries can be read concurrently without locking.
+#[derive(Debug)]
+#[cfg_attr(feature = "docsrs", doc(cfg(feature = "reader")))]
+pub struct SharedReader<A: ReadAt, FileIndexT: FileIndex = index::NameHashMap> {
+    inner: Reader<ReadAtCursor<A>, FileIndexT>,
+}
+
+impl<A: ReadAt, FileIndexT: FileIndex> SharedReader<A, FileIndexT> {
+    /// Parses the phar file.
+    ///
+    /// See `Reader::read` for details.
+    pub fn read(source: A, options: Options) -> Result<Self> {
+        let inner = Reader::read(ReadAtCursor::new(source), options)?;
+        Ok(Self { inner })
+    }
+
+    /// Returns the underlying source.
+    pub fn source(&self) -> &A {
+        self.inner.get_ref().get_ref()
+    }
+
+    /// Creates a new cursor over the underlying source.
+    fn cursor(&self) -> ReadAtCursor<&A> {
+        ReadAtCursor::new(self.source())
+    }
+
+    fn section<'t>(&'t self, section: &'t Section) -> Result<Cow<'t, [u8]>> {
+        section.as_ref().as_memory(&mut self.cursor())
+    }
+
+    /// Returns the stub, including the `__HALT_COMPILER(); ?>` terminator.
+    ///
+    /// This is only copied if `cache_stub` is false.
+    pub fn stub(&self) -> Result<Cow<'_, [u8]>> {
+        self.section(&self.inner.stub)
+    }
+
+    /// Returns the alias.
+    ///
+    /// This is only copied if `cache_alias` is false.
+    pub fn alias(&self) -> Result<Cow<'_, [u8]>> {
+        self.section(&self.inner.alias)
+    }
+
+    /// Returns the metadata.
+    ///
+    /// This is only copied if `cache_metadata` is false.
+    pub fn metadata(&self) -> Result<Cow<'_, [u8]>> {
+        self.section(&self.inner.metadata)
+    }
+
+    /// Returns the number of file entries declared in the manifest.
+    pub fn entry_count(&self) -> u32 {
+        self.inner.entry_count()
+    }
+
+    /// Returns the manifest API version.
+    pub fn api_version(&self) -> ApiVersion {
+        self.inner.api_version()
+    }
+
+    /// Returns the global flags.
+    pub fn global_flags(&self) -> GlobalFlags {
+        self.inner.global_flags()
+    }
+
+    /// Returns the signature of the phar file.
+    ///
+    /// See `Reader::signature` for details.
+    pub fn signature(&self) -> Option<&SignatureInfo> {
+        self.inner.signature()
+    }
+
+    /// Returns the file index.
+    pub fn index(&self) -> &FileIndexT {
+        self.inner.index()
+    }
+
+    /// Checks the phar file for structural inconsistencies.
+    ///
+    /// See `Reader::validate` for details.
+    pub fn validate(&self) -> Result<Vec<Diagnostic>> {
+        validate::validate(&mut self.cursor(), self.inner.stub.len())
+    }
+}
+
+impl<A: ReadAt, FileIndexT: index::Iterable> SharedReader<A, FileIndexT> {
+    /// Iterates over the files in this archive.
+    ///
+    /// Directory entries are skipped.
+    pub fn for_each_file<F>(&self, f: F) -> Result<()>
+    where
+        F: FnMut(&[u8], &mut dyn Read) -> Result<()>,
+    {
+        index::fold_files(
+            &self.inner.file_index,
+            self.cursor(),
+            f,
+            |_, ()| (),
+            &self.inner.limits,
+        )
+        .map(|_| ())
+    }
+
+    /// Iterates over all entries in this archive, including directories.
+    ///
+    /// See `Reader::for_each_entry` for details.
+    pub fn for_each_entry<F>(&self, f: F) -> Result<()>
+    where
+        F: FnMut(EntryHandle<'_, ReadAtCursor<&A>>) -> Result<()>,
+    {
+        self.for_each_matching(b"", |_| true, f)
+    }
+
+    /// Iterates over the files whose names start with `prefix`.
+    ///
+    /// See `Reader::for_each_prefix` for details.
+    pub fn for_each_prefix<F>(&self, prefix: &[u8], f: F) -> Result<()>
+    where
+        F: FnMut(EntryHandle<'_, ReadAtCursor<&A>>) -> Result<()>,
+    {
+        self.for_each_matching(prefix, |name| !index::is_dir_name(name), f)
+    }
+
+    /// Iterates over the files whose names match `glob`.
+    ///
+    /// See `Reader::for_each_glob` for details.
+    pub fn for_each_glob<F>(&self, glob: &Glob, f: F) -> Result<()>
+    where
+        F: FnMut(EntryHandle<'_, ReadAtCursor<&A>>) -> Result<()>,
+    {
+        self.for_each_matching(
+            &glob.literal_prefix(),
+            |name| !index::is_dir_name(name) && glob.is_match(name),
+            f,
+        )
+    }
+
+    /// Verifies the decompressed contents of all files against the CRC32 checksums in the manifest.
+    ///
+    /// See `Reader::verify_crc32` for details.
+    pub fn verify_crc32(&self) -> Result<()> {
+        let jobs = extract::jobs(&self.inner.file_index, &mut self.cursor())?;
+        for job in &jobs {
+            extract::verify(&mut job.handle(&mut self.cursor(), self.inner.limits))?;
+        }
+        Ok(())
+    }
+
+    /// Extracts all entries into `dir`, creating it if it does not exist.
+    ///
+    /// See `Reader::extract_to` for details.
+    pub fn extract_to(&self, dir: impl AsRef<Path>) -> Result<()> {
+        self.extract_to_with(dir, ())
+    }
+
+    /// Like `extract_to`, but reports the progress of extraction to `observer`.
+    ///
+    /// See `Reader::extract_to_with` for details.
+    pub fn extract_to_with(&self, dir: impl AsRef<Path>, observer: impl Observer) -> Result<()> {
+        let mut cursor = self.cursor();
+        let jobs = extract::jobs(&self.inner.file_index, &mut cursor)?;
+        extract::extract_all(
+            &jobs,
+            &mut cursor,
+            self.inner.limits,
+            dir.as_ref(),
+            observer,
+        )
+    }
+
+    /// Like `verify_crc32`, but checks entries in parallel on the rayon thread pool,
+    /// each thread reading from its own cursor.
+    ///
+    /// All files are checked even if some fail,
+    /// and the error of the first failing file in manifest order is returned,
+    /// so the result does not depend on thread scheduling.
+    #[cfg(feature = "rayon")]
+    #[cfg_attr(feature = "docsrs", doc(cfg(feature = "rayon")))]
+    pub fn par_verify_crc32(&self) -> Result<()>
+    where
+        A: Sync,
+        FileIndexT: Sync,
+    {
+        let jobs = extract::jobs(&self.inner.file_index, &mut self.cursor())?;
+        extract::par_run(&jobs, self.inner.limits, || self.cursor(), extract::verify)
+    }
+
+    /// Like `extract_to`, but extracts entries in parallel on the rayon thread pool,
+    /// each thread reading from its own cursor.
+    ///
+    /// Errors are reported as in `par_verify_crc32`.
+    #[cfg(feature = "rayon")]
+    #[cfg_attr(feature = "docsrs", doc(cfg(feature = "rayon")))]
+    pub fn par_extract_to(&self, dir: impl AsRef<Path>) -> Result<()>
+    where
+        A: Sync,
+        FileIndexT: Sync,
+    {
+        let dir = dir.as_ref();
+        let jobs = extract::jobs(&self.inner.file_index, &mut self.cursor())?;
+        extract::par_run(
+            &jobs,
+            self.inner.limits,
+            || self.cursor(),
+            |entry| extract::extract(entry, dir),
+        )
+    }
+
+    fn for_each_matching<P, F>(&self, prefix: &[u8], filter: P, f: F) -> Result<()>
+    where
+        P: FnMut(&[u8]) -> bool,
+        F: FnMut(EntryHandle<'_, ReadAtCursor<&A>>) -> Result<()>,
+    {
+        handle::for_each_matching(
+            &self.inner.file_index,
+            &mut self.cursor(),
+            self.inner.limits,
+            prefix,
+            filter,
+            f,
+        )
+    }
+}
@@ -0,0 +1,274 @@
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::io::{Cursor, Read, Result};
+use std::ops::Range;
+
+use super::limits::EntryLimits;
+use super::section::slice_range;
+use super::trailer::SignatureInfo;
+use super::validate::{self, Diagnostic};
+use super::{index, EntryInfo, EntryReader, FileIndex, Glob, Options, Reader, Section};
+use crate::{ApiVersion, Compression, CompressionMethod, GlobalFlags};
+
+/// A phar reader over an in-memory byte slice.
+///
+/// Unlike `Reader`, sections and uncompressed file contents
+/// are returned as subslices of the input without copying,
+/// and all accessors only require `&self`.
+///
+/// The `cache_*` fields in `Options` are ignored,
+/// since the whole file is already in memory.
+#[derive(Debug)]
+#[cfg_attr(feature = "docsrs", doc(cfg(feature = "reader")))]
+pub struct SliceReader<'a, FileIndexT: FileIndex = index::NameHashMap> {
+    data: &'a [u8],
+    inner: Reader<Cursor<&'a [u8]>, FileIndexT>,
+}
+
+impl<'a, FileIndexT: FileIndex> SliceReader<'a, FileIndexT> {
+    /// Parses the phar file.
+    ///
+    /// See `Reader::read` for details.
+    pub fn read(data: &'a [u8], options: Options) -> Result<Self> {
+        let inner = Reader::read(Cursor::new(data), options.without_cache())?;
+        Ok(Self { data, inner })
+    }
+
+    fn section(&self, section: &Section) -> Result<&'a [u8]> {
+        match section {
+            Section::Offset(start, end) => slice_range(self.data, *start..*end),
+            Section::Cached(_) => unreachable!("SliceReader disables section caching"),
+        }
+    }
+
+    /// Returns the whole phar file.
+    pub fn bytes(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Returns the stub, including the `__HALT_COMPILER(); ?>` terminator.
+    pub fn stub(&self) -> Result<&'a [u8]> {
+        self.section(&self.inner.stub)
+    }
+
+    /// Returns the alias.
+    pub fn alias(&self) -> Result<&'a [u8]> {
+        self.section(&self.inner.alias)
+    }
+
+    /// Returns the metadata.
+    pub fn metadata(&self) -> Result<&'a [u8]> {
+        self.section(&self.inner.metadata)
+    }
+
+    /// Returns the number of file entries declared in the manifest.
+    pub fn entry_count(&self) -> u32 {
+        self.inner.entry_count()
+    }
+
+    /// Returns the manifest API version.
+    pub fn api_version(&self) -> ApiVersion {
+        self.inner.api_version()
+    }
+
+    /// Returns the global flags.
+    pub fn global_flags(&self) -> GlobalFlags {
+        self.inner.global_flags()
+    }
+
+    /// Returns the signature of the phar file.
+    ///
+    /// See `Reader::signature` for details.
+    pub fn signature(&self) -> Option<&SignatureInfo> {
+        self.inner.signature()
+    }
+
+    /// Returns the file index.
+    pub fn index(&self) -> &FileIndexT {
+        self.inner.index()
+    }
+
+    /// Checks the phar file for structural inconsistencies.
+    ///
+    /// See `Reader::validate` for details.
+    pub fn validate(&self) -> Result<Vec<Diagnostic>> {
+        validate::validate(&mut Cursor::new(self.data), self.inner.stub.len())
+    }
+}
+
+impl<'a, FileIndexT: index::Iterable> SliceReader<'a, FileIndexT> {
+    /// Iterates over the files in this archive.
+    ///
+    /// Directory entries are skipped.
+    /// The name passed to `f` borrows from the input if the index does not cache names,
+    /// and from the index otherwise.
+    pub fn for_each_file<F>(&self, f: F) -> Result<()>
+    where
+        F: FnMut(&[u8], SliceEntry<'a>) -> Result<()>,
+    {
+        self.for_each_matching(b"", |name| !index::is_dir_name(name), f)
+    }
+
+    /// Iterates over all entries in this archive, including directories.
+    ///
+    /// See `Reader::for_each_entry` for details.
+    pub fn for_each_entry<F>(&self, f: F) -> Result<()>
+    where
+        F: FnMut(&[u8], SliceEntry<'a>) -> Result<()>,
+    {
+        self.for_each_matching(b"", |_| true, f)
+    }
+
+    /// Iterates over the files whose names start with `prefix`.
+    ///
+    /// See `Reader::for_each_prefix` for details.
+    pub fn for_each_prefix<F>(&self, prefix: &[u8], f: F) -> Result<()>
+    where
+        F: FnMut(&[u8], SliceEntry<'a>) -> Result<()>,
+    {
+        self.for_each_matching(prefix, |name| !index::is_dir_name(name), f)
+    }
+
+    /// Iterates over the files whose names match `glob`.
+    ///
+    /// See `Reader::for_each_glob` for details.
+    pub fn for_each_glob<F>(&self, glob: &Glob, f: F) -> Result<()>
+    where
+        F: FnMut(&[u8], SliceEntry<'a>) -> Result<()>,
+    {
+        self.for_each_matching(
+            &glob.literal_prefix(),
+            |name| !index::is_dir_name(name) && glob.is_match(name),
+            f,
+        )
+    }
+
+    fn for_each_matching<P, F>(&self, prefix: &[u8], mut filter: P, mut f: F) -> Result<()>
+    where
+        P: FnMut(&[u8]) -> bool,
+        F: FnMut(&[u8], SliceEntry<'a>) -> Result<()>,
+    {
+        let data = self.data;
+        let limits = self.inner.limits;
+        self.inner
+            .file_index
+            .for_each_entry_range_with_prefix(prefix, |name, info, range| {
+                let name = name.as_slice(data)?;
+                if !name.starts_with(prefix) || !filter(name) {
+                    return Ok(());
+                }
+                f(name, SliceEntry::new(data, info, range, limits)?)
+            })
+    }
+}
+
+/// The contents of a file entry in a `SliceReader`.
+#[derive(Debug, Clone, Copy)]
+pub struct SliceEntry<'a> {
+    info: EntryInfo,
+    raw: &'a [u8],
+    limits: EntryLimits,
+}
+
+impl<'a> SliceEntry<'a> {
+    fn new(
+        data: &'a [u8],
+        info: EntryInfo,
+        range: Range<u64>,
+        limits: EntryLimits,
+    ) -> Result<Self> {
+        Ok(Self {
+            info,
+            raw: slice_range(data, range)?,
+            limits,
+        })
+    }
+
+    /// Returns the compression method of this entry.
+    pub fn compression(&self) -> CompressionMethod {
+        self.info.compression()
+    }
+
+    /// Estimates the compression settings of this entry from the start of its contents.
+    ///
+    /// See `EntryHandle::estimate_compression` for details.
+    pub fn estimate_compression(&self) -> Option<Compression> {
+        self.info.estimate_compression(self.raw)
+    }
+
+    /// Returns the fields of the entry stored in the manifest.
+    pub fn info(&self) -> EntryInfo {
+        self.info
+    }
+
+    /// Returns the contents as stored in the archive, i.e. possibly compressed.
+    pub fn raw(&self) -> &'a [u8] {
+        self.raw
+    }
+
+    /// Returns the contents without copying if the entry is not compressed.
+    ///
+    /// Decompression limits in `Options` do not apply to the returned slice,
+    /// since it is already in memory.
+    pub fn as_slice(&self) -> Option<&'a [u8]> {
+        match self.compression() {
+            CompressionMethod::None => Some(self.raw),
+            _ => None,
+        }
+    }
+
+    /// Returns an `io::Read` over the decompressed contents,
+    /// decompressing directly from the input slice.
+    ///
+    /// The decompression limits in `Options` are applied on the returned reader.
+    pub fn read(&self) -> Result<Box<dyn Read + 'a>> {
+        let compression = self.compression();
+        let read = compression.make_read(self.raw)?;
+        let len = u64::try_from(self.raw.len()).expect("usize <= u64");
+        Ok(Box::new(self.limits.wrap(
+            read,
+            compression.bit() != 0,
+            len,
+        )))
+    }
+
+    /// Returns an `io::Read + io::Seek` over the decompressed contents.
+    ///
+    /// See `EntryReader` for the cost of seeking in compressed entries.
+    pub fn read_seekable(&self) -> Result<EntryReader<Cursor<&'a [u8]>>> {
+        let len = u64::try_from(self.raw.len()).expect("usize <= u64");
+        EntryReader::new(
+            Cursor::new(self.raw),
+            self.compression(),
+            0,
+            len,
+            self.info.original_size.into(),
+            self.limits,
+        )
+    }
+
+    /// Returns the decompressed contents,
+    /// which is only copied if the entry is compressed.
+    pub fn contents(&self) -> Result<Cow<'a, [u8]>> {
+        match self.as_slice() {
+            Some(slice) => Ok(Cow::Borrowed(slice)),
+            None => {
+                let mut vec = Vec::new();
+                let _ = self.read()?.read_to_end(&mut vec)?;
+                Ok(Cow::Owned(vec))
+            }
+        }
+    }
+}
+
+/// Memory-maps a file so that it can be read with `SliceReader::read`.
+///
+/// # Safety
+/// The behaviour is undefined if the file is modified or truncated,
+/// by this or any other process, while the map is alive.
+/// See `memmap2::Mmap::map` for details.
+#[cfg(feature = "mmap")]
+#[cfg_attr(feature = "docsrs", doc(cfg(feature = "mmap")))]
+pub unsafe fn map_file(file: &std::fs::File) -> Result<memmap2::Mmap> {
+    memmap2::Mmap::map(file)
+}
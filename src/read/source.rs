@@ -0,0 +1,41 @@
+use std::io::{Read, Result, Seek};
+use std::ops::Range;
+
+use super::handle::EntryHandle;
+use super::{extract, index, EntryInfo, Reader};
+use crate::util::PERMISSION_MASK;
+use crate::write::{Source, SourceEntry};
+
+/// The entries of another phar archive in manifest order.
+///
+/// Entry metadata, modification times and permissions are preserved.
+/// The contents are decompressed and compressed again with the compression in `BuildOptions`;
+/// use `repack::repack` to keep the original compression instead.
+impl<R: Read + Seek, FileIndexT: index::Iterable> Source for Reader<R, FileIndexT> {
+    /// The manifest fields and the position of the contents in the archive
+    type Id = (EntryInfo, Range<u64>);
+
+    fn list(&mut self) -> Result<Vec<SourceEntry<Self::Id>>> {
+        let jobs = extract::jobs(&self.file_index, &mut self.stream)?;
+        let mut entries = Vec::with_capacity(jobs.len());
+        for job in &jobs {
+            let mut handle = job.handle(&mut self.stream, self.limits);
+            let info = handle.info();
+            entries.push(SourceEntry {
+                name: handle.name().to_vec(),
+                metadata: handle.metadata()?,
+                modified: info.modified(),
+                mode: info.flags() & PERMISSION_MASK,
+                is_dir: handle.is_dir(),
+                compression: None,
+                id: (info, handle.range()),
+            });
+        }
+        Ok(entries)
+    }
+
+    fn open(&mut self, (info, range): &Self::Id) -> Result<Box<dyn Read + '_>> {
+        let handle = EntryHandle::new(&[], *info, range.clone(), &mut self.stream, self.limits);
+        Ok(Box::new(handle.into_reader()?))
+    }
+}
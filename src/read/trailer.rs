@@ -0,0 +1,115 @@
+use std::fmt::Write as _;
+use std::io::{Error, ErrorKind, Read, Result, Seek, SeekFrom};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::signature::{Signature, SignatureAlgorithm};
+use crate::util::PHAR_TERMINATOR;
+
+/// The signature of a phar file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureInfo {
+    pub(super) algorithm: SignatureAlgorithm,
+    pub(super) digest: Vec<u8>,
+    pub(super) offset: u64,
+    pub(super) verified: bool,
+}
+
+impl SignatureInfo {
+    /// Returns the signature algorithm.
+    pub fn algorithm(&self) -> SignatureAlgorithm {
+        self.algorithm
+    }
+
+    /// Returns the digest stored in the file.
+    pub fn digest(&self) -> &[u8] {
+        &self.digest
+    }
+
+    /// Returns the digest stored in the file as a lowercase hex string.
+    pub fn hex_digest(&self) -> String {
+        let mut hex = String::with_capacity(self.digest.len() * 2);
+        for byte in &self.digest {
+            write!(hex, "{:02x}", byte).expect("String::write_fmt is infallible");
+        }
+        hex
+    }
+
+    /// Returns the offset of the digest in the file.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Whether the digest was verified against the file contents.
+    ///
+    /// This is only true if `verify_signature` was set when the file was read.
+    pub fn is_verified(&self) -> bool {
+        self.verified
+    }
+}
+
+/// Parses the last 8 bytes of a phar file,
+/// i.e. the signature type and the `GBMB` terminator.
+pub(super) fn parse_trailer(trailer: [u8; 8]) -> Result<SignatureAlgorithm> {
+    let (mut discrim, gbmb) = trailer.split_at(4);
+    if gbmb != PHAR_TERMINATOR {
+        return Err(Error::new(ErrorKind::Other, "corrupted file"));
+    }
+
+    let discrim = discrim.read_u32::<LittleEndian>()?;
+    SignatureAlgorithm::from_u32(discrim).ok_or_else(|| {
+        Error::new(
+            ErrorKind::Other,
+            format!("unsupported signature type {:x}", discrim),
+        )
+    })
+}
+
+/// Parses the signature trailer into a `Signature` to verify the file with.
+pub(super) fn parse_signature_trailer(trailer: [u8; 8]) -> Result<Signature> {
+    let algorithm = parse_trailer(trailer)?;
+    Signature::new(algorithm).ok_or_else(|| {
+        Error::new(
+            ErrorKind::Other,
+            format!("signature algorithm {} is not enabled", algorithm),
+        )
+    })
+}
+
+/// Returns the offset of the signature digest, given the file length and the trailer.
+pub(super) fn signature_offset(file_len: u64, algorithm: SignatureAlgorithm) -> Option<u64> {
+    file_len.checked_sub(8 + u64::from(algorithm.size()))
+}
+
+/// Reads the signature of a phar file without verifying it.
+///
+/// Returns `None` if the file does not end with a valid signature trailer.
+pub(super) fn read_unverified(read: &mut (impl Read + Seek)) -> Result<Option<SignatureInfo>> {
+    let file_len = read.seek(SeekFrom::End(0))?;
+    if file_len < 8 {
+        return Ok(None);
+    }
+
+    let _ = read.seek(SeekFrom::End(-8))?;
+    let mut trailer = [0u8; 8];
+    read.read_exact(&mut trailer[..])?;
+    let algorithm = match parse_trailer(trailer) {
+        Ok(algorithm) => algorithm,
+        Err(_) => return Ok(None),
+    };
+    let offset = match signature_offset(file_len, algorithm) {
+        Some(offset) => offset,
+        None => return Ok(None),
+    };
+
+    let mut digest = vec![0u8; algorithm.size().into()];
+    let _ = read.seek(SeekFrom::Start(offset))?;
+    read.read_exact(&mut digest[..])?;
+
+    Ok(Some(SignatureInfo {
+        algorithm,
+        digest,
+        offset,
+        verified: false,
+    }))
+}
@@ -1,53 +1,173 @@
-use std::io::{Error, ErrorKind, Read, Result};
+use std::convert::TryFrom;
+use std::io::{Cursor, Error, ErrorKind, Read, Result, Seek, SeekFrom};
 
-use itertools::{multipeek, MultiPeek};
+use memchr::memmem;
 
 use super::Section;
 
-pub fn read_find_bstr(file: &mut impl Read, buf: &mut Section, bstr: &[u8]) -> Result<()> {
-    fn multi_peek_starts_with(
-        iter: &mut MultiPeek<impl Iterator<Item = Result<u8>>>,
-        bstr: &[u8],
-    ) -> Result<bool> {
-        iter.reset_peek();
-        for &expected in bstr {
-            let actual: Option<&Result<u8>> = iter.peek();
-            match actual {
-                None => return Err(ErrorKind::UnexpectedEof.into()),
-                Some(Err(err)) => return Err(Error::new(err.kind(), err.to_string())),
-                Some(&Ok(actual)) if actual == expected => continue,
-                Some(&Ok(_)) => return Ok(false),
-            }
-        }
-        Ok(true)
-    }
+/// The size of each chunk read from the stream when scanning for a terminator.
+const SCAN_CHUNK_SIZE: usize = 64 * 1024;
 
-    debug_assert_ne!(bstr.len(), 0, "SSearching for empty string is nonsense");
+/// Reads `file` into `buf` until (inclusively) the first occurrence of `bstr`.
+///
+/// The stream is read in chunks and searched with a Two-Way substring search.
+/// Bytes read past the end of `bstr` are not fed into `buf`,
+/// and the stream is seeked back to the byte right after `bstr`.
+///
+/// At most `limit` bytes are read from the stream,
+/// so that readers teeing the stream (e.g. into a signature digest)
+/// never observe bytes beyond `limit`.
+pub fn read_find_bstr(
+    file: &mut (impl Read + Seek),
+    buf: &mut Section,
+    bstr: &[u8],
+    limit: u64,
+) -> Result<()> {
+    read_find_bstr_chunked(file, buf, bstr, limit, SCAN_CHUNK_SIZE)
+}
+
+fn read_find_bstr_chunked(
+    file: &mut (impl Read + Seek),
+    buf: &mut Section,
+    bstr: &[u8],
+    limit: u64,
+    chunk_size: usize,
+) -> Result<()> {
+    let mut scanner = BstrScanner::new(bstr, limit, chunk_size);
 
-    let mut iter = multipeek(file.bytes());
     loop {
-        if multi_peek_starts_with(&mut iter, bstr)? {
-            for _ in 0..bstr.len() {
-                let next = iter
-                    .next()
-                    .expect("EOF was thrown in multi_peek_starts_with()")
-                    .expect("Err values were checked in multi_peek_starts_with()");
-                buf.feed(&[next]);
+        let read = match file.read(scanner.free()) {
+            Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(read) => read,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+
+        if let Some(unread) = scanner.filled(read, buf)? {
+            if unread > 0 {
+                let _ = file.seek(SeekFrom::Current(-unread))?;
             }
             return Ok(());
         }
+    }
+}
+
+/// The I/O-agnostic state of `read_find_bstr`.
+///
+/// The caller repeatedly reads into `free()` and reports the number of bytes with `filled()`,
+/// until `filled()` returns the number of bytes read past the end of `bstr`.
+pub struct BstrScanner<'b> {
+    finder: memmem::Finder<'b>,
+    /// The chunk buffer, which must be able to hold a partial match and at least one new byte
+    chunk: Vec<u8>,
+    /// The number of bytes at the start of `chunk` that are not fed into the section yet
+    filled: usize,
+    /// The number of bytes that may still be read from the stream
+    limit: u64,
+}
+
+impl<'b> BstrScanner<'b> {
+    pub fn new(bstr: &'b [u8], limit: u64, chunk_size: usize) -> Self {
+        debug_assert_ne!(bstr.len(), 0, "Searching for empty string is nonsense");
 
-        let next = iter
-            .next()
-            .expect("EOF was thrown in multi_peek_starts_with()")
-            .expect("Err values were checked in multi_peek_starts_with()");
-        buf.feed(&[next]);
+        Self {
+            finder: memmem::Finder::new(bstr),
+            chunk: vec![0u8; chunk_size.max(bstr.len())],
+            filled: 0,
+            limit,
+        }
+    }
+
+    /// Creates a scanner with the default chunk size.
+    pub fn with_limit(bstr: &'b [u8], limit: u64) -> Self {
+        Self::new(bstr, limit, SCAN_CHUNK_SIZE)
+    }
+
+    /// Returns the buffer to read the next chunk into.
+    ///
+    /// The returned slice is empty if the limit has been reached.
+    pub fn free(&mut self) -> &mut [u8] {
+        let free = self
+            .chunk
+            .get_mut(self.filled..)
+            .expect("filled <= chunk.len()");
+        let free_len =
+            usize::try_from(self.limit).map_or(free.len(), |limit| limit.min(free.len()));
+        free.get_mut(..free_len).expect("free_len <= free.len()")
+    }
+
+    /// Reports that `read` bytes have been read into `free()`.
+    ///
+    /// Returns the number of bytes read past the end of `bstr` if it is found.
+    pub fn filled(&mut self, read: usize, buf: &mut Section) -> Result<Option<i64>> {
+        self.filled += read;
+        self.limit -= u64::try_from(read).expect("usize <= u64");
+
+        let bstr_len = self.finder.needle().len();
+        let window = self
+            .chunk
+            .get(..self.filled)
+            .expect("filled <= chunk.len()");
+        if let Some(position) = self.finder.find(window) {
+            let (consumed, unread) = window.split_at(position + bstr_len);
+            buf.feed(consumed);
+
+            let unread = i64::try_from(unread.len())
+                .map_err(|_| Error::new(ErrorKind::Other, "chunk size is too large"))?;
+            return Ok(Some(unread));
+        }
+
+        // the last `bstr.len() - 1` bytes may be the prefix of a match across chunks
+        let keep = (bstr_len - 1).min(self.filled);
+        let (consumed, _) = window.split_at(self.filled - keep);
+        buf.feed(consumed);
+        self.chunk.copy_within(self.filled - keep..self.filled, 0);
+        self.filled = keep;
+        Ok(None)
+    }
+}
+
+/// A cursor over an in-memory copy of part of a stream,
+/// which reports offsets relative to the original stream.
+pub struct ShiftedCursor<T> {
+    inner: Cursor<T>,
+    base: u64,
+}
+
+impl<T: AsRef<[u8]>> ShiftedCursor<T> {
+    /// Creates a cursor over `data`, which was read from offset `base` in the original stream.
+    pub fn new(data: T, base: u64) -> Self {
+        Self {
+            inner: Cursor::new(data),
+            base,
+        }
+    }
+}
+
+impl<T: AsRef<[u8]>> Read for ShiftedCursor<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<T: AsRef<[u8]>> Seek for ShiftedCursor<T> {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => {
+                SeekFrom::Start(pos.checked_sub(self.base).ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidInput, "seek before start of buffer")
+                })?)
+            }
+            pos => pos,
+        };
+        Ok(self.inner.seek(pos)? + self.base)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::io::Cursor;
+    use std::convert::TryFrom;
+    use std::io::{Cursor, Read, Seek};
 
     use super::super::Section;
 
@@ -55,25 +175,73 @@ mod tests {
     fn read_until_bstr() {
         let haystack = b"mississippi";
 
-        for needle in (1..=7).flat_map(|len| haystack.windows(len)) {
-            let offset = haystack
-                .windows(needle.len())
-                .position(|substr| substr == needle)
-                .expect("needle was extracted from haystack");
+        for chunk_size in 1..=haystack.len() + 1 {
+            for needle in (1..=7).flat_map(|len| haystack.windows(len)) {
+                let offset = haystack
+                    .windows(needle.len())
+                    .position(|substr| substr == needle)
+                    .expect("needle was extracted from haystack");
 
-            let buf = Vec::with_capacity(haystack.len());
-            let mut section = Section::Cached(buf);
-            super::read_find_bstr(&mut Cursor::new(haystack.iter()), &mut section, needle)
+                let buf = Vec::with_capacity(haystack.len());
+                let mut section = Section::Cached(buf);
+                let mut cursor = Cursor::new(haystack.iter());
+                super::read_find_bstr_chunked(
+                    &mut cursor,
+                    &mut section,
+                    needle,
+                    u64::MAX,
+                    chunk_size,
+                )
                 .unwrap_or_else(|_| {
                     panic!("Failed to find needle {}", String::from_utf8_lossy(needle))
                 });
 
-            let buf = match section {
-                Section::Cached(buf) => buf,
-                _ => unreachable!(),
-            };
+                let buf = match section {
+                    Section::Cached(buf) => buf,
+                    _ => unreachable!(),
+                };
+
+                assert_eq!(haystack.get(0..offset + needle.len()), Some(&buf[..]));
+                assert_eq!(
+                    cursor.stream_position().expect("cursor seek is infallible"),
+                    u64::try_from(offset + needle.len()).expect("usize <= u64"),
+                );
 
-            assert_eq!(haystack.get(0..offset + needle.len()), Some(&buf[..]));
+                let mut rest = Vec::new();
+                let _ = cursor
+                    .read_to_end(&mut rest)
+                    .expect("cursor read is infallible");
+                assert_eq!(haystack.get(offset + needle.len()..), Some(&rest[..]));
+            }
         }
     }
+
+    #[test]
+    fn read_until_missing_bstr() {
+        let mut section = Section::Offset(0, 0);
+        let err = super::read_find_bstr_chunked(
+            &mut Cursor::new(&b"mississippi"[..]),
+            &mut section,
+            b"ppp",
+            u64::MAX,
+            4,
+        )
+        .expect_err("needle does not exist in haystack");
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn read_until_bstr_within_limit() {
+        fn scan(needle: &[u8], limit: u64) -> (Option<u64>, u64) {
+            let mut cursor = Cursor::new(&b"mississippi"[..]);
+            let mut section = Section::Offset(0, 0);
+            let found = super::read_find_bstr_chunked(&mut cursor, &mut section, needle, limit, 4)
+                .ok()
+                .map(|()| section.len());
+            (found, cursor.position())
+        }
+
+        assert_eq!(scan(b"ssi", 5), (Some(5), 5));
+        assert_eq!(scan(b"sip", 8), (None, 8));
+    }
 }
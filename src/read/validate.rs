@@ -0,0 +1,357 @@
+//! Structural validation of phar files
+
+use std::borrow::Cow;
+use std::error;
+use std::fmt;
+use std::io::{self, ErrorKind, Read, Result, Seek, SeekFrom};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use takes::Ext;
+
+use super::trailer::{parse_trailer, signature_offset};
+use super::{Entry, Options, Section};
+use crate::path::{self, NameError};
+use crate::util::tell;
+use crate::{ApiVersion, GlobalFlags};
+
+/// The bits of the global and entry flags that indicate compression.
+const COMPRESSION_MASK: u32 = GlobalFlags::COMPRESSION_MASK.bits();
+
+/// A structural inconsistency in a phar file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// The offset in the phar file of the field or section concerned
+    pub offset: u64,
+    /// The inconsistency found
+    pub kind: DiagnosticKind,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at offset {:#x}: {}", self.offset, self.kind)
+    }
+}
+
+/// The kinds of structural inconsistency reported in a `Diagnostic`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DiagnosticKind {
+    /// The manifest size does not match the size of the parsed manifest.
+    ManifestSize {
+        /// The manifest size declared in the file
+        declared: u32,
+        /// The number of bytes actually parsed
+        parsed: u64,
+    },
+    /// The API version is not supported.
+    ApiVersion {
+        /// The API version stored in the file
+        version: ApiVersion,
+    },
+    /// The compression bits of the global flags
+    /// do not match the compression methods used by the entries.
+    GlobalCompression {
+        /// The global flags
+        flags: u32,
+        /// The union of compression bits of all entries
+        entries: u32,
+    },
+    /// An entry declares an unknown combination of compression bits.
+    EntryCompression {
+        /// The entry flags
+        flags: u32,
+    },
+    /// An uncompressed entry has different compressed and uncompressed sizes.
+    UncompressedSize {
+        /// The compressed size declared in the entry
+        compressed: u32,
+        /// The uncompressed size declared in the entry
+        original: u32,
+    },
+    /// The file contents do not end where the signature (or the file) starts.
+    ContentsEnd {
+        /// The end of file contents computed from the entry sizes
+        end: u64,
+        /// The offset of the signature, or the file size if there is no signature
+        expected: u64,
+    },
+    /// The global flags declare a signature, but the file has no valid signature trailer.
+    MissingSignature,
+    /// The file has a signature trailer, but the global flags do not declare a signature.
+    UndeclaredSignature,
+    /// An entry name is not normalized, so PHP cannot access the entry.
+    InaccessibleName {
+        /// The entry name stored in the file
+        name: Vec<u8>,
+        /// The reason the name cannot be normalized,
+        /// or `None` if it normalizes to a different name
+        error: Option<NameError>,
+    },
+}
+
+impl fmt::Display for DiagnosticKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ManifestSize { declared, parsed } => write!(
+                f,
+                "manifest size is declared as {} but {} bytes were parsed",
+                declared, parsed
+            ),
+            Self::ApiVersion { version } => write!(f, "unsupported API version {}", version),
+            Self::GlobalCompression { flags, entries } => write!(
+                f,
+                "global flags {:#x} do not match entry compression bits {:#x}",
+                flags, entries
+            ),
+            Self::EntryCompression { flags } => {
+                write!(f, "entry flags {:#x} declare unknown compression", flags)
+            }
+            Self::UncompressedSize {
+                compressed,
+                original,
+            } => write!(
+                f,
+                "uncompressed entry has compressed size {} but original size {}",
+                compressed, original
+            ),
+            Self::ContentsEnd { end, expected } => write!(
+                f,
+                "file contents end at {:#x} instead of {:#x}",
+                end, expected
+            ),
+            Self::MissingSignature => write!(f, "declared signature is missing"),
+            Self::UndeclaredSignature => write!(f, "signature is not declared in global flags"),
+            Self::InaccessibleName { name, error } => {
+                write!(
+                    f,
+                    "entry name {:?} cannot be accessed from PHP",
+                    String::from_utf8_lossy(name)
+                )?;
+                if let Some(error) = error {
+                    write!(f, ": {}", error)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Error returned by `Reader::read` in strict mode
+/// if the phar file has structural inconsistencies.
+///
+/// This is wrapped in an `io::Error` of kind `InvalidData`,
+/// and can be retrieved with `io::Error::get_ref` and `downcast_ref`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl ValidationError {
+    /// Returns all inconsistencies found in the file.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} structural inconsistencies", self.diagnostics.len())?;
+        if let Some(first) = self.diagnostics.first() {
+            write!(f, ", first {}", first)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for ValidationError {}
+
+impl From<ValidationError> for io::Error {
+    fn from(err: ValidationError) -> Self {
+        io::Error::new(ErrorKind::InvalidData, err)
+    }
+}
+
+/// Returns an error if `diagnostics` is not empty.
+pub(super) fn strict(diagnostics: Vec<Diagnostic>) -> Result<()> {
+    if diagnostics.is_empty() {
+        Ok(())
+    } else {
+        Err(ValidationError { diagnostics }.into())
+    }
+}
+
+/// The values collected from the manifest to validate the rest of the file.
+pub(super) struct ManifestSummary {
+    flags_offset: u64,
+    flags: u32,
+    contents_end: u64,
+}
+
+/// Validates the manifest, where `read` is positioned at the manifest size field.
+pub(super) fn validate_manifest(
+    read: &mut (impl Read + Seek),
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<ManifestSummary> {
+    let manifest_offset = tell(&mut *read)?;
+    let manifest_size = read.read_u32::<LittleEndian>()?;
+    let mut manifest = read.takes(manifest_size.into())?;
+
+    let num_files = manifest.read_u32::<LittleEndian>()?;
+
+    let api_offset = tell(&mut manifest)?;
+    let mut api = [0u8; 2];
+    manifest.read_exact(&mut api[..])?;
+    let version = ApiVersion::from_bytes(api);
+    if !version.is_supported() {
+        diagnostics.push(Diagnostic {
+            offset: api_offset,
+            kind: DiagnosticKind::ApiVersion { version },
+        });
+    }
+
+    let flags_offset = tell(&mut manifest)?;
+    let flags = manifest.read_u32::<LittleEndian>()?;
+
+    for _ in 0..2 {
+        // alias and metadata
+        let len = manifest.read_u32::<LittleEndian>()?;
+        let _ = manifest.seek(SeekFrom::Current(len.into()))?;
+    }
+
+    let mut entry_compression = 0;
+    let mut contents_size = 0u64;
+    for _ in 0..num_files {
+        let entry_offset = tell(&mut manifest)?;
+        let entry = Entry::parse(&mut manifest, true, false, &Options::default())?;
+
+        if let Section::Cached(name) = &entry.name {
+            let error = match path::normalize(name) {
+                Ok(Cow::Borrowed(_)) => None,
+                Ok(Cow::Owned(_)) => Some(None),
+                Err(err) => Some(Some(err)),
+            };
+            if let Some(error) = error {
+                diagnostics.push(Diagnostic {
+                    offset: entry_offset,
+                    kind: DiagnosticKind::InaccessibleName {
+                        name: name.clone(),
+                        error,
+                    },
+                });
+            }
+        }
+
+        let compression = entry.flags & COMPRESSION_MASK;
+        entry_compression |= compression;
+        match compression {
+            0 => {
+                if entry.compressed_file_size != entry.original_file_size {
+                    diagnostics.push(Diagnostic {
+                        offset: entry_offset,
+                        kind: DiagnosticKind::UncompressedSize {
+                            compressed: entry.compressed_file_size,
+                            original: entry.original_file_size,
+                        },
+                    });
+                }
+            }
+            0x1000 | 0x2000 => {}
+            _ => diagnostics.push(Diagnostic {
+                offset: entry_offset,
+                kind: DiagnosticKind::EntryCompression { flags: entry.flags },
+            }),
+        }
+
+        contents_size += u64::from(entry.compressed_file_size);
+    }
+
+    if flags & COMPRESSION_MASK != entry_compression {
+        diagnostics.push(Diagnostic {
+            offset: flags_offset,
+            kind: DiagnosticKind::GlobalCompression {
+                flags,
+                entries: entry_compression,
+            },
+        });
+    }
+
+    let contents_start = tell(&mut manifest)?;
+    let parsed = contents_start - manifest_offset - 4;
+    if parsed != u64::from(manifest_size) {
+        diagnostics.push(Diagnostic {
+            offset: manifest_offset,
+            kind: DiagnosticKind::ManifestSize {
+                declared: manifest_size,
+                parsed,
+            },
+        });
+    }
+
+    Ok(ManifestSummary {
+        flags_offset,
+        flags,
+        contents_end: contents_start + contents_size,
+    })
+}
+
+/// Validates the file contents and signature against the manifest.
+///
+/// `trailer` is the last 8 bytes of the file, or `None` if the file is shorter.
+pub(super) fn validate_trailer(
+    summary: &ManifestSummary,
+    file_len: u64,
+    trailer: Option<[u8; 8]>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let sig_offset = trailer
+        .and_then(|trailer| parse_trailer(trailer).ok())
+        .and_then(|algorithm| signature_offset(file_len, algorithm));
+
+    let declared = GlobalFlags::from_bits_truncate(summary.flags).contains(GlobalFlags::SIGNATURE);
+    match (declared, sig_offset) {
+        (true, None) => diagnostics.push(Diagnostic {
+            offset: summary.flags_offset,
+            kind: DiagnosticKind::MissingSignature,
+        }),
+        (false, Some(sig_offset)) => diagnostics.push(Diagnostic {
+            offset: sig_offset,
+            kind: DiagnosticKind::UndeclaredSignature,
+        }),
+        _ => {}
+    }
+
+    let expected = sig_offset.unwrap_or(file_len);
+    if summary.contents_end != expected {
+        diagnostics.push(Diagnostic {
+            offset: summary.contents_end.min(expected),
+            kind: DiagnosticKind::ContentsEnd {
+                end: summary.contents_end,
+                expected,
+            },
+        });
+    }
+}
+
+/// Validates the structure of a phar file, where the manifest starts at `manifest_offset`.
+pub(super) fn validate(
+    read: &mut (impl Read + Seek),
+    manifest_offset: u64,
+) -> Result<Vec<Diagnostic>> {
+    let mut diagnostics = Vec::new();
+
+    let _ = read.seek(SeekFrom::Start(manifest_offset))?;
+    let summary = validate_manifest(read, &mut diagnostics)?;
+
+    let file_len = read.seek(SeekFrom::End(0))?;
+    let trailer = if file_len >= 8 {
+        let _ = read.seek(SeekFrom::End(-8))?;
+        let mut trailer = [0u8; 8];
+        read.read_exact(&mut trailer[..])?;
+        Some(trailer)
+    } else {
+        None
+    };
+    validate_trailer(&summary, file_len, trailer, &mut diagnostics);
+
+    Ok(diagnostics)
+}
@@ -0,0 +1,209 @@
+//! Rewriting a phar archive with different compression, signature or format
+//!
+//! Entries whose compression is unchanged are copied without decompression,
+//! so only the entries that are actually recompressed are decompressed.
+//! The output can be a native, tar or zip based phar, as selected by `Options::format`.
+//! To repack a tar or zip based phar,
+//! first build a native phar from `write::TarSource` or `write::ZipSource`.
+
+use std::convert::TryFrom;
+use std::io::{self, Read, Result, Seek, SeekFrom, Write};
+
+use typed_builder::TypedBuilder;
+
+use crate::read::{index, Glob};
+use crate::util::{tell, Crc32Read, PERMISSION_MASK, STUB_TERMINATOR};
+use crate::{Compression, Reader, Signature};
+
+#[cfg(feature = "tar")]
+mod tar_output;
+mod zip_output;
+
+/// The file format of a repacked phar
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    /// The native phar format
+    #[default]
+    Phar,
+    /// A tar archive with the stub, alias and all metadata in the `.phar` directory
+    ///
+    /// Tar based phars cannot compress individual entries,
+    /// so all entries are decompressed,
+    /// and compression other than `Compression::None` is rejected.
+    #[cfg(feature = "tar")]
+    #[cfg_attr(feature = "docsrs", doc(cfg(feature = "tar")))]
+    Tar,
+    /// A zip archive with the stub and alias in the `.phar` directory,
+    /// the global metadata in the archive comment
+    /// and the entry metadata in the file comments
+    ///
+    /// Zip archives store modification times without a time zone,
+    /// so they are written in UTC.
+    Zip,
+}
+
+/// Compresses the files whose names match a glob pattern with a specific method.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    glob: Glob,
+    compression: Compression,
+}
+
+impl Rule {
+    /// Creates a rule compressing the files matching `glob` with `compression`.
+    pub fn new(glob: Glob, compression: Compression) -> Self {
+        Self { glob, compression }
+    }
+}
+
+/// Options for repacking a phar archive
+#[derive(Debug, TypedBuilder)]
+pub struct Options {
+    /// The compression method for files not matched by any rule
+    ///
+    /// Default `None`, which keeps the original compression of each file
+    /// and copies its contents without recompression.
+    #[builder(default, setter(strip_option))]
+    compression: Option<Compression>,
+
+    /// Per-file compression rules
+    ///
+    /// The first rule whose glob matches the file name is used.
+    /// Default empty.
+    #[builder(default)]
+    rules: Vec<Rule>,
+
+    /// The format of the output
+    ///
+    /// Default `Format::Phar`.
+    #[builder(default)]
+    format: Format,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl Options {
+    /// Returns the compression for the file `name`, or `None` to keep its original compression.
+    fn compression_for(&self, name: &[u8]) -> Option<Compression> {
+        self.rules
+            .iter()
+            .find(|rule| rule.glob.is_match(name))
+            .map(|rule| rule.compression)
+            .or(self.compression)
+    }
+}
+
+/// Rewrites the archive read by `input` into `output`, signed with `signature`.
+///
+/// The stub, alias, global metadata
+/// and the names, modification times, permissions and metadata of all entries are preserved.
+/// Tar and zip based phars are signed like PHP does,
+/// with the signature stored in `.phar/signature.bin`.
+/// Entries are written in the iteration order of the index of `input`,
+/// so use an index that preserves manifest order, such as `index::NameIndexMap`,
+/// to keep the order of the original archive.
+///
+/// Recompressed files are checked against the CRC32 checksum in the manifest,
+/// and an `InvalidData` error is returned on mismatch.
+pub fn repack<R, I, W>(
+    input: &mut Reader<R, I>,
+    output: W,
+    signature: Signature,
+    options: Options,
+) -> Result<()>
+where
+    R: Read + Seek,
+    I: index::Iterable,
+    W: Read + Write + Seek,
+{
+    match options.format {
+        Format::Phar => repack_native(input, output, signature, &options),
+        #[cfg(feature = "tar")]
+        Format::Tar => tar_output::repack(input, output, signature, &options),
+        Format::Zip => zip_output::repack(input, output, signature, &options),
+    }
+}
+
+fn repack_native<R, I, W>(
+    input: &mut Reader<R, I>,
+    output: W,
+    signature: Signature,
+    options: &Options,
+) -> Result<()>
+where
+    R: Read + Seek,
+    I: index::Iterable,
+    W: Read + Write + Seek,
+{
+    let stub = input.stub_bytes()?.as_ref().to_vec();
+    let stub = stub.strip_suffix(STUB_TERMINATOR).unwrap_or(&stub);
+    let alias = input.alias_bytes()?.as_ref().to_vec();
+    let metadata = input.metadata_bytes()?.as_ref().to_vec();
+
+    let mut writer = crate::write::create(output, signature)
+        .stub(stub)?
+        .alias(&alias[..])?
+        .metadata(&metadata[..])?;
+    input.for_each_entry(|mut entry| {
+        let info = entry.info();
+        let entry_metadata = entry.metadata()?;
+        match options.compression_for(entry.name()) {
+            Some(compression) if !entry.is_dir() => writer.entry(
+                entry.name(),
+                &entry_metadata[..],
+                info.modified(),
+                info.flags() & PERMISSION_MASK,
+                compression,
+            ),
+            _ => writer.raw_entry(entry.name(), &entry_metadata[..], info.into()),
+        }
+    })?;
+
+    let mut contents = writer.contents()?;
+    // the index is iterated in the same order as when writing the manifest
+    input.for_each_entry(|mut entry| {
+        if entry.is_dir() {
+            return Ok(());
+        }
+        match options.compression_for(entry.name()) {
+            Some(_) => {
+                let name = entry.name();
+                let expected = entry.info().crc32();
+                contents.feed(Crc32Read::new(entry.open()?, name, expected))
+            }
+            None => contents.feed_raw(entry.open_raw()?),
+        }
+    })?;
+
+    Ok(())
+}
+
+/// Writes the bytes of `stream` from `start` to the current position into `signature`.
+///
+/// The stream is left at the position it was at before signing.
+fn sign_written(mut stream: impl Read + Seek, start: u64, signature: &mut Signature) -> Result<()> {
+    let end = tell(&mut stream)?;
+    let _ = stream.seek(SeekFrom::Start(start))?;
+    let _ = io::copy(&mut (&mut stream).take(end - start), &mut signature.write())?;
+    let _ = stream.seek(SeekFrom::Start(end))?;
+    Ok(())
+}
+
+/// Returns the contents of `.phar/signature.bin` for the data written into `signature`.
+fn signature_file(signature: Signature) -> Vec<u8> {
+    let flags = signature.to_u32();
+    let digest = signature.finalize();
+    let mut contents = Vec::with_capacity(digest.len() + 8);
+    contents.extend_from_slice(&flags.to_le_bytes());
+    contents.extend_from_slice(
+        &u32::try_from(digest.len())
+            .expect("signatures are shorter than 64 bytes")
+            .to_le_bytes(),
+    );
+    contents.extend_from_slice(&digest);
+    contents
+}
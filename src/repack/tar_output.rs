@@ -0,0 +1,126 @@
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind, Read, Result, Seek, Write};
+use std::path::PathBuf;
+
+use super::{sign_written, signature_file, Options};
+use crate::read::index;
+use crate::util::{tell, Crc32Read, PERMISSION_MASK};
+use crate::write::phar_dir;
+use crate::{Compression, Reader, Signature};
+
+/// Writes a tar based phar.
+pub(super) fn repack<R, I, W>(
+    input: &mut Reader<R, I>,
+    mut output: W,
+    mut signature: Signature,
+    options: &Options,
+) -> Result<()>
+where
+    R: Read + Seek,
+    I: index::Iterable,
+    W: Read + Write + Seek,
+{
+    if options
+        .rules
+        .iter()
+        .map(|rule| rule.compression)
+        .chain(options.compression)
+        .any(|compression| compression != Compression::None)
+    {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "tar based phars cannot compress individual entries",
+        ));
+    }
+
+    let stub = input.stub_bytes()?.as_ref().to_vec();
+    let alias = input.alias_bytes()?.as_ref().to_vec();
+    let metadata = input.metadata_bytes()?.as_ref().to_vec();
+
+    let start = tell(&mut output)?;
+    let mut builder = tar::Builder::new(output);
+    append_file(&mut builder, phar_dir::STUB.as_bytes(), &stub)?;
+    if !alias.is_empty() {
+        append_file(&mut builder, phar_dir::ALIAS.as_bytes(), &alias)?;
+    }
+    if !metadata.is_empty() {
+        append_file(&mut builder, phar_dir::METADATA.as_bytes(), &metadata)?;
+    }
+
+    input.for_each_entry(|mut entry| {
+        let info = entry.info();
+        let entry_metadata = entry.metadata()?;
+        let name = entry.name();
+
+        let mut header = tar::Header::new_ustar();
+        header.set_mode(info.flags() & PERMISSION_MASK);
+        header.set_mtime(u64::try_from(info.timestamp()).unwrap_or(0));
+        if entry.is_dir() {
+            header.set_entry_type(tar::EntryType::Directory);
+            header.set_size(0);
+            builder.append_data(&mut header, entry_path(name)?, &[][..])?;
+        } else {
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_size(u64::from(info.original_size()));
+            let contents = Crc32Read::new(entry.open()?, name, info.crc32());
+            builder.append_data(&mut header, entry_path(name)?, contents)?;
+        }
+
+        if !entry_metadata.is_empty() {
+            let owner = name.strip_suffix(b"/").unwrap_or(name);
+            let metadata_name = [
+                phar_dir::ENTRY_METADATA_PREFIX.as_bytes(),
+                owner,
+                phar_dir::ENTRY_METADATA_SUFFIX.as_bytes(),
+            ]
+            .concat();
+            append_file(&mut builder, &metadata_name, &entry_metadata)?;
+        }
+        Ok(())
+    })?;
+
+    // like PHP, the signature covers all entries before `.phar/signature.bin`
+    sign_written(builder.get_mut(), start, &mut signature)?;
+    append_file(
+        &mut builder,
+        phar_dir::SIGNATURE.as_bytes(),
+        &signature_file(signature),
+    )?;
+    let _ = builder.into_inner()?;
+    Ok(())
+}
+
+/// Appends a regular file in the `.phar` directory.
+fn append_file<W: Write>(
+    builder: &mut tar::Builder<W>,
+    name: &[u8],
+    contents: &[u8],
+) -> Result<()> {
+    let mut header = tar::Header::new_ustar();
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_mode(0o644);
+    header.set_mtime(0);
+    header.set_size(u64::try_from(contents.len()).expect("usize <= u64"));
+    builder.append_data(&mut header, entry_path(name)?, contents)
+}
+
+/// Converts an entry name into the path passed to the `tar` crate.
+fn entry_path(name: &[u8]) -> Result<PathBuf> {
+    #[cfg(unix)]
+    let path = {
+        use std::os::unix::ffi::OsStrExt;
+        PathBuf::from(std::ffi::OsStr::from_bytes(name))
+    };
+    #[cfg(not(unix))]
+    let path = PathBuf::from(std::str::from_utf8(name).map_err(|err| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "name {:?} cannot be written on this platform: {}",
+                String::from_utf8_lossy(name),
+                err
+            ),
+        )
+    })?);
+    Ok(path)
+}
@@ -0,0 +1,336 @@
+use std::convert::TryFrom;
+use std::io::{self, Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
+use std::time::SystemTime;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use super::{sign_written, signature_file, Options};
+use crate::read::index;
+use crate::util::{tell, Crc32Read, PERMISSION_MASK};
+use crate::write::phar_dir;
+use crate::{CompressionMethod, Reader, Signature};
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0605_4b50;
+
+/// Version 2.0 on Unix, so that readers use the permissions in the external attributes
+const VERSION_MADE_BY: u16 = (3 << 8) | 20;
+const VERSION_DEFLATE: u16 = 20;
+const VERSION_BZIP2: u16 = 46;
+
+/// The general purpose flag indicating UTF-8 names and comments
+const FLAG_UTF8: u16 = 1 << 11;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+const METHOD_BZIP2: u16 = 12;
+
+const UNIX_DIRECTORY: u32 = 0o040_000;
+const UNIX_REGULAR_FILE: u32 = 0o100_000;
+const DOS_DIRECTORY: u32 = 0x10;
+
+/// Writes a zip based phar.
+pub(super) fn repack<R, I, W>(
+    input: &mut Reader<R, I>,
+    output: W,
+    signature: Signature,
+    options: &Options,
+) -> Result<()>
+where
+    R: Read + Seek,
+    I: index::Iterable,
+    W: Read + Write + Seek,
+{
+    let stub = input.stub_bytes()?.as_ref().to_vec();
+    let alias = input.alias_bytes()?.as_ref().to_vec();
+    let metadata = input.metadata_bytes()?.as_ref().to_vec();
+
+    let mut zip = ZipWriter::new(output)?;
+    zip.add_stored(phar_dir::STUB.as_bytes(), &stub)?;
+    if !alias.is_empty() {
+        zip.add_stored(phar_dir::ALIAS.as_bytes(), &alias)?;
+    }
+
+    input.for_each_entry(|mut entry| {
+        let info = entry.info();
+        let entry_metadata = entry.metadata()?;
+        let name = entry.name();
+        let mut header = FileHeader {
+            name: name.to_vec(),
+            comment: &entry_metadata,
+            modified: info.modified(),
+            mode: info.flags() & PERMISSION_MASK,
+            is_dir: entry.is_dir(),
+        };
+
+        if entry.is_dir() {
+            if !header.name.ends_with(b"/") {
+                header.name.push(b'/');
+            }
+            return zip.add(&header, CompressionMethod::None, |_| Ok((0, 0)));
+        }
+
+        match options.compression_for(name) {
+            Some(compression) => zip.add(&header, compression.method(), |write| {
+                let expected = info.crc32();
+                let mut comp_write = compression.make_write(write)?;
+                let size = io::copy(
+                    &mut Crc32Read::new(entry.open()?, name, expected),
+                    &mut comp_write,
+                )?;
+                drop(comp_write);
+                Ok((expected, size))
+            }),
+            None => zip.add(&header, info.compression(), |write| {
+                let _ = io::copy(&mut entry.open_raw()?, write)?;
+                Ok((info.crc32(), u64::from(info.original_size())))
+            }),
+        }
+    })?;
+
+    zip.finish(&metadata, signature)
+}
+
+/// The fields of a file in the local and central headers
+struct FileHeader<'t> {
+    name: Vec<u8>,
+    comment: &'t [u8],
+    modified: SystemTime,
+    mode: u32,
+    is_dir: bool,
+}
+
+/// Writes zip entries and keeps the central directory in memory until `finish`.
+struct ZipWriter<W> {
+    write: W,
+    /// The position of the start of the archive in `write`
+    start: u64,
+    central: Vec<u8>,
+    count: u16,
+}
+
+impl<W: Read + Write + Seek> ZipWriter<W> {
+    fn new(mut write: W) -> Result<Self> {
+        let start = tell(&mut write)?;
+        Ok(Self {
+            write,
+            start,
+            central: Vec::new(),
+            count: 0,
+        })
+    }
+
+    /// Returns the current offset from the start of the archive.
+    fn offset(&mut self) -> Result<u32> {
+        u32::try_from(tell(&mut self.write)? - self.start).map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "archive is too large for zip format",
+            )
+        })
+    }
+
+    /// Writes an uncompressed file in the `.phar` directory.
+    fn add_stored(&mut self, name: &[u8], contents: &[u8]) -> Result<()> {
+        let header = FileHeader {
+            name: name.to_vec(),
+            comment: b"",
+            modified: SystemTime::UNIX_EPOCH,
+            mode: 0o644,
+            is_dir: false,
+        };
+        self.add(&header, CompressionMethod::None, |write| {
+            write.write_all(contents)?;
+            Ok((
+                crc32fast::hash(contents),
+                u64::try_from(contents.len()).expect("usize <= u64"),
+            ))
+        })
+    }
+
+    /// Writes a file whose data, compressed with `method`, is written by `data`,
+    /// which returns the CRC32 checksum and the size of the uncompressed contents.
+    fn add(
+        &mut self,
+        header: &FileHeader<'_>,
+        method: CompressionMethod,
+        data: impl FnOnce(&mut W) -> Result<(u32, u64)>,
+    ) -> Result<()> {
+        let name_len = u16::try_from(header.name.len()).map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "name {:?} is too long for zip format",
+                    String::from_utf8_lossy(&header.name)
+                ),
+            )
+        })?;
+        let comment_len = u16::try_from(header.comment.len()).map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "metadata of {:?} is too long for zip format",
+                    String::from_utf8_lossy(&header.name)
+                ),
+            )
+        })?;
+        self.count = self.count.checked_add(1).ok_or_else(|| {
+            Error::new(ErrorKind::InvalidInput, "too many entries for zip format")
+        })?;
+
+        let (method, version) = match method {
+            CompressionMethod::None => (METHOD_STORED, VERSION_DEFLATE),
+            CompressionMethod::Zlib => (METHOD_DEFLATED, VERSION_DEFLATE),
+            CompressionMethod::Bzip => (METHOD_BZIP2, VERSION_BZIP2),
+        };
+        let flags = if std::str::from_utf8(&header.name).is_ok()
+            && std::str::from_utf8(header.comment).is_ok()
+        {
+            FLAG_UTF8
+        } else {
+            0
+        };
+        let (time, date) = dos_time(header.modified);
+
+        let offset = self.offset()?;
+        self.write
+            .write_u32::<LittleEndian>(LOCAL_HEADER_SIGNATURE)?;
+        self.write.write_u16::<LittleEndian>(version)?;
+        self.write.write_u16::<LittleEndian>(flags)?;
+        self.write.write_u16::<LittleEndian>(method)?;
+        self.write.write_u16::<LittleEndian>(time)?;
+        self.write.write_u16::<LittleEndian>(date)?;
+        let sizes_position = tell(&mut self.write)?;
+        self.write.write_all(&[0; 12])?; // crc32 and sizes, written after the data
+        self.write.write_u16::<LittleEndian>(name_len)?;
+        self.write.write_u16::<LittleEndian>(0)?; // extra field length
+        self.write.write_all(&header.name)?;
+
+        let data_start = tell(&mut self.write)?;
+        let (crc32, uncompressed_size) = data(&mut self.write)?;
+        let data_end = tell(&mut self.write)?;
+        let too_large = || {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "{:?} is too large for zip format",
+                    String::from_utf8_lossy(&header.name)
+                ),
+            )
+        };
+        let compressed_size = u32::try_from(data_end - data_start).map_err(|_| too_large())?;
+        let uncompressed_size = u32::try_from(uncompressed_size).map_err(|_| too_large())?;
+
+        let _ = self.write.seek(SeekFrom::Start(sizes_position))?;
+        self.write.write_u32::<LittleEndian>(crc32)?;
+        self.write.write_u32::<LittleEndian>(compressed_size)?;
+        self.write.write_u32::<LittleEndian>(uncompressed_size)?;
+        let _ = self.write.seek(SeekFrom::Start(data_end))?;
+
+        let file_type = if header.is_dir {
+            (UNIX_DIRECTORY << 16) | DOS_DIRECTORY
+        } else {
+            UNIX_REGULAR_FILE << 16
+        };
+
+        let central = &mut self.central;
+        central.write_u32::<LittleEndian>(CENTRAL_HEADER_SIGNATURE)?;
+        central.write_u16::<LittleEndian>(VERSION_MADE_BY)?;
+        central.write_u16::<LittleEndian>(version)?;
+        central.write_u16::<LittleEndian>(flags)?;
+        central.write_u16::<LittleEndian>(method)?;
+        central.write_u16::<LittleEndian>(time)?;
+        central.write_u16::<LittleEndian>(date)?;
+        central.write_u32::<LittleEndian>(crc32)?;
+        central.write_u32::<LittleEndian>(compressed_size)?;
+        central.write_u32::<LittleEndian>(uncompressed_size)?;
+        central.write_u16::<LittleEndian>(name_len)?;
+        central.write_u16::<LittleEndian>(0)?; // extra field length
+        central.write_u16::<LittleEndian>(comment_len)?;
+        central.write_u16::<LittleEndian>(0)?; // disk number
+        central.write_u16::<LittleEndian>(0)?; // internal attributes
+        central.write_u32::<LittleEndian>(file_type | (header.mode << 16))?;
+        central.write_u32::<LittleEndian>(offset)?;
+        central.write_all(&header.name)?;
+        central.write_all(header.comment)?;
+        Ok(())
+    }
+
+    /// Writes the signature, the central directory and the archive comment.
+    ///
+    /// Like PHP, the signature covers the local headers and data written so far,
+    /// the central directory entries written so far and the archive comment.
+    fn finish(mut self, comment: &[u8], mut signature: Signature) -> Result<()> {
+        let comment_len = u16::try_from(comment.len()).map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "metadata is too long for zip format",
+            )
+        })?;
+
+        sign_written(&mut self.write, self.start, &mut signature)?;
+        signature.write().write_all(&self.central)?;
+        signature.write().write_all(comment)?;
+        self.add_stored(phar_dir::SIGNATURE.as_bytes(), &signature_file(signature))?;
+
+        let central_offset = self.offset()?;
+        self.write.write_all(&self.central)?;
+        let central_size = u32::try_from(self.central.len()).map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "archive is too large for zip format",
+            )
+        })?;
+
+        self.write
+            .write_u32::<LittleEndian>(END_OF_CENTRAL_DIRECTORY_SIGNATURE)?;
+        self.write.write_u16::<LittleEndian>(0)?; // disk number
+        self.write.write_u16::<LittleEndian>(0)?; // disk with the central directory
+        self.write.write_u16::<LittleEndian>(self.count)?;
+        self.write.write_u16::<LittleEndian>(self.count)?;
+        self.write.write_u32::<LittleEndian>(central_size)?;
+        self.write.write_u32::<LittleEndian>(central_offset)?;
+        self.write.write_u16::<LittleEndian>(comment_len)?;
+        self.write.write_all(comment)?;
+        Ok(())
+    }
+}
+
+/// Converts a `SystemTime` into the MS-DOS time and date in UTC.
+///
+/// Times outside the range of MS-DOS dates (1980..=2107) are clamped.
+fn dos_time(modified: SystemTime) -> (u16, u16) {
+    let timestamp = modified
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let secs = timestamp % 86400;
+
+    // civil from days, counting years from March so that leap days are at the end
+    let days = timestamp / 86400 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let (year, month) = if month_from_march < 10 {
+        (era * 400 + year_of_era, month_from_march + 3)
+    } else {
+        (era * 400 + year_of_era + 1, month_from_march - 9)
+    };
+
+    if year < 1980 {
+        return (0, (1 << 5) | 1); // 1980-01-01 00:00:00
+    }
+    if year > 2107 {
+        return ((23 << 11) | (59 << 5) | 29, (127 << 9) | (12 << 5) | 31); // 2107-12-31 23:59:58
+    }
+    let time = ((secs / 3600) << 11) | ((secs / 60 % 60) << 5) | (secs % 60 / 2);
+    let date = ((year - 1980) << 9) | (month << 5) | day;
+    (
+        u16::try_from(time).expect("time fits in 16 bits"),
+        u16::try_from(date).expect("date is in 1980..=2107"),
+    )
+}
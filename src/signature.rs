@@ -1,3 +1,4 @@
+use std::fmt;
 use std::io::{Result, Write};
 
 use auto_enums::auto_enum;
@@ -26,6 +27,13 @@ pub enum Signature {
 }
 
 impl Signature {
+    /// Creates a signature of the specified algorithm
+    ///
+    /// Returns `None` if the feature for the algorithm is not enabled.
+    pub fn new(algorithm: SignatureAlgorithm) -> Option<Self> {
+        Self::from_u32(algorithm.to_u32())
+    }
+
     /// Creates an md5 signature
     #[cfg(feature = "sig-md5")]
     pub fn md5() -> Self {
@@ -79,20 +87,25 @@ impl Signature {
         }
     }
 
-    /// The number of bytes used for this signature
-    pub fn size(&self) -> u8 {
+    /// Returns the algorithm of this signature
+    pub fn algorithm(&self) -> SignatureAlgorithm {
         match self {
             #[cfg(feature = "sig-md5")]
-            Self::Md5(_) => 16,
+            Self::Md5(_) => SignatureAlgorithm::Md5,
             #[cfg(feature = "sig-sha1")]
-            Self::Sha1(_) => 20,
+            Self::Sha1(_) => SignatureAlgorithm::Sha1,
             #[cfg(feature = "sig-sha2")]
-            Self::Sha256(_) => 32,
+            Self::Sha256(_) => SignatureAlgorithm::Sha256,
             #[cfg(feature = "sig-sha2")]
-            Self::Sha512(_) => 64,
+            Self::Sha512(_) => SignatureAlgorithm::Sha512,
         }
     }
 
+    /// The number of bytes used for this signature
+    pub fn size(&self) -> u8 {
+        self.algorithm().size()
+    }
+
     /// Returns a `Write` that writes to the underlying digest
     pub fn write(&mut self) -> impl Write + '_ {
         #[allow(clippy::as_conversions)]
@@ -125,6 +138,66 @@ impl Signature {
     }
 }
 
+/// A phar signature algorithm
+///
+/// Unlike `Signature`, all variants are available regardless of features,
+/// because this is used to describe signatures in existing files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SignatureAlgorithm {
+    /// `Phar::MD5`
+    Md5,
+    /// `Phar::SHA1`
+    Sha1,
+    /// `Phar::SHA256`
+    Sha256,
+    /// `Phar::SHA512`
+    Sha512,
+}
+
+impl SignatureAlgorithm {
+    /// Returns the algorithm of the phar format flag
+    pub fn from_u32(discrim: u32) -> Option<Self> {
+        Some(match discrim {
+            1 => Self::Md5,
+            2 => Self::Sha1,
+            3 => Self::Sha256,
+            4 => Self::Sha512,
+            _ => return None,
+        })
+    }
+
+    /// Returns the phar format flag of the algorithm
+    pub fn to_u32(self) -> u32 {
+        match self {
+            Self::Md5 => 1,
+            Self::Sha1 => 2,
+            Self::Sha256 => 3,
+            Self::Sha512 => 4,
+        }
+    }
+
+    /// The number of bytes used for signatures of this algorithm
+    pub fn size(self) -> u8 {
+        match self {
+            Self::Md5 => 16,
+            Self::Sha1 => 20,
+            Self::Sha256 => 32,
+            Self::Sha512 => 64,
+        }
+    }
+}
+
+impl fmt::Display for SignatureAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Md5 => "MD5",
+            Self::Sha1 => "SHA-1",
+            Self::Sha256 => "SHA-256",
+            Self::Sha512 => "SHA-512",
+        })
+    }
+}
+
 pub enum MaybeDummy {
     Real(Signature),
     Dummy(NullDevice),
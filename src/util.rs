@@ -1,4 +1,6 @@
 use std::io::{self, Seek, SeekFrom};
+#[cfg(feature = "reader")]
+use std::io::{Error, ErrorKind, Read};
 
 /// Stub terminator
 pub const STUB_TERMINATOR: &[u8] = b"__HALT_COMPILER(); ?>\r\n";
@@ -6,6 +8,56 @@ pub const STUB_TERMINATOR: &[u8] = b"__HALT_COMPILER(); ?>\r\n";
 /// Stub terminator
 pub const PHAR_TERMINATOR: &[u8] = b"GBMB";
 
+/// The permission bits in entry flags and Unix file modes.
+pub const PERMISSION_MASK: u32 = 0o777;
+
 pub fn tell(mut seek: impl Seek) -> io::Result<u64> {
     seek.seek(SeekFrom::Current(0))
 }
+
+/// Passes read bytes through and checks their CRC32 checksum at the end of `inner`,
+/// failing with `InvalidData` on mismatch.
+#[cfg(feature = "reader")]
+pub struct Crc32Read<'t, R> {
+    inner: R,
+    hasher: crc32fast::Hasher,
+    name: &'t [u8],
+    expected: u32,
+}
+
+#[cfg(feature = "reader")]
+impl<'t, R: Read> Crc32Read<'t, R> {
+    /// Checks the contents of the entry `name` against the checksum `expected`.
+    pub fn new(inner: R, name: &'t [u8], expected: u32) -> Self {
+        Self {
+            inner,
+            hasher: crc32fast::Hasher::new(),
+            name,
+            expected,
+        }
+    }
+}
+
+#[cfg(feature = "reader")]
+impl<R: Read> Read for Crc32Read<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        if len == 0 && !buf.is_empty() {
+            let actual = self.hasher.clone().finalize();
+            if actual != self.expected {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "CRC32 mismatch in entry {:?}: expected {:08x}, got {:08x}",
+                        String::from_utf8_lossy(self.name),
+                        self.expected,
+                        actual
+                    ),
+                ));
+            }
+        }
+        self.hasher
+            .update(buf.get(..len).expect("len <= buf.len()"));
+        Ok(len)
+    }
+}
@@ -0,0 +1,20 @@
+use std::io::{Read, Result, Write};
+
+use crate::ArchiveCompression;
+
+/// Compresses a phar file as a whole, e.g. to create `.phar.gz` and `.phar.bz2` files.
+///
+/// `phar` shall be a complete phar file,
+/// such as the stream passed to `create` after the contents of the last entry are fed,
+/// rewound to the start.
+/// The signature is not changed,
+/// since PHP verifies it against the decompressed file.
+///
+/// Returns the uncompressed size of the phar file.
+pub fn compress_archive(
+    phar: impl Read,
+    output: impl Write,
+    compression: ArchiveCompression,
+) -> Result<u64> {
+    compression.compress(phar, output)
+}
@@ -0,0 +1,405 @@
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind, Result, SeekFrom, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::SystemTime;
+
+use tokio::io::{
+    self, AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt,
+};
+
+use super::util::{
+    add_unique_name, encode_timestamp, entry_flags, manifest_header, signature_trailer,
+    terminate_dir_name, Crc32Writer, EntryFields, GLOBAL_FLAGS,
+};
+use crate::signature::Signature;
+use crate::util::STUB_TERMINATOR;
+use crate::{path, Compression};
+
+/// Creates a phar file asynchronously.
+///
+/// This is the asynchronous counterpart of `create`,
+/// and the same constraints on `stream` apply.
+pub fn create_async<W>(stream: W, signature: Signature) -> AsyncNeedStub<W>
+where
+    W: AsyncRead + AsyncWrite + AsyncSeek + Unpin + Send,
+{
+    AsyncNeedStub { stream, signature }
+}
+
+/// Intermediate type for writing phar asynchronously.
+///
+/// Call `stub` to progress to the next builder step.
+pub struct AsyncNeedStub<W> {
+    stream: W,
+    signature: Signature,
+}
+
+impl<W> AsyncNeedStub<W>
+where
+    W: AsyncRead + AsyncWrite + AsyncSeek + Unpin + Send,
+{
+    /// Sets the stub for the phar archive.
+    ///
+    /// See `NeedStub::stub` for details.
+    pub async fn stub(mut self, mut stub: impl AsyncRead + Unpin) -> Result<AsyncNeedAlias<W>> {
+        let _ = io::copy(&mut stub, &mut self.stream).await?;
+        self.stream.write_all(STUB_TERMINATOR).await?;
+        let manifest_size_offset = self.stream.stream_position().await?;
+
+        self.stream
+            .write_all(&manifest_header(0, 0, GLOBAL_FLAGS)?)
+            .await?; // rewritten by `contents`
+
+        Ok(AsyncNeedAlias {
+            manifest_size_offset,
+            stream: self.stream,
+            signature: self.signature,
+        })
+    }
+}
+
+/// Intermediate type for writing phar asynchronously.
+///
+/// Call `alias` or `metadata` to progress to the next builder step.
+pub struct AsyncNeedAlias<W> {
+    manifest_size_offset: u64,
+    stream: W,
+    signature: Signature,
+}
+
+impl<W> AsyncNeedAlias<W>
+where
+    W: AsyncRead + AsyncWrite + AsyncSeek + Unpin + Send,
+{
+    /// Sets the alias for the phar archive.
+    pub async fn alias(mut self, alias: impl AsyncRead + Unpin) -> Result<AsyncNeedGlobMeta<W>> {
+        write_bstr(&mut self.stream, alias, "alias is too long").await?;
+        Ok(AsyncNeedGlobMeta {
+            manifest_size_offset: self.manifest_size_offset,
+            stream: self.stream,
+            signature: self.signature,
+        })
+    }
+
+    /// Sets the metadata for the phar archive.
+    ///
+    /// See `NeedAlias::metadata` for details.
+    pub async fn metadata(self, metadata: impl AsyncRead + Unpin) -> Result<AsyncNeedEntries<W>> {
+        self.alias(io::empty()).await?.metadata(metadata).await
+    }
+}
+
+/// Intermediate type for writing phar asynchronously.
+pub struct AsyncNeedGlobMeta<W> {
+    manifest_size_offset: u64,
+    stream: W,
+    signature: Signature,
+}
+
+impl<W> AsyncNeedGlobMeta<W>
+where
+    W: AsyncRead + AsyncWrite + AsyncSeek + Unpin + Send,
+{
+    /// Sets the metadata for the phar archive.
+    ///
+    /// See `NeedGlobMeta::metadata` for details.
+    pub async fn metadata(
+        mut self,
+        metadata: impl AsyncRead + Unpin,
+    ) -> Result<AsyncNeedEntries<W>> {
+        write_bstr(&mut self.stream, metadata, "metadata is too long").await?;
+        Ok(AsyncNeedEntries {
+            manifest_size_offset: self.manifest_size_offset,
+            stream: self.stream,
+            signature: self.signature,
+            entries: Vec::new(),
+            names: HashSet::new(),
+            num_dirs: 0,
+            global_flags: GLOBAL_FLAGS,
+        })
+    }
+}
+
+/// Preparation step for writing phar entries asynchronously.
+///
+/// See `NeedEntries` for details.
+pub struct AsyncNeedEntries<W> {
+    manifest_size_offset: u64,
+    stream: W,
+    signature: Signature,
+    entries: Vec<WriteEntry>,
+    /// The names of all entries added so far, used to reject duplicates
+    names: HashSet<Vec<u8>>,
+    num_dirs: usize,
+    global_flags: u32,
+}
+
+impl<W> AsyncNeedEntries<W>
+where
+    W: AsyncRead + AsyncWrite + AsyncSeek + Unpin + Send,
+{
+    /// Adds an entry to the phar.
+    ///
+    /// The file contents shall be later passed with the `AsyncContents::feed` method in the same order.
+    /// The name is normalized with `path::normalize`.
+    /// Returns an `InvalidInput` error if the name is invalid
+    /// or an entry with the same normalized name was already added.
+    pub async fn entry(
+        &mut self,
+        mut name: impl AsyncRead + Unpin,
+        metadata: impl AsyncRead + Unpin,
+        timestamp: SystemTime,
+        mode: u32,
+        compression: Compression,
+    ) -> Result<()> {
+        let mut raw_name = Vec::new();
+        let _ = name.read_to_end(&mut raw_name).await?;
+        let file_name = path::normalize(&raw_name)?.into_owned();
+        add_unique_name(&mut self.names, &file_name)?;
+        write_bstr(&mut self.stream, &file_name[..], "file name is too long").await?;
+        let fields_offset = self.stream.stream_position().await?;
+        let fields = EntryFields {
+            timestamp: encode_timestamp(timestamp),
+            flags: entry_flags(mode, compression),
+            ..EntryFields::default()
+        };
+        self.stream.write_all(&fields.to_bytes()).await?; // sizes and crc32 rewritten by `feed`
+
+        self.global_flags |= compression.bit();
+
+        write_bstr(&mut self.stream, metadata, "file metadata is too large").await?;
+
+        self.entries.push(WriteEntry {
+            fields_offset,
+            fields,
+            compression,
+        });
+
+        Ok(())
+    }
+
+    /// Adds a directory entry to the phar.
+    ///
+    /// See `NeedEntries::directory` for details.
+    pub async fn directory(
+        &mut self,
+        mut name: impl AsyncRead + Unpin,
+        metadata: impl AsyncRead + Unpin,
+        timestamp: SystemTime,
+        mode: u32,
+    ) -> Result<()> {
+        let mut raw_name = Vec::new();
+        let _ = name.read_to_end(&mut raw_name).await?;
+        let mut dir_name = path::normalize(&raw_name)?.into_owned();
+        terminate_dir_name(&mut dir_name);
+        add_unique_name(&mut self.names, &dir_name)?;
+        write_bstr(&mut self.stream, &dir_name[..], "file name is too long").await?;
+
+        let fields = EntryFields {
+            timestamp: encode_timestamp(timestamp),
+            flags: entry_flags(mode, Compression::None),
+            ..EntryFields::default()
+        };
+        self.stream.write_all(&fields.to_bytes()).await?; // no contents, so the crc32 is also zero
+
+        write_bstr(&mut self.stream, metadata, "file metadata is too large").await?;
+
+        self.num_dirs += 1;
+
+        Ok(())
+    }
+
+    /// Starts writing the contents section of the phar.
+    ///
+    /// See `NeedEntries::contents` for details.
+    pub async fn contents(mut self) -> Result<AsyncContents<W>> {
+        let content_offset = self.stream.stream_position().await?;
+        let manifest_size = content_offset - (self.manifest_size_offset + 4);
+
+        let _ = self
+            .stream
+            .seek(SeekFrom::Start(self.manifest_size_offset))
+            .await?;
+        let header = manifest_header(
+            manifest_size,
+            self.entries.len() + self.num_dirs,
+            self.global_flags,
+        )?;
+        self.stream.write_all(&header).await?;
+
+        let mut signature = Some(self.signature);
+        if self.entries.is_empty() {
+            // there will be no `feed` calls to write the signature
+            let signature = signature.take().expect("just created");
+            write_signature(&mut self.stream, content_offset, signature).await?;
+        }
+
+        Ok(AsyncContents {
+            stream: self.stream,
+            entries: self.entries,
+            ptr: Some(0),
+            signature,
+            end_offset: content_offset,
+        })
+    }
+}
+
+struct WriteEntry {
+    /// The offset of the fields written before the contents are known
+    fields_offset: u64,
+    fields: EntryFields,
+    compression: Compression,
+}
+
+/// Step for writing phar file contents asynchronously.
+///
+/// See `Contents` for details.
+pub struct AsyncContents<W> {
+    stream: W,
+    entries: Vec<WriteEntry>,
+    ptr: Option<usize>,
+    signature: Option<Signature>,
+    end_offset: u64,
+}
+
+impl<W> AsyncContents<W>
+where
+    W: AsyncRead + AsyncWrite + AsyncSeek + Unpin + Send,
+{
+    /// Passes the content source for the next file entry.
+    pub async fn feed(&mut self, read: impl AsyncRead + Unpin) -> Result<()> {
+        let ptr = match self.ptr {
+            Some(ptr) => ptr,
+            None => {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    "feed() called again after returning Err",
+                ))
+            }
+        };
+        let entry = match self.entries.get(ptr) {
+            Some(entry) => entry,
+            None => return Err(Error::new(ErrorKind::Other, "feed() called too many times")),
+        };
+        let ret = try_feed(entry, read, &mut self.stream, self.end_offset).await;
+        match ret {
+            Ok(new_end_offset) => {
+                self.ptr = Some(ptr + 1);
+                if self.entries.get(ptr + 1).is_none() {
+                    let signature = self.signature.take().expect("last call");
+                    write_signature(&mut self.stream, new_end_offset, signature).await?;
+                }
+                self.end_offset = new_end_offset;
+                Ok(())
+            }
+            Err(err) => {
+                self.ptr = None;
+                Err(err)
+            }
+        }
+    }
+}
+
+async fn try_feed(
+    entry: &WriteEntry,
+    mut read: impl AsyncRead + Unpin,
+    write: &mut (impl AsyncWrite + AsyncSeek + Unpin + Send),
+    start_offset: u64,
+) -> Result<u64> {
+    let start = write.seek(SeekFrom::Start(start_offset)).await?;
+
+    let mut cksum = Crc32Writer::default();
+    let mut uncompressed_size = 0u64;
+    {
+        let mut comp_write = entry
+            .compression
+            .make_async_write(NoShutdown(&mut *write))?;
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let read = read.read(&mut buf[..]).await?;
+            if read == 0 {
+                break;
+            }
+            let chunk = buf.get(..read).expect("read <= buf.len()");
+            cksum.write_all(chunk)?;
+            comp_write.write_all(chunk).await?;
+            uncompressed_size += u64::try_from(read).expect("usize <= u64");
+        }
+        // finishes the compressed stream without shutting down `write`
+        comp_write.shutdown().await?;
+    }
+
+    let end = write.stream_position().await?;
+    let mut fields = entry.fields;
+    fields.set_contents(uncompressed_size, end - start, cksum.finish())?;
+    let _ = write.seek(SeekFrom::Start(entry.fields_offset)).await?;
+    write.write_all(&fields.to_bytes()).await?;
+
+    Ok(end)
+}
+
+async fn write_signature(
+    stream: &mut (impl AsyncRead + AsyncWrite + AsyncSeek + Unpin),
+    end_offset: u64,
+    mut signature: Signature,
+) -> Result<()> {
+    let _ = stream.seek(SeekFrom::Start(0)).await?;
+    {
+        let mut content = (&mut *stream).take(end_offset);
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let read = content.read(&mut buf[..]).await?;
+            if read == 0 {
+                break;
+            }
+            signature
+                .write()
+                .write_all(buf.get(..read).expect("read <= buf.len()"))?;
+        }
+    }
+    let _ = stream.seek(SeekFrom::Start(end_offset)).await?;
+    stream.write_all(&signature_trailer(signature)).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+async fn write_bstr(
+    stream: &mut (impl AsyncWrite + AsyncSeek + Unpin),
+    mut bstr: impl AsyncRead + Unpin,
+    error_msg: &str,
+) -> Result<()> {
+    let start = stream.stream_position().await?;
+    stream.write_all(&[0u8; 4]).await?; // bstr size
+    let size = io::copy(&mut bstr, stream).await?;
+    let size = u32::try_from(size).map_err(|_| Error::new(ErrorKind::Other, error_msg))?;
+    let _ = stream.seek(SeekFrom::Start(start)).await?;
+    stream.write_u32_le(size).await?;
+    let _ = stream.seek(SeekFrom::Current(size.into())).await?;
+    Ok(())
+}
+
+/// Forwards writes to the inner writer, but only flushes it on shutdown.
+///
+/// Async encoders must be shut down to write their trailers,
+/// which would otherwise also shut down the phar stream.
+struct NoShutdown<W>(W);
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for NoShutdown<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+}
@@ -0,0 +1,118 @@
+use std::convert::TryFrom;
+use std::io::{Result, Write};
+
+/// The size of the buffer for compressed output.
+const OUTPUT_BUFFER_SIZE: usize = 1 << 15;
+
+/// A compressor driven by `Encoder`.
+pub(crate) trait Codec {
+    /// Compresses `input` into the spare capacity of `output`,
+    /// finishing the stream if `finish` is true.
+    ///
+    /// Returns whether the end of the stream has been written.
+    fn compress(&mut self, input: &[u8], output: &mut Vec<u8>, finish: bool) -> Result<bool>;
+
+    /// Returns the total number of bytes consumed.
+    fn total_in(&self) -> u64;
+}
+
+#[cfg(any(feature = "comp-zlib-c", feature = "comp-zlib-rs"))]
+impl Codec for flate2::Compress {
+    fn compress(&mut self, input: &[u8], output: &mut Vec<u8>, finish: bool) -> Result<bool> {
+        let flush = if finish {
+            flate2::FlushCompress::Finish
+        } else {
+            flate2::FlushCompress::None
+        };
+        let status = self.compress_vec(input, output, flush)?;
+        Ok(status == flate2::Status::StreamEnd)
+    }
+
+    fn total_in(&self) -> u64 {
+        flate2::Compress::total_in(self)
+    }
+}
+
+#[cfg(any(feature = "comp-bzip", feature = "comp-bzip-rs"))]
+impl Codec for bzip2::Compress {
+    fn compress(&mut self, input: &[u8], output: &mut Vec<u8>, finish: bool) -> Result<bool> {
+        let action = if finish {
+            bzip2::Action::Finish
+        } else {
+            bzip2::Action::Run
+        };
+        let status = self.compress_vec(input, output, action)?;
+        Ok(status == bzip2::Status::StreamEnd)
+    }
+
+    fn total_in(&self) -> u64 {
+        bzip2::Compress::total_in(self)
+    }
+}
+
+/// Writes the output of a `Codec` created with custom settings,
+/// which the writers of `flate2` and `bzip2` do not accept.
+///
+/// Like those writers, the stream is finished when the encoder is dropped,
+/// ignoring any errors.
+pub(crate) struct Encoder<W: Write, C: Codec> {
+    write: W,
+    codec: C,
+    buf: Vec<u8>,
+    done: bool,
+}
+
+impl<W: Write, C: Codec> Encoder<W, C> {
+    pub(crate) fn new(write: W, codec: C) -> Self {
+        Self {
+            write,
+            codec,
+            buf: Vec::with_capacity(OUTPUT_BUFFER_SIZE),
+            done: false,
+        }
+    }
+
+    /// Writes out the buffered compressed output.
+    fn dump(&mut self) -> Result<()> {
+        self.write.write_all(&self.buf)?;
+        self.buf.clear();
+        Ok(())
+    }
+
+    /// Writes the end of the stream.
+    fn finish(&mut self) -> Result<()> {
+        while !self.done {
+            self.dump()?;
+            self.done = self.codec.compress(&[], &mut self.buf, true)?;
+        }
+        self.dump()
+    }
+}
+
+impl<W: Write, C: Codec> Write for Encoder<W, C> {
+    fn write(&mut self, data: &[u8]) -> Result<usize> {
+        if data.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            self.dump()?;
+            let before = self.codec.total_in();
+            let _ = self.codec.compress(data, &mut self.buf, false)?;
+            let consumed = self.codec.total_in() - before;
+            if consumed > 0 {
+                return Ok(usize::try_from(consumed).expect("consumed <= data.len()"));
+            }
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.dump()?;
+        self.write.flush()
+    }
+}
+
+impl<W: Write, C: Codec> Drop for Encoder<W, C> {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
@@ -5,4 +5,50 @@ mod writer;
 
 pub use writer::*;
 
+mod options;
+pub use options::{BuildOptions, Symlinks};
+
+mod plan;
+pub use plan::{Plan, PlannedEntry};
+
+mod walk;
+pub use walk::walk_directory;
+
+mod source;
+pub use source::{Source, SourceEntry};
+
+#[cfg(any(feature = "reader", feature = "tar", feature = "zip"))]
+pub(crate) mod phar_dir;
+#[cfg(any(feature = "reader", feature = "tar", feature = "zip"))]
+pub use phar_dir::PharDir;
+
+#[cfg(feature = "tar")]
+mod tar_source;
+#[cfg(feature = "tar")]
+#[cfg_attr(feature = "docsrs", doc(cfg(feature = "tar")))]
+pub use tar_source::TarSource;
+
+#[cfg(feature = "zip")]
+mod zip_source;
+#[cfg(feature = "zip")]
+#[cfg_attr(feature = "docsrs", doc(cfg(feature = "zip")))]
+pub use zip_source::ZipSource;
+
+mod archive;
+pub use archive::compress_archive;
+
+#[cfg(any(
+    feature = "comp-zlib-c",
+    feature = "comp-zlib-rs",
+    feature = "comp-bzip",
+    feature = "comp-bzip-rs"
+))]
+pub(crate) mod encoder;
+
+#[cfg(feature = "async")]
+mod async_writer;
+#[cfg(feature = "async")]
+#[cfg_attr(feature = "docsrs", doc(cfg(feature = "async")))]
+pub use async_writer::*;
+
 mod util;
@@ -0,0 +1,69 @@
+use typed_builder::TypedBuilder;
+
+use crate::Compression;
+
+/// Options for building phar archives from the filesystem
+///
+/// A `Compression` can be converted into `BuildOptions`
+/// with default values for other options.
+#[derive(Debug, TypedBuilder)]
+pub struct BuildOptions {
+    /// The compression method for file contents
+    ///
+    /// Default `Compression::None`.
+    #[builder(default = Compression::None)]
+    pub(super) compression: Compression,
+
+    /// Whether to add directory entries
+    ///
+    /// Default false.
+    /// If set to true, all directories are added as directory entries,
+    /// including empty directories that would otherwise be lost.
+    /// If set to false, directories are only implied by the names of files inside them.
+    #[builder(default)]
+    pub(super) directories: bool,
+
+    /// How symbolic links are handled
+    ///
+    /// Default `Symlinks::Contained`.
+    /// Earlier versions of this library included links to files anywhere on the filesystem,
+    /// which could silently add files outside the directory being packed,
+    /// so such links are now rejected by default.
+    /// Use `Symlinks::Follow` to include them.
+    #[builder(default)]
+    pub(super) symlinks: Symlinks,
+}
+
+/// How symbolic links are handled when building from the filesystem.
+///
+/// The phar format has no symbolic link entries,
+/// so followed links are stored as copies of their targets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Symlinks {
+    /// Follows all symbolic links, including links to directories.
+    ///
+    /// A link to one of its own ancestor directories fails with an `InvalidInput` error.
+    Follow,
+    /// Follows symbolic links whose targets are inside the walked directory,
+    /// and fails with an `InvalidInput` error for links to targets outside it.
+    ///
+    /// Cycles are detected as in `Follow`.
+    #[default]
+    Contained,
+    /// Skips symbolic links.
+    Skip,
+    /// Fails with an `InvalidInput` error if a symbolic link is found.
+    Error,
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl From<Compression> for BuildOptions {
+    fn from(compression: Compression) -> Self {
+        Self::builder().compression(compression).build()
+    }
+}
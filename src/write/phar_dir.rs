@@ -0,0 +1,96 @@
+#[cfg(any(feature = "tar", feature = "zip"))]
+use std::io::{Error, ErrorKind, Read, Result};
+
+#[cfg(any(feature = "tar", feature = "zip"))]
+use crate::util::STUB_TERMINATOR;
+use crate::SignatureAlgorithm;
+
+/// The directory holding the stub, alias, metadata and signature in tar and zip based phars
+#[cfg(any(feature = "tar", feature = "zip"))]
+pub(crate) const DIR: &[u8] = b".phar/";
+
+/// The full stub, including the `__HALT_COMPILER(); ?>` terminator
+pub(crate) const STUB: &str = ".phar/stub.php";
+
+/// The alias, which is omitted if empty
+pub(crate) const ALIAS: &str = ".phar/alias.txt";
+
+/// The signature flags, the signature length and the signature, always the last entry
+pub(crate) const SIGNATURE: &str = ".phar/signature.bin";
+
+/// The global metadata of tar based phars.
+/// Zip based phars store it in the archive comment instead.
+#[cfg(feature = "tar")]
+pub(crate) const METADATA: &str = ".phar/.metadata.bin";
+
+/// Tar based phars store the metadata of each entry in `.phar/.metadata/NAME/.metadata.bin`.
+/// Zip based phars store it in the file comment instead.
+#[cfg(feature = "tar")]
+pub(crate) const ENTRY_METADATA_PREFIX: &str = ".phar/.metadata/";
+
+/// See `ENTRY_METADATA_PREFIX`.
+#[cfg(feature = "tar")]
+pub(crate) const ENTRY_METADATA_SUFFIX: &str = "/.metadata.bin";
+
+/// The stub, alias, global metadata and signature algorithm
+/// stored in the `.phar` directory of a tar or zip based phar.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PharDir {
+    /// The stub without the `__HALT_COMPILER(); ?>` terminator,
+    /// or `None` if the archive has no `.phar/stub.php`
+    pub stub: Option<Vec<u8>>,
+    /// The alias, which is empty if the archive has no `.phar/alias.txt`
+    pub alias: Vec<u8>,
+    /// The global metadata
+    pub metadata: Vec<u8>,
+    /// The algorithm in `.phar/signature.bin`, or `None` if the archive is not signed
+    pub signature: Option<SignatureAlgorithm>,
+}
+
+#[cfg(any(feature = "tar", feature = "zip"))]
+impl PharDir {
+    /// Stores the contents of the file `name` in the `.phar` directory.
+    ///
+    /// Files not describing the archive as a whole are ignored.
+    pub(crate) fn read_file(&mut self, name: &[u8], mut read: impl Read) -> Result<()> {
+        let mut contents = Vec::new();
+        if name == STUB.as_bytes() {
+            let _ = read.read_to_end(&mut contents)?;
+            let stub = contents.strip_suffix(STUB_TERMINATOR).unwrap_or(&contents);
+            self.stub = Some(stub.to_vec());
+        } else if name == ALIAS.as_bytes() {
+            let _ = read.read_to_end(&mut self.alias)?;
+        } else if name == SIGNATURE.as_bytes() {
+            let mut flags = [0; 4];
+            read.read_exact(&mut flags)?;
+            let algorithm =
+                SignatureAlgorithm::from_u32(u32::from_le_bytes(flags)).ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::InvalidData,
+                        "unknown signature algorithm in .phar/signature.bin",
+                    )
+                })?;
+            self.signature = Some(algorithm);
+        } else {
+            #[cfg(feature = "tar")]
+            if name == METADATA.as_bytes() {
+                let _ = read.read_to_end(&mut self.metadata)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns whether `name` is in the `.phar` directory
+/// and is therefore not a regular entry of a tar or zip based phar.
+#[cfg(any(feature = "tar", feature = "zip"))]
+pub(crate) fn contains(name: &[u8]) -> bool {
+    name == b".phar" || name.starts_with(DIR)
+}
+
+/// Returns the name of the entry whose metadata is stored in the tar entry `name`.
+#[cfg(feature = "tar")]
+pub(crate) fn metadata_owner(name: &[u8]) -> Option<&[u8]> {
+    name.strip_prefix(ENTRY_METADATA_PREFIX.as_bytes())?
+        .strip_suffix(ENTRY_METADATA_SUFFIX.as_bytes())
+}
@@ -0,0 +1,283 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, Result, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+use super::util::{add_unique_name, terminate_dir_name};
+use super::walk::check_symlink;
+use super::{walk_directory, BuildOptions};
+use crate::signature::Signature;
+use crate::util::{PHAR_TERMINATOR, STUB_TERMINATOR};
+use crate::{path, Compression};
+
+/// The size of the fixed fields in the manifest,
+/// i.e. manifest size, number of entries, API version, global flags,
+/// alias size and global metadata size.
+const MANIFEST_FIXED_SIZE: u64 = 4 + 4 + 2 + 4 + 4 + 4;
+
+/// The size of the fixed fields of each entry in the manifest,
+/// i.e. name size, uncompressed size, timestamp, compressed size, CRC32, flags
+/// and metadata size.
+const ENTRY_FIXED_SIZE: u64 = 4 + 4 + 4 + 4 + 4 + 4 + 4;
+
+/// The entries that would be written when building a phar from the filesystem.
+///
+/// A plan is created from the same inputs as `NeedEntries::build_from_path_iter`
+/// without writing anything,
+/// so that the entries can be inspected, filtered or assigned different compression
+/// before passing the plan to `NeedEntries::build_from_plan`.
+#[derive(Debug, Clone, Default)]
+pub struct Plan {
+    entries: Vec<PlannedEntry>,
+}
+
+/// An entry in a `Plan`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PlannedEntry {
+    /// The normalized name of the entry in the archive
+    ///
+    /// Directory names end with a slash.
+    pub name: Vec<u8>,
+    /// The path of the file or directory on the filesystem
+    pub path: PathBuf,
+    /// Whether the entry is a directory
+    pub is_dir: bool,
+    /// The permission bits of the entry
+    pub mode: u32,
+    /// The modification time of the entry
+    pub modified: SystemTime,
+    /// The size of the file on the filesystem, or 0 for directories
+    pub size: u64,
+    /// The compression method for the file contents
+    pub compression: Compression,
+}
+
+impl PlannedEntry {
+    /// Estimates the size of the contents as stored in the archive
+    /// by compressing the file without writing the output.
+    ///
+    /// Directories always have size 0.
+    /// The estimate is exact unless the file changes before it is written.
+    pub fn estimate_size(&self) -> Result<u64> {
+        if self.is_dir {
+            return Ok(0);
+        }
+        if self.compression == Compression::None {
+            return Ok(self.size);
+        }
+
+        let mut counter = Counter(0);
+        let mut write = self.compression.make_write(&mut counter)?;
+        let _ = io::copy(&mut fs::File::open(&self.path)?, &mut write)?;
+        drop(write);
+        Ok(counter.0)
+    }
+}
+
+/// Counts the bytes written to it.
+struct Counter(u64);
+
+impl Write for Counter {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.0 += u64::try_from(buf.len()).expect("usize <= u64");
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Plan {
+    /// Plans the entries for the `(S, P)` pairs yielded by `iter`,
+    /// as described in `NeedEntries::build_from_path_iter`.
+    ///
+    /// The files are only inspected with `Path::symlink_metadata` and `Path::metadata`.
+    /// Symbolic links are handled according to `options.symlinks`;
+    /// for `Symlinks::Contained`, a link must point inside the directory
+    /// that its path is relative to, i.e. the path without the trailing components
+    /// it shares with the name, or at least the directory containing the link.
+    /// Returns an `InvalidInput` error if a name is invalid
+    /// or multiple entries have the same normalized name.
+    pub fn from_path_iter<S, P, I>(iter: I, options: impl Into<BuildOptions>) -> Result<Self>
+    where
+        I: IntoIterator<Item = Result<(S, P)>>,
+        S: AsRef<OsStr>,
+        P: AsRef<Path>,
+    {
+        let options = options.into();
+
+        let mut entries = Vec::new();
+        let mut names = HashSet::new();
+        for pair in iter {
+            let (name, file) = pair?;
+            let stat = file.as_ref().symlink_metadata()?;
+            let stat = if stat.file_type().is_symlink() {
+                let root = link_root(file.as_ref(), Path::new(name.as_ref()));
+                if !check_symlink(options.symlinks, &root, &mut None, file.as_ref())? {
+                    continue;
+                }
+                file.as_ref().metadata()?
+            } else {
+                stat
+            };
+            let name = os_str_to_bytes(name.as_ref());
+            let is_dir = if stat.is_file() {
+                false
+            } else if stat.is_dir() && options.directories && !name.as_ref().is_empty() {
+                // the root directory has an empty name
+                true
+            } else {
+                continue;
+            };
+
+            let mut normalized = path::normalize(name.as_ref())?.into_owned();
+            if is_dir {
+                terminate_dir_name(&mut normalized);
+            }
+            add_unique_name(&mut names, &normalized)?;
+            entries.push(PlannedEntry {
+                name: normalized,
+                path: file.as_ref().to_owned(),
+                is_dir,
+                mode: stat_to_mode(stat.permissions()),
+                modified: stat.modified()?,
+                size: if is_dir { 0 } else { stat.len() },
+                compression: if is_dir {
+                    Compression::None
+                } else {
+                    options.compression
+                },
+            });
+        }
+        Ok(Self { entries })
+    }
+
+    /// Plans the entries in a directory on the filesystem,
+    /// as described in `NeedEntries::build_from_directory`.
+    pub fn from_directory(path: &Path, options: impl Into<BuildOptions>) -> Result<Self> {
+        let options = options.into();
+        let walked = walk_directory(path, options.symlinks).collect::<Result<Vec<_>>>()?;
+        Self::from_path_iter(walked.into_iter().map(Ok), options)
+    }
+
+    /// Returns the planned entries in the order they would be written.
+    pub fn entries(&self) -> &[PlannedEntry] {
+        &self.entries
+    }
+
+    /// Returns the planned entries mutably, e.g. to change the compression of some files.
+    ///
+    /// Names changed here are not checked until the plan is built.
+    pub fn entries_mut(&mut self) -> &mut [PlannedEntry] {
+        &mut self.entries
+    }
+
+    /// Removes the entries for which `f` returns false.
+    pub fn retain(&mut self, f: impl FnMut(&PlannedEntry) -> bool) {
+        self.entries.retain(f);
+    }
+
+    /// Estimates the size of the phar file built from this plan
+    /// with the given stub, alias, global metadata and signature.
+    ///
+    /// The stub should not include the `__HALT_COMPILER();` terminator,
+    /// as in `NeedStub::stub`.
+    /// The contents of every file are compressed as in `PlannedEntry::estimate_size`.
+    pub fn estimate_size(
+        &self,
+        stub: &[u8],
+        alias: &[u8],
+        metadata: &[u8],
+        signature: &Signature,
+    ) -> Result<u64> {
+        let mut contents_size = 0;
+        for entry in &self.entries {
+            contents_size += entry.estimate_size()?;
+        }
+        Ok(self.size_with_contents(stub, alias, metadata, signature, contents_size))
+    }
+
+    /// Computes the size of the phar file built from this plan like `estimate_size`,
+    /// given the total size of the stored contents of all entries,
+    /// e.g. the sum of `PlannedEntry::estimate_size` if it is already known.
+    pub fn size_with_contents(
+        &self,
+        stub: &[u8],
+        alias: &[u8],
+        metadata: &[u8],
+        signature: &Signature,
+        contents_size: u64,
+    ) -> u64 {
+        let len = |bytes: &[u8]| u64::try_from(bytes.len()).expect("usize <= u64");
+
+        let mut size = len(stub) + len(STUB_TERMINATOR);
+        size += MANIFEST_FIXED_SIZE + len(alias) + len(metadata);
+        for entry in &self.entries {
+            size += ENTRY_FIXED_SIZE + len(&entry.name);
+        }
+        size += contents_size;
+        size += u64::from(signature.size()) + 4 + len(PHAR_TERMINATOR);
+        size
+    }
+}
+
+/// Returns the directory that the path of an entry is relative to,
+/// i.e. `path` without the trailing components it shares with `name`,
+/// or the directory containing `path` if it does not end with the last component of `name`.
+fn link_root(path: &Path, name: &Path) -> PathBuf {
+    let name = name
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)));
+    let shared = path
+        .components()
+        .rev()
+        .zip(name.rev())
+        .take_while(|(path, name)| path == name)
+        .count();
+    match path.ancestors().nth(shared.max(1)) {
+        Some(root) if !root.as_os_str().is_empty() => root.to_owned(),
+        // relative paths end with an empty ancestor
+        _ => PathBuf::from("."),
+    }
+}
+
+#[cfg(unix)]
+fn os_str_to_bytes(name: &OsStr) -> impl AsRef<[u8]> + '_ {
+    use std::os::unix::ffi::OsStrExt;
+    Cow::Borrowed(name.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn os_str_to_bytes(name: &OsStr) -> impl AsRef<[u8]> + '_ {
+    // phar entry names always use `/` as the separator
+    let name = name.to_string_lossy();
+    if name.contains('\\') {
+        Cow::Owned(name.replace('\\', "/").into_bytes())
+    } else {
+        match name {
+            Cow::Borrowed(name) => Cow::Borrowed(name.as_bytes()),
+            Cow::Owned(name) => Cow::Owned(name.into_bytes()),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn stat_to_mode(permissions: fs::Permissions) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    permissions.mode()
+}
+
+#[cfg(not(unix))]
+fn stat_to_mode(permissions: fs::Permissions) -> u32 {
+    if permissions.readonly() {
+        0o444
+    } else {
+        0o664
+    }
+}
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::fs;
+use std::hash::BuildHasher;
+use std::io::{Error, ErrorKind, Read, Result};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use super::Plan;
+use crate::Compression;
+
+/// A collection of files that can be written into a phar
+/// with `NeedEntries::build_from_source`.
+///
+/// The writer first calls `list` to declare all entries in the manifest,
+/// then calls `open` for each file entry in the same order to write the contents.
+pub trait Source {
+    /// Identifies an entry in this source when it is opened,
+    /// such as a path or a position in an archive.
+    type Id;
+
+    /// Lists the entries in the order they should be written.
+    fn list(&mut self) -> Result<Vec<SourceEntry<Self::Id>>>;
+
+    /// Opens the contents of the file entry identified by `id`.
+    ///
+    /// This is not called for directory entries.
+    fn open(&mut self, id: &Self::Id) -> Result<Box<dyn Read + '_>>;
+}
+
+/// An entry in a `Source`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceEntry<Id> {
+    /// The name of the entry in the archive, which is normalized by the writer
+    pub name: Vec<u8>,
+    /// The entry metadata, which should be empty or comply to PHP serialization format
+    pub metadata: Vec<u8>,
+    /// The modification time of the entry
+    pub modified: SystemTime,
+    /// The permission bits of the entry
+    pub mode: u32,
+    /// Whether the entry is a directory, which has no contents
+    pub is_dir: bool,
+    /// The compression method for the contents,
+    /// or `None` to use the compression in `BuildOptions`
+    pub compression: Option<Compression>,
+    /// Identifies the entry when passed to `Source::open`
+    pub id: Id,
+}
+
+impl<Id> SourceEntry<Id> {
+    /// Creates a file entry with empty metadata, permissions `0o644`,
+    /// the Unix epoch as the modification time and the default compression.
+    pub fn file(name: impl Into<Vec<u8>>, id: Id) -> Self {
+        Self {
+            name: name.into(),
+            metadata: Vec::new(),
+            modified: SystemTime::UNIX_EPOCH,
+            mode: 0o644,
+            is_dir: false,
+            compression: None,
+            id,
+        }
+    }
+}
+
+/// Files on the filesystem, opened by their paths.
+impl Source for Plan {
+    type Id = PathBuf;
+
+    fn list(&mut self) -> Result<Vec<SourceEntry<PathBuf>>> {
+        let mut plan = &*self;
+        plan.list()
+    }
+
+    fn open(&mut self, path: &PathBuf) -> Result<Box<dyn Read + '_>> {
+        Ok(Box::new(fs::File::open(path)?))
+    }
+}
+
+/// Same as `Plan`, without taking ownership of the plan.
+impl Source for &Plan {
+    type Id = PathBuf;
+
+    fn list(&mut self) -> Result<Vec<SourceEntry<PathBuf>>> {
+        Ok(self
+            .entries()
+            .iter()
+            .map(|entry| SourceEntry {
+                name: entry.name.clone(),
+                metadata: Vec::new(),
+                modified: entry.modified,
+                mode: entry.mode,
+                is_dir: entry.is_dir,
+                compression: Some(entry.compression),
+                id: entry.path.clone(),
+            })
+            .collect())
+    }
+
+    fn open(&mut self, path: &PathBuf) -> Result<Box<dyn Read + '_>> {
+        Ok(Box::new(fs::File::open(path)?))
+    }
+}
+
+/// Files in memory, keyed by their names.
+///
+/// Entries are written in the order of their names
+/// with the defaults of `SourceEntry::file`,
+/// so that the output does not depend on the hash order or the build time.
+impl<H: BuildHasher> Source for HashMap<String, Vec<u8>, H> {
+    type Id = String;
+
+    fn list(&mut self) -> Result<Vec<SourceEntry<String>>> {
+        let mut names: Vec<&String> = self.keys().collect();
+        names.sort();
+        Ok(names
+            .into_iter()
+            .map(|name| SourceEntry::file(name.as_bytes(), name.clone()))
+            .collect())
+    }
+
+    fn open(&mut self, name: &String) -> Result<Box<dyn Read + '_>> {
+        match self.get(name) {
+            Some(data) => Ok(Box::new(&data[..])),
+            None => Err(Error::new(
+                ErrorKind::NotFound,
+                format!("no file named {:?}", name),
+            )),
+        }
+    }
+}
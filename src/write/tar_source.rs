@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use std::io::{Read, Result, Seek, SeekFrom};
+use std::ops::Range;
+use std::time::{Duration, SystemTime};
+
+use super::phar_dir::{self, PharDir};
+use super::{Source, SourceEntry};
+use crate::util::PERMISSION_MASK;
+
+/// The regular files and directories in a tar archive.
+///
+/// The archive is read from the start of the stream.
+/// Other entry types, such as symbolic links, hard links and sparse files, are skipped.
+/// Compressed tar files must be decompressed first,
+/// since the contents are read by seeking to their positions in the archive.
+///
+/// Files in the `.phar` directory of tar based phars are not listed as entries.
+/// The metadata in `.phar/.metadata/NAME/.metadata.bin` is used as the metadata of `NAME`,
+/// and the stub, alias and global metadata are returned by `phar_dir`.
+#[derive(Debug)]
+pub struct TarSource<R: Read + Seek> {
+    read: R,
+}
+
+impl<R: Read + Seek> TarSource<R> {
+    /// Creates a source reading the tar archive in `read`.
+    pub fn new(read: R) -> Self {
+        Self { read }
+    }
+
+    /// Reads the stub, alias, global metadata and signature algorithm
+    /// from the `.phar` directory of a tar based phar.
+    pub fn phar_dir(&mut self) -> Result<PharDir> {
+        let _ = self.read.seek(SeekFrom::Start(0))?;
+        let mut archive = tar::Archive::new(&mut self.read);
+
+        let mut dir = PharDir::default();
+        for entry in archive.entries_with_seek()? {
+            let entry = entry?;
+            let name = entry.path_bytes().into_owned();
+            dir.read_file(&name, entry)?;
+        }
+        Ok(dir)
+    }
+
+    /// Returns the underlying stream.
+    pub fn into_inner(self) -> R {
+        self.read
+    }
+}
+
+impl<R: Read + Seek> Source for TarSource<R> {
+    /// The position of the contents in the archive
+    type Id = Range<u64>;
+
+    fn list(&mut self) -> Result<Vec<SourceEntry<Range<u64>>>> {
+        let _ = self.read.seek(SeekFrom::Start(0))?;
+        let mut archive = tar::Archive::new(&mut self.read);
+
+        let mut entries = Vec::new();
+        let mut metadata = HashMap::new();
+        for entry in archive.entries_with_seek()? {
+            let mut entry = entry?;
+            let name = entry.path_bytes().into_owned();
+            if phar_dir::contains(&name) {
+                if let Some(owner) = phar_dir::metadata_owner(&name) {
+                    let mut contents = Vec::new();
+                    let _ = entry.read_to_end(&mut contents)?;
+                    let _ = metadata.insert(owner.to_vec(), contents);
+                }
+                continue;
+            }
+
+            let header = entry.header();
+            let entry_type = header.entry_type();
+            let is_dir = if entry_type.is_file() || entry_type.is_contiguous() {
+                false
+            } else if entry_type.is_dir() {
+                true
+            } else {
+                continue;
+            };
+
+            let start = entry.raw_file_position();
+            entries.push(SourceEntry {
+                name,
+                metadata: Vec::new(),
+                modified: SystemTime::UNIX_EPOCH + Duration::from_secs(header.mtime()?),
+                mode: header.mode()? & PERMISSION_MASK,
+                is_dir,
+                compression: None,
+                id: start..(start + entry.size()),
+            });
+        }
+
+        for entry in &mut entries {
+            let name = entry.name.strip_suffix(b"/").unwrap_or(&entry.name);
+            if let Some(contents) = metadata.remove(name) {
+                entry.metadata = contents;
+            }
+        }
+        Ok(entries)
+    }
+
+    fn open(&mut self, range: &Range<u64>) -> Result<Box<dyn Read + '_>> {
+        let _ = self.read.seek(SeekFrom::Start(range.start))?;
+        Ok(Box::new((&mut self.read).take(range.end - range.start)))
+    }
+}
@@ -1,9 +1,153 @@
+use std::collections::HashSet;
 use std::convert::TryFrom;
 use std::io::{self, Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use byteorder::{LittleEndian, WriteBytesExt};
 
-use crate::util::tell;
+use crate::signature::Signature;
+use crate::util::{tell, PERMISSION_MASK, PHAR_TERMINATOR};
+use crate::{ApiVersion, Compression, GlobalFlags};
+
+/// The phar API version written in the manifest.
+pub const API_VERSION: [u8; 2] = ApiVersion::CURRENT.to_bytes();
+
+/// The initial global flags, which only includes `PHAR_HDR_SIGNATURE`.
+pub const GLOBAL_FLAGS: u32 = GlobalFlags::SIGNATURE.bits();
+
+/// Encodes the fields at the start of the manifest:
+/// the manifest size, the number of entries, the API version and the global flags.
+///
+/// The sizes are not known until all entries are declared,
+/// so this is first written with zero sizes and rewritten afterwards.
+pub fn manifest_header(
+    manifest_size: u64,
+    num_entries: usize,
+    global_flags: u32,
+) -> Result<Vec<u8>> {
+    let manifest_size = u32::try_from(manifest_size)
+        .map_err(|_| Error::new(ErrorKind::Other, "manifest too large"))?;
+    let num_entries = u32::try_from(num_entries)
+        .map_err(|_| Error::new(ErrorKind::Other, "too many file entries"))?;
+
+    let mut bytes = Vec::with_capacity(14);
+    bytes.extend_from_slice(&manifest_size.to_le_bytes());
+    bytes.extend_from_slice(&num_entries.to_le_bytes());
+    bytes.extend_from_slice(&API_VERSION);
+    bytes.extend_from_slice(&global_flags.to_le_bytes());
+    Ok(bytes)
+}
+
+/// The fields of an entry in the manifest between its name and its metadata.
+///
+/// The sizes and the checksum are not known until the contents are written,
+/// so the fields are first written with zeros and rewritten afterwards.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EntryFields {
+    pub original_size: u32,
+    pub timestamp: u32,
+    pub compressed_size: u32,
+    pub crc32: u32,
+    pub flags: u32,
+}
+
+impl EntryFields {
+    /// Records the sizes and the checksum of the written contents.
+    pub fn set_contents(
+        &mut self,
+        original_size: u64,
+        compressed_size: u64,
+        crc32: u32,
+    ) -> Result<()> {
+        self.original_size = u32::try_from(original_size)
+            .map_err(|_| Error::new(ErrorKind::Other, "content is too large"))?;
+        self.set_compressed_size(compressed_size)?;
+        self.crc32 = crc32;
+        Ok(())
+    }
+
+    /// Records the size of the written contents, which are already compressed.
+    pub fn set_compressed_size(&mut self, compressed_size: u64) -> Result<()> {
+        self.compressed_size = u32::try_from(compressed_size)
+            .map_err(|_| Error::new(ErrorKind::Other, "content is too large"))?;
+        Ok(())
+    }
+
+    pub fn to_bytes(self) -> Vec<u8> {
+        [
+            self.original_size,
+            self.timestamp,
+            self.compressed_size,
+            self.crc32,
+            self.flags,
+        ]
+        .iter()
+        .flat_map(|field| field.to_le_bytes())
+        .collect()
+    }
+}
+
+/// Encodes the signature appended after the contents,
+/// followed by the signature flags and the `GBMB` terminator.
+pub fn signature_trailer(signature: Signature) -> Vec<u8> {
+    let sig_id = signature.to_u32();
+    let mut bytes = signature.finalize().to_vec();
+    bytes.extend_from_slice(&sig_id.to_le_bytes());
+    bytes.extend_from_slice(PHAR_TERMINATOR);
+    bytes
+}
+
+/// Encodes a timestamp as the 32-bit unix timestamp stored in entry manifests.
+pub fn encode_timestamp(timestamp: SystemTime) -> u32 {
+    #[allow(clippy::as_conversions)]
+    // explicit truncation to u32, since we have no better solution
+    match timestamp.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as u32,
+        Err(err) => {
+            let secs = err.duration().as_secs() as u32;
+            secs.wrapping_neg()
+        }
+    }
+}
+
+/// Computes the entry flags from the file mode and the compression method.
+pub fn entry_flags(mode: u32, compression: Compression) -> u32 {
+    let mut out = mode & PERMISSION_MASK; // should we panic if mode >= 0x200?
+    out |= compression.bit();
+    out
+}
+
+/// Checks that raw entry flags only contain permission bits and at most one compression method.
+pub fn check_raw_flags(flags: u32) -> Result<()> {
+    match flags & !PERMISSION_MASK {
+        0 | 0x1000 | 0x2000 => Ok(()),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "entry flags {:#x} contain unknown bits or multiple compression methods",
+                flags
+            ),
+        )),
+    }
+}
+
+/// Records an entry name, failing if it was already used by another entry.
+pub fn add_unique_name(names: &mut HashSet<Vec<u8>>, name: &[u8]) -> Result<()> {
+    if !names.insert(name.to_vec()) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("duplicate entry name {:?}", String::from_utf8_lossy(name)),
+        ));
+    }
+    Ok(())
+}
+
+/// Appends a slash to a directory entry name if it does not end with one.
+pub fn terminate_dir_name(name: &mut Vec<u8>) {
+    if name.last() != Some(&b'/') {
+        name.push(b'/');
+    }
+}
 
 pub fn write_bstr(
     mut stream: impl Write + Seek,
@@ -0,0 +1,112 @@
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+
+use walkdir::WalkDir;
+
+use super::Symlinks;
+
+/// Walks a directory recursively, handling symbolic links according to `symlinks`.
+///
+/// Yields `(name, path)` pairs in the form accepted by `NeedEntries::build_from_path_iter`,
+/// where `name` is the path relative to `root`
+/// and `path` is the path of the file on the filesystem.
+/// The first pair is `root` itself with an empty name.
+/// If `root` is a file, only that pair is yielded.
+pub fn walk_directory(
+    root: &Path,
+    symlinks: Symlinks,
+) -> impl Iterator<Item = Result<(PathBuf, PathBuf)>> + '_ {
+    let follow = matches!(symlinks, Symlinks::Follow | Symlinks::Contained);
+    // only computed when the first link is checked
+    let mut canonical_root = None;
+
+    WalkDir::new(root)
+        .follow_links(follow)
+        .into_iter()
+        .filter_map(move |entry| {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => return Some(Err(walk_error(err))),
+            };
+            // the root is given by the caller, so it is accepted even if it is a link
+            if entry.depth() > 0 && entry.path_is_symlink() {
+                match check_symlink(symlinks, root, &mut canonical_root, entry.path()) {
+                    Ok(true) => {}
+                    Ok(false) => return None,
+                    Err(err) => return Some(Err(err)),
+                }
+            }
+
+            let name = match entry.path().strip_prefix(root) {
+                Ok(name) => name.to_owned(),
+                Err(_) => {
+                    return Some(Err(Error::new(
+                        ErrorKind::Other,
+                        "path is not a prefix of walked entry",
+                    )))
+                }
+            };
+            Some(Ok((name, entry.into_path())))
+        })
+}
+
+/// Applies `symlinks` to the symbolic link at `path` inside `root`.
+///
+/// `canonical_root` caches the canonical path of `root` across calls.
+/// Returns whether the link should be followed.
+pub(super) fn check_symlink(
+    symlinks: Symlinks,
+    root: &Path,
+    canonical_root: &mut Option<PathBuf>,
+    path: &Path,
+) -> Result<bool> {
+    match symlinks {
+        Symlinks::Follow => Ok(true),
+        Symlinks::Contained => {
+            check_contained(root, canonical_root, path)?;
+            Ok(true)
+        }
+        Symlinks::Skip => Ok(false),
+        Symlinks::Error => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("found symbolic link {}", path.display()),
+        )),
+    }
+}
+
+/// Fails if the target of the link at `path` is not inside `root`.
+fn check_contained(root: &Path, canonical_root: &mut Option<PathBuf>, path: &Path) -> Result<()> {
+    let canonical_root = match canonical_root {
+        Some(canonical_root) => canonical_root,
+        None => canonical_root.insert(fs::canonicalize(root)?),
+    };
+    let target = fs::canonicalize(path)?;
+    if !target.starts_with(canonical_root) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "symbolic link {} points to {} outside {}",
+                path.display(),
+                target.display(),
+                root.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Converts a walk error, reporting symbolic link cycles as `InvalidInput`.
+fn walk_error(err: walkdir::Error) -> Error {
+    match (err.path(), err.loop_ancestor()) {
+        (Some(path), Some(ancestor)) => Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "symbolic link cycle: {} points to its ancestor {}",
+                path.display(),
+                ancestor.display()
+            ),
+        ),
+        _ => err.into(),
+    }
+}
@@ -1,17 +1,19 @@
-use std::borrow::Cow;
-use std::convert::TryInto;
+use std::collections::HashSet;
 use std::ffi::OsStr;
 use std::io::{self, Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
 use std::path::Path;
-use std::time::{SystemTime, UNIX_EPOCH};
-
-use byteorder::{LittleEndian, WriteBytesExt};
-use walkdir::WalkDir;
-
-use super::util::{write_bstr, Crc32Writer, MultiWrite};
+use std::time::{Instant, SystemTime};
+
+use super::util::{
+    add_unique_name, check_raw_flags, encode_timestamp, entry_flags, manifest_header,
+    signature_trailer, terminate_dir_name, write_bstr, Crc32Writer, EntryFields, MultiWrite,
+    GLOBAL_FLAGS,
+};
+use super::{BuildOptions, Plan, Source};
+use crate::progress::{EntryEvent, Observer, Totals};
 use crate::signature::Signature;
-use crate::util::{tell, PHAR_TERMINATOR, STUB_TERMINATOR};
-use crate::Compression;
+use crate::util::{tell, STUB_TERMINATOR};
+use crate::{path, Compression, CompressionMethod};
 
 /// Creates a phar file.
 ///
@@ -52,9 +54,8 @@ impl<W: Read + Write + Seek> NeedStub<W> {
         self.stream.write_all(STUB_TERMINATOR)?;
         let manifest_size_offset = tell(&mut self.stream)?;
 
-        let _ = self.stream.seek(SeekFrom::Current(8))?; // manifest size, num_files
-        self.stream.write_all(&[0x11, 0])?; // api
-        self.stream.write_u32::<LittleEndian>(0x00010000)?; // flag
+        self.stream
+            .write_all(&manifest_header(0, 0, GLOBAL_FLAGS)?)?; // rewritten by `contents`
 
         Ok(NeedAlias {
             manifest_size_offset,
@@ -112,7 +113,10 @@ impl<W: Read + Write + Seek> NeedGlobMeta<W> {
             stream: self.stream,
             signature: self.signature,
             entries: Vec::new(),
-            global_flags: 0x00010000,
+            names: HashSet::new(),
+            num_dirs: 0,
+            global_flags: GLOBAL_FLAGS,
+            observer: None,
         })
     }
 }
@@ -128,57 +132,170 @@ pub struct NeedEntries<W: Read + Write + Seek> {
     stream: W,
     signature: Signature,
     entries: Vec<WriteEntry>,
+    /// The names of all entries added so far, used to reject duplicates
+    names: HashSet<Vec<u8>>,
+    num_dirs: usize,
     global_flags: u32,
+    observer: Option<Box<dyn Observer + Send>>,
 }
 
 impl<W: Read + Write + Seek> NeedEntries<W> {
+    /// Reports the progress of writing this phar to `observer`.
+    ///
+    /// Only entries declared after this call are passed to `Observer::entry_declared`,
+    /// so this should be called before adding entries.
+    pub fn with_observer(mut self, observer: impl Observer + Send + 'static) -> Self {
+        self.observer = Some(Box::new(observer));
+        self
+    }
+
     /// Adds an entry to the phar.
     ///
     /// The file contents shall be later passed with the `Contents::feed` method in the same order.
+    /// The name is normalized with `path::normalize`.
+    /// Returns an `InvalidInput` error if the name is invalid
+    /// or an entry with the same normalized name was already added.
     pub fn entry(
         &mut self,
-        name: impl Read,
+        mut name: impl Read,
         metadata: impl Read,
         timestamp: SystemTime,
         mode: u32,
         compression: Compression,
     ) -> Result<()> {
-        write_bstr(&mut self.stream, name, "file name is too long")?;
-        let uncompressed_offset = tell(&mut self.stream)?;
-
-        let _ = self.stream.seek(SeekFrom::Current(4))?; // uncompressed filesize
-
-        self.stream.write_u32::<LittleEndian>(
-            #[allow(clippy::as_conversions)]
-            // explicit truncation to u32, since we have no better solution
-            match timestamp.duration_since(UNIX_EPOCH) {
-                Ok(duration) => duration.as_secs() as u32,
-                Err(err) => {
-                    let secs = err.duration().as_secs() as u32;
-                    secs.wrapping_neg()
-                }
-            },
-        )?;
-
-        let _ = self.stream.seek(SeekFrom::Current(8))?; // compressed filesize, crc32
-        self.stream.write_u32::<LittleEndian>({
-            let mut out = mode & 0x1FF; // should we panic if mode >= 0x200?
-            out |= compression.bit();
-            out
-        })?;
+        let mut raw_name = Vec::new();
+        let _ = name.read_to_end(&mut raw_name)?;
+        let file_name = path::normalize(&raw_name)?.into_owned();
+        add_unique_name(&mut self.names, &file_name)?;
+        write_bstr(&mut self.stream, &file_name[..], "file name is too long")?;
+        let fields_offset = tell(&mut self.stream)?;
+        let fields = EntryFields {
+            timestamp: encode_timestamp(timestamp),
+            flags: entry_flags(mode, compression),
+            ..EntryFields::default()
+        };
+        self.stream.write_all(&fields.to_bytes())?; // sizes and crc32 rewritten by `feed`
 
         self.global_flags |= compression.bit();
 
         write_bstr(&mut self.stream, metadata, "file metadata is too large")?;
 
+        if let Some(observer) = &mut self.observer {
+            observer.entry_declared(&file_name);
+        }
         self.entries.push(WriteEntry {
-            uncompressed_offset,
-            compression,
+            name: file_name,
+            fields_offset,
+            fields,
+            compression: Some(compression),
         });
 
         Ok(())
     }
 
+    /// Adds a directory entry to the phar.
+    ///
+    /// A slash is appended to `name` if it does not already end with one.
+    /// Directory entries have no contents,
+    /// so they are skipped when calling `Contents::feed`.
+    /// The name is normalized with `path::normalize`.
+    /// Returns an `InvalidInput` error if the name is invalid
+    /// or an entry with the same normalized name was already added.
+    pub fn directory(
+        &mut self,
+        mut name: impl Read,
+        metadata: impl Read,
+        timestamp: SystemTime,
+        mode: u32,
+    ) -> Result<()> {
+        let mut raw_name = Vec::new();
+        let _ = name.read_to_end(&mut raw_name)?;
+        let mut dir_name = path::normalize(&raw_name)?.into_owned();
+        terminate_dir_name(&mut dir_name);
+        add_unique_name(&mut self.names, &dir_name)?;
+        write_bstr(&mut self.stream, &dir_name[..], "file name is too long")?;
+
+        let fields = EntryFields {
+            timestamp: encode_timestamp(timestamp),
+            flags: entry_flags(mode, Compression::None),
+            ..EntryFields::default()
+        };
+        self.stream.write_all(&fields.to_bytes())?; // no contents, so the crc32 is also zero
+
+        write_bstr(&mut self.stream, metadata, "file metadata is too large")?;
+
+        if let Some(observer) = &mut self.observer {
+            observer.entry_declared(&dir_name);
+        }
+        self.num_dirs += 1;
+
+        Ok(())
+    }
+
+    /// Adds an entry whose contents are already compressed, e.g. copied from another archive.
+    ///
+    /// The manifest fields are written as given in `raw`,
+    /// and the contents shall be later passed verbatim with `Contents::feed_raw`
+    /// in the same order as other entries.
+    /// If the name ends with a slash, the entry is a directory,
+    /// which has no contents and is skipped when feeding contents.
+    /// The name is normalized and checked as in `entry`.
+    /// Returns an `InvalidInput` error if `raw.flags` contains bits other than
+    /// the permission bits and at most one compression method,
+    /// or if a directory entry declares a non-zero size or CRC32.
+    pub fn raw_entry(
+        &mut self,
+        mut name: impl Read,
+        metadata: impl Read,
+        raw: RawEntry,
+    ) -> Result<()> {
+        check_raw_flags(raw.flags)?;
+        let mut raw_name = Vec::new();
+        let _ = name.read_to_end(&mut raw_name)?;
+        let file_name = path::normalize(&raw_name)?.into_owned();
+        let is_dir = file_name.last() == Some(&b'/');
+        if is_dir && (raw.original_size != 0 || raw.crc32 != 0) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "directory entry {:?} declares non-empty contents",
+                    String::from_utf8_lossy(&file_name)
+                ),
+            ));
+        }
+        add_unique_name(&mut self.names, &file_name)?;
+        write_bstr(&mut self.stream, &file_name[..], "file name is too long")?;
+        let fields_offset = tell(&mut self.stream)?;
+        let fields = EntryFields {
+            original_size: raw.original_size,
+            timestamp: u32::from_le_bytes(raw.timestamp.to_le_bytes()),
+            compressed_size: 0,
+            crc32: raw.crc32,
+            flags: raw.flags,
+        };
+        self.stream.write_all(&fields.to_bytes())?; // compressed size rewritten by `feed_raw`
+
+        self.global_flags |= CompressionMethod::from_bit(raw.flags).bit();
+
+        write_bstr(&mut self.stream, metadata, "file metadata is too large")?;
+
+        if let Some(observer) = &mut self.observer {
+            observer.entry_declared(&file_name);
+        }
+        if is_dir {
+            self.num_dirs += 1;
+        } else {
+            self.entries.push(WriteEntry {
+                name: file_name,
+                fields_offset,
+                fields,
+                compression: None,
+            });
+        }
+
+        Ok(())
+    }
+
     /// Starts writing the contents section of the phar.
     ///
     /// Users should call `feed` on the returned `Contents` value with the file contents
@@ -190,50 +307,44 @@ impl<W: Read + Write + Seek> NeedEntries<W> {
         let _ = self
             .stream
             .seek(SeekFrom::Start(self.manifest_size_offset))?;
-        self.stream.write_u32::<LittleEndian>(
-            manifest_size
-                .try_into()
-                .map_err(|_| Error::new(ErrorKind::Other, "manifest too large"))?,
-        )?;
-        self.stream.write_u32::<LittleEndian>(
-            self.entries
-                .len()
-                .try_into()
-                .map_err(|_| Error::new(ErrorKind::Other, "too many file entries"))?,
-        )?;
-        let _ = self.stream.seek(SeekFrom::Current(2))?; // phar api version
-        self.stream.write_u32::<LittleEndian>(self.global_flags)?;
+        self.stream.write_all(&manifest_header(
+            manifest_size,
+            self.entries.len() + self.num_dirs,
+            self.global_flags,
+        )?)?;
+
+        let mut signature = Some(self.signature);
+        if self.entries.is_empty() {
+            // there will be no `feed` calls to write the signature
+            write_signature(
+                &mut self.stream,
+                content_offset,
+                signature.take().expect("just created"),
+            )?;
+            if let Some(observer) = &mut self.observer {
+                observer.finished(&Totals::default());
+            }
+        }
 
         Ok(Contents {
             stream: self.stream,
             entries: self.entries,
             ptr: Some(0),
-            signature: Some(self.signature),
+            signature,
             end_offset: content_offset,
+            observer: self.observer,
+            started: Instant::now(),
+            totals: Totals::default(),
         })
     }
 
     /// Builds the phar from a directory on the filesystem.
-    pub fn build_from_directory(self, path: &Path, compression: Compression) -> Result<()> {
-        let vec: Result<Vec<(_, _)>> = WalkDir::new(path)
-            .into_iter()
-            .map(|entry| {
-                let entry = entry?;
-                Ok((
-                    entry
-                        .path()
-                        .strip_prefix(path)
-                        .map_err(|_| {
-                            Error::new(ErrorKind::Other, "path is not a prefix of walked entry")
-                        })?
-                        .as_os_str()
-                        .to_owned(),
-                    entry.path().to_owned(),
-                ))
-            })
-            .collect();
-        let vec = vec?;
-        self.build_from_path_iter(|| vec.iter().map(|(a, b)| Ok((a, b))), compression)
+    ///
+    /// `options` may also be a `Compression` to use the default options otherwise.
+    /// Symbolic links are handled according to `options.symlinks`,
+    /// as described in `walk_directory`.
+    pub fn build_from_directory(self, path: &Path, options: impl Into<BuildOptions>) -> Result<()> {
+        self.build_from_plan(&Plan::from_directory(path, options)?)
     }
 
     /// Builds the phar from an iterator of file paths.
@@ -242,71 +353,98 @@ impl<W: Read + Write + Seek> NeedEntries<W> {
     /// where each `S` is an `OsStr` representing the path inside the archive
     /// and each `P` is a `Path` that resolves to the actual file to include
     /// (at least relative to the current working directory).
+    ///
+    /// Directories are skipped unless `options.directories` is set.
+    /// Other file types are always skipped.
+    /// Symbolic links are handled according to `options.symlinks`,
+    /// as described in `Plan::from_path_iter`.
+    ///
+    /// This is equivalent to passing `Plan::from_path_iter` to `build_from_plan`.
     pub fn build_from_path_iter<S, P, I>(
-        mut self,
+        self,
         iter: impl Fn() -> I,
-        compression: Compression,
+        options: impl Into<BuildOptions>,
     ) -> Result<()>
     where
         I: Iterator<Item = Result<(S, P)>>,
         S: AsRef<OsStr>,
         P: AsRef<Path>,
     {
-        use std::fs;
-
-        #[cfg(unix)]
-        fn os_str_to_bytes(name: &OsStr) -> impl AsRef<[u8]> + '_ {
-            use std::os::unix::ffi::OsStrExt;
-            Cow::Borrowed(name.as_bytes())
-        }
+        self.build_from_plan(&Plan::from_path_iter(iter(), options)?)
+    }
 
-        #[cfg(not(unix))]
-        fn os_str_to_bytes(name: &OsStr) -> impl AsRef<[u8]> + '_ {
-            match name.to_string_lossy() {
-                Cow::Borrowed(name) => Cow::Borrowed(name.as_bytes()),
-                Cow::Owned(name) => Cow::Owned(name.into_bytes()),
-            }
-        }
+    /// Builds the phar from the entries in a `Plan`.
+    ///
+    /// Files are opened by their paths in the plan,
+    /// so they should not be removed after the plan is created.
+    pub fn build_from_plan(self, mut plan: &Plan) -> Result<()> {
+        // every planned entry has its own compression, so the default is unused
+        self.build_from_source(&mut plan, Compression::None)
+    }
 
-        #[cfg(unix)]
-        fn stat_to_mode(permissions: fs::Permissions) -> u32 {
-            use std::os::unix::fs::PermissionsExt;
-            permissions.mode()
-        }
-        #[cfg(not(unix))]
-        fn stat_to_mode(permissions: fs::Permissions) -> u32 {
-            if permissions.readonly() {
-                0o444
+    /// Builds the phar from the entries in a `Source`.
+    ///
+    /// Files without their own compression are compressed with `options.compression`.
+    /// Directory entries returned by the source are always added,
+    /// regardless of `options.directories`.
+    pub fn build_from_source<S: Source>(
+        mut self,
+        source: &mut S,
+        options: impl Into<BuildOptions>,
+    ) -> Result<()> {
+        let options = options.into();
+        let entries = source.list()?;
+        for entry in &entries {
+            if entry.is_dir {
+                self.directory(
+                    &entry.name[..],
+                    &entry.metadata[..],
+                    entry.modified,
+                    entry.mode,
+                )?;
             } else {
-                0o664
-            }
-        }
-
-        for pair in iter() {
-            let (name, file) = pair?;
-            let stat = file.as_ref().metadata()?;
-            if stat.is_file() {
                 self.entry(
-                    os_str_to_bytes(name.as_ref()).as_ref(),
-                    &b""[..],
-                    stat.modified()?,
-                    stat_to_mode(stat.permissions()),
-                    compression,
+                    &entry.name[..],
+                    &entry.metadata[..],
+                    entry.modified,
+                    entry.mode,
+                    entry.compression.unwrap_or(options.compression),
                 )?;
             }
         }
         let mut contents = self.contents()?;
-        for pair in iter() {
-            let (_, file) = pair?;
-            contents.feed(fs::File::open(file)?)?;
+        for entry in &entries {
+            if !entry.is_dir {
+                contents.feed(source.open(&entry.id)?)?;
+            }
         }
         Ok(())
     }
 }
 
 struct WriteEntry {
-    uncompressed_offset: u64,
-    compression: Compression,
+    name: Vec<u8>,
+    /// The offset of the fields written before the contents are known
+    fields_offset: u64,
+    fields: EntryFields,
+    /// `None` if the entry was added with `raw_entry`
+    compression: Option<Compression>,
+}
+
+/// The manifest fields of an entry added with `NeedEntries::raw_entry`.
+///
+/// With the `reader` feature, this can be converted from a `read::EntryInfo`
+/// to copy an entry from another archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawEntry {
+    /// The size of the decompressed contents
+    pub original_size: u32,
+    /// The modification time as a Unix timestamp
+    pub timestamp: i32,
+    /// The CRC32 checksum of the decompressed contents
+    pub crc32: u32,
+    /// The entry flags, including the permission bits and the compression method
+    pub flags: u32,
 }
 
 /// Step for writing phar file contents.
@@ -321,20 +459,26 @@ pub struct Contents<W: Read + Write + Seek> {
     ptr: Option<usize>,
     signature: Option<Signature>,
     end_offset: u64,
+    observer: Option<Box<dyn Observer + Send>>,
+    started: Instant,
+    totals: Totals,
 }
 
 impl<W: Read + Write + Seek> Contents<W> {
     /// Passes the content source for the next file entry.
-    pub fn feed(&mut self, read: impl Read) -> Result<()> {
-        fn try_feed(
-            entry: &WriteEntry,
-            mut read: impl Read,
-            mut write: impl Write + Seek,
-            start_offset: u64,
-        ) -> Result<u64> {
-            let start = write.seek(SeekFrom::Start(start_offset))?;
-
-            let mut comp_write = entry.compression.make_write(&mut write)?;
+    ///
+    /// Returns an `InvalidInput` error if the entry was added with `raw_entry`.
+    pub fn feed(&mut self, mut read: impl Read) -> Result<()> {
+        self.feed_next(|entry, mut write| {
+            let compression = entry.compression.ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    "feed() called for an entry added with raw_entry()",
+                )
+            })?;
+            let start = tell(&mut write)?;
+
+            let mut comp_write = compression.make_write(&mut write)?;
 
             let mut cksum = Crc32Writer::default();
 
@@ -349,41 +493,45 @@ impl<W: Read + Write + Seek> Contents<W> {
             drop(comp_write);
 
             let end = tell(&mut write)?;
-            let compressed_size = end - start;
+            let mut fields = entry.fields;
+            fields.set_contents(uncompressed_size, end - start, cksum.finish())?;
+            let _ = write.seek(SeekFrom::Start(entry.fields_offset))?;
+            write.write_all(&fields.to_bytes())?;
 
-            let _ = write.seek(SeekFrom::Start(entry.uncompressed_offset))?;
-            write.write_u32::<LittleEndian>(
-                uncompressed_size
-                    .try_into()
-                    .map_err(|_| Error::new(ErrorKind::Other, "content is too large"))?,
-            )?;
-            let _ = write.seek(SeekFrom::Current(4))?; // unix timestamp already written
-            write.write_u32::<LittleEndian>(
-                compressed_size
-                    .try_into()
-                    .map_err(|_| Error::new(ErrorKind::Other, "content is too large"))?,
-            )?;
-            write.write_u32::<LittleEndian>(cksum.finish())?;
+            Ok((end, uncompressed_size))
+        })
+    }
 
-            Ok(end)
-        }
+    /// Passes the contents of the next entry added with `raw_entry`,
+    /// which are written verbatim without compression or checksumming.
+    ///
+    /// Returns an `InvalidInput` error if the entry was added with `entry`.
+    pub fn feed_raw(&mut self, mut read: impl Read) -> Result<()> {
+        self.feed_next(|entry, mut write| {
+            if entry.compression.is_some() {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "feed_raw() called for an entry not added with raw_entry()",
+                ));
+            }
+            let compressed_size = io::copy(&mut read, &mut write)?;
+            let end = tell(&mut write)?;
 
-        fn write_signature(
-            mut stream: impl Read + Write + Seek,
-            end_offset: u64,
-            mut signature: Signature,
-        ) -> Result<()> {
-            let _ = stream.seek(SeekFrom::Start(0))?;
-            let _ = io::copy(&mut (&mut stream).take(end_offset), &mut signature.write())?;
-            let sig_id = signature.to_u32();
-            let bytes = signature.finalize();
-            let _ = stream.seek(SeekFrom::Start(end_offset))?;
-            stream.write_all(&bytes[..])?;
-            stream.write_u32::<LittleEndian>(sig_id)?;
-            stream.write_all(PHAR_TERMINATOR)?;
-            Ok(())
-        }
+            let mut fields = entry.fields;
+            fields.set_compressed_size(compressed_size)?;
+            let _ = write.seek(SeekFrom::Start(entry.fields_offset))?;
+            write.write_all(&fields.to_bytes())?;
+
+            Ok((end, compressed_size))
+        })
+    }
 
+    /// Writes the contents of the next entry with `feed`,
+    /// which returns the offset after the contents and the number of bytes read.
+    fn feed_next<F>(&mut self, feed: F) -> Result<()>
+    where
+        F: FnOnce(&WriteEntry, &mut W) -> Result<(u64, u64)>,
+    {
         let ptr = match self.ptr {
             Some(ptr) => ptr,
             None => {
@@ -398,19 +546,42 @@ impl<W: Read + Write + Seek> Contents<W> {
             entries,
             end_offset,
             signature,
+            observer,
+            started,
+            totals,
             ..
         } = self;
         let entry = match entries.get(ptr) {
             Some(entry) => entry,
             None => return Err(Error::new(ErrorKind::Other, "feed() called too many times")),
         };
-        let ret = try_feed(entry, read, &mut *write, *end_offset);
+        let entry_started = Instant::now();
+        let ret = write
+            .seek(SeekFrom::Start(*end_offset))
+            .and_then(|_| feed(entry, &mut *write));
         match &ret {
-            Ok(new_end_offset) => {
+            Ok((new_end_offset, bytes_read)) => {
                 self.ptr = Some(ptr + 1);
-                if entries.get(ptr + 1).is_none() {
+                let is_last = entries.get(ptr + 1).is_none();
+                if is_last {
                     write_signature(write, *new_end_offset, signature.take().expect("last call"))?;
                 }
+                if let Some(observer) = observer {
+                    let event = EntryEvent {
+                        name: &entry.name,
+                        index: ptr,
+                        total: entries.len(),
+                        bytes_read: *bytes_read,
+                        bytes_written: new_end_offset - *end_offset,
+                        elapsed: entry_started.elapsed(),
+                    };
+                    observer.entry_done(&event);
+                    totals.add(&event);
+                    if is_last {
+                        totals.elapsed = started.elapsed();
+                        observer.finished(totals);
+                    }
+                }
                 self.end_offset = *new_end_offset;
             }
             Err(_) => self.ptr = None,
@@ -418,3 +589,15 @@ impl<W: Read + Write + Seek> Contents<W> {
         ret.map(|_| ())
     }
 }
+
+fn write_signature(
+    mut stream: impl Read + Write + Seek,
+    end_offset: u64,
+    mut signature: Signature,
+) -> Result<()> {
+    let _ = stream.seek(SeekFrom::Start(0))?;
+    let _ = io::copy(&mut (&mut stream).take(end_offset), &mut signature.write())?;
+    let _ = stream.seek(SeekFrom::Start(end_offset))?;
+    stream.write_all(&signature_trailer(signature))?;
+    Ok(())
+}
@@ -0,0 +1,120 @@
+use std::io::{Read, Result, Seek};
+use std::time::{Duration, SystemTime};
+
+use super::phar_dir::{self, PharDir};
+use super::{Source, SourceEntry};
+use crate::util::PERMISSION_MASK;
+
+/// The file type bits in the file mode.
+const FILE_TYPE_MASK: u32 = 0o170_000;
+
+/// The file type of symbolic links.
+const SYMLINK_TYPE: u32 = 0o120_000;
+
+/// The files and directories in a zip archive.
+///
+/// Symbolic links stored by Unix tools are skipped.
+/// Reading bzip2 compressed files requires the `comp-bzip` feature.
+/// Entries without Unix permissions get `0o644` for files and `0o755` for directories.
+/// Zip archives store modification times without a time zone,
+/// so they are interpreted as UTC.
+///
+/// Files in the `.phar` directory of zip based phars are not listed as entries.
+/// File comments are used as entry metadata,
+/// and the stub, alias and archive comment are returned by `phar_dir`.
+/// The `zip` crate decodes file comments as UTF-8 or CP437 text,
+/// so entry metadata is only preserved exactly if it is valid UTF-8
+/// and the UTF-8 flag of the file is set.
+#[derive(Debug)]
+pub struct ZipSource<R: Read + Seek> {
+    archive: zip::ZipArchive<R>,
+}
+
+impl<R: Read + Seek> ZipSource<R> {
+    /// Reads the central directory of the zip archive in `read`.
+    pub fn new(read: R) -> Result<Self> {
+        Ok(Self {
+            archive: zip::ZipArchive::new(read)?,
+        })
+    }
+
+    /// Reads the stub, alias and signature algorithm from the `.phar` directory
+    /// and the global metadata from the archive comment of a zip based phar.
+    pub fn phar_dir(&mut self) -> Result<PharDir> {
+        let mut dir = PharDir {
+            metadata: self.archive.comment().to_vec(),
+            ..PharDir::default()
+        };
+        for index in 0..self.archive.len() {
+            let file = self.archive.by_index(index)?;
+            let name = file.name_raw().to_vec();
+            dir.read_file(&name, file)?;
+        }
+        Ok(dir)
+    }
+}
+
+impl<R: Read + Seek> Source for ZipSource<R> {
+    /// The index of the file in the archive
+    type Id = usize;
+
+    fn list(&mut self) -> Result<Vec<SourceEntry<usize>>> {
+        let mut entries = Vec::new();
+        for index in 0..self.archive.len() {
+            let file = self.archive.by_index_raw(index)?;
+            if phar_dir::contains(file.name_raw()) {
+                continue;
+            }
+            let is_dir = file.is_dir();
+            let mode = match file.unix_mode() {
+                Some(mode) if mode & FILE_TYPE_MASK == SYMLINK_TYPE => continue,
+                Some(mode) => mode & PERMISSION_MASK,
+                None if is_dir => 0o755,
+                None => 0o644,
+            };
+
+            let time = file.last_modified();
+            entries.push(SourceEntry {
+                name: file.name_raw().to_vec(),
+                metadata: file.comment().as_bytes().to_vec(),
+                modified: utc_time(
+                    time.year(),
+                    time.month(),
+                    time.day(),
+                    time.hour(),
+                    time.minute(),
+                    time.second(),
+                ),
+                mode,
+                is_dir,
+                compression: None,
+                id: index,
+            });
+        }
+        Ok(entries)
+    }
+
+    fn open(&mut self, index: &usize) -> Result<Box<dyn Read + '_>> {
+        Ok(Box::new(self.archive.by_index(*index)?))
+    }
+}
+
+/// Converts a UTC calendar date and time into a `SystemTime`.
+///
+/// Zip dates are always in 1980..=2107, so the result is never before the Unix epoch.
+fn utc_time(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> SystemTime {
+    // days from civil, counting years from March so that leap days are at the end
+    let (year, month) = if month <= 2 {
+        (u64::from(year) - 1, u64::from(month) + 9)
+    } else {
+        (u64::from(year), u64::from(month) - 3)
+    };
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let day_of_year = (153 * month + 2) / 5 + u64::from(day).saturating_sub(1);
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = (era * 146_097 + day_of_era).saturating_sub(719_468);
+
+    let secs = days * 86400 + u64::from(hour) * 3600 + u64::from(minute) * 60 + u64::from(second);
+    SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+}
@@ -0,0 +1,157 @@
+#![cfg(feature = "async")]
+
+use std::collections::HashMap;
+use std::io::{self, Cursor, Result};
+use std::time::SystemTime;
+
+use phar::{read, AsyncReader, Compression, Level, Reader, Signature};
+use tokio::io::AsyncReadExt;
+
+async fn write_mixed() -> Result<Vec<u8>> {
+    let mut file = Cursor::new(Vec::new());
+    let mut writer = phar::create_async(&mut file, Signature::sha256())
+        .stub(&b"<?php "[..])
+        .await?
+        .metadata(&br#"s:3:"met";"#[..])
+        .await?;
+    writer
+        .entry(
+            &b"foo"[..],
+            &b""[..],
+            SystemTime::now(),
+            0o664,
+            Compression::Zlib(Level::BEST),
+        )
+        .await?;
+    writer
+        .entry(
+            &b"qux"[..],
+            &b""[..],
+            SystemTime::now(),
+            0o664,
+            Compression::Bzip(Level::BEST),
+        )
+        .await?;
+    let mut contents = writer.contents().await?;
+    contents.feed(&b"bar"[..]).await?;
+    contents.feed(&b"corge"[..]).await?;
+    Ok(file.into_inner())
+}
+
+#[tokio::test]
+async fn test_async_write_sync_read() -> Result<()> {
+    let data = write_mixed().await?;
+
+    let mut reader = Reader::<_, read::index::OffsetOnly>::read(
+        io::Cursor::new(data),
+        read::Options::builder().build(),
+    )?;
+    assert_eq!(
+        reader.stub_bytes()?.as_ref(),
+        b"<?php __HALT_COMPILER(); ?>\r\n"
+    );
+
+    let mut contents = HashMap::new();
+    reader.for_each_file(|name, read| {
+        let mut vec = vec![];
+        read.read_to_end(&mut vec)?;
+        contents.insert(name.to_vec(), vec);
+        Ok(())
+    })?;
+    assert_eq!(contents.len(), 2);
+    assert_eq!(contents.get(&b"foo"[..]), Some(&b"bar".to_vec()));
+    assert_eq!(contents.get(&b"qux"[..]), Some(&b"corge".to_vec()));
+
+    Ok(())
+}
+
+async fn verify_async<T: read::index::Iterable>() -> Result<()> {
+    let data = write_mixed().await?;
+
+    let mut reader =
+        AsyncReader::<_, T>::read(Cursor::new(data), read::Options::builder().build()).await?;
+    assert_eq!(
+        &reader.stub_bytes().await?[..],
+        b"<?php __HALT_COMPILER(); ?>\r\n"
+    );
+    assert_eq!(&reader.metadata_bytes().await?[..], br#"s:3:"met";"#);
+
+    let mut contents = HashMap::new();
+    reader
+        .for_each_file(&mut contents, |contents, name, read| {
+            Box::pin(async move {
+                let mut vec = vec![];
+                read.read_to_end(&mut vec).await?;
+                contents.insert(name.to_vec(), vec);
+                Ok(())
+            })
+        })
+        .await?;
+    assert_eq!(contents.len(), 2);
+    assert_eq!(contents.get(&b"foo"[..]), Some(&b"bar".to_vec()));
+    assert_eq!(contents.get(&b"qux"[..]), Some(&b"corge".to_vec()));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_async_read_offset_only() -> Result<()> {
+    verify_async::<read::index::OffsetOnly>().await
+}
+
+#[tokio::test]
+async fn test_async_read_metadata_map() -> Result<()> {
+    verify_async::<read::index::MetadataBTreeMap>().await
+}
+
+#[tokio::test]
+async fn test_async_read_bad_signature() -> Result<()> {
+    let mut data = write_mixed().await?;
+    let first_content = data.len() - 8 - 32 - 1;
+    data[first_content] ^= 1;
+
+    let result = AsyncReader::<_, read::index::NoIndex>::read(
+        Cursor::new(data),
+        read::Options::builder().build(),
+    )
+    .await;
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_async_reader_is_send() {
+    fn assert_send<T: Send>(_: T) {}
+    assert_send(AsyncReader::<_, read::index::NameHashMap>::read(
+        Cursor::new(Vec::new()),
+        read::Options::builder().build(),
+    ));
+}
+
+#[tokio::test]
+async fn test_async_validate() -> Result<()> {
+    let mut data = write_mixed().await?;
+
+    let mut reader = AsyncReader::<_, read::index::NoIndex>::read(
+        Cursor::new(&data),
+        read::Options::builder().strict(true).build(),
+    )
+    .await?;
+    assert_eq!(reader.validate().await?, vec![]);
+
+    // append garbage after the signature
+    data.extend_from_slice(b"garbage!");
+    let err = AsyncReader::<_, read::index::NoIndex>::read(
+        Cursor::new(&data),
+        read::Options::builder()
+            .verify_signature(false)
+            .strict(true)
+            .build(),
+    )
+    .await
+    .expect_err("strict mode accepts trailing garbage");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    Ok(())
+}
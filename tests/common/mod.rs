@@ -0,0 +1,62 @@
+//! Fixtures shared by the integration tests
+
+#![allow(dead_code)]
+
+use std::io::{self, Read, Result, Seek, Write};
+use std::time::{Duration, SystemTime};
+
+use phar::write::NeedEntries;
+use phar::{Compression, Signature};
+
+/// The modification time of all fixture entries.
+pub fn time() -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(2000)
+}
+
+/// Declares `files` as `(name, contents, compression)` and writes their contents.
+///
+/// Names ending with a slash are directories with permissions `0o755`,
+/// whose contents are ignored.
+/// Other names are files with permissions `0o664`.
+/// The metadata of each entry is its name,
+/// so that tests can tell whether entry metadata is preserved.
+pub fn write_entries<W, C>(
+    mut writer: NeedEntries<W>,
+    files: &[(&str, C, Compression)],
+) -> Result<()>
+where
+    W: Read + Write + Seek,
+    C: AsRef<[u8]>,
+{
+    for (name, _, compression) in files {
+        if name.ends_with('/') {
+            writer.directory(name.as_bytes(), name.as_bytes(), time(), 0o755)?;
+        } else {
+            writer.entry(
+                name.as_bytes(),
+                name.as_bytes(),
+                time(),
+                0o664,
+                *compression,
+            )?;
+        }
+    }
+    let mut contents = writer.contents()?;
+    for (name, data, _) in files {
+        if !name.ends_with('/') {
+            contents.feed(data.as_ref())?;
+        }
+    }
+    Ok(())
+}
+
+/// Builds a phar with the stub `<?php `, empty global metadata and a SHA-256 signature
+/// containing the entries described in `write_entries`.
+pub fn build<C: AsRef<[u8]>>(files: &[(&str, C, Compression)]) -> Result<Vec<u8>> {
+    let mut file = io::Cursor::new(Vec::new());
+    let writer = phar::create(&mut file, Signature::sha256())
+        .stub(&b"<?php "[..])?
+        .metadata(&b""[..])?;
+    write_entries(writer, files)?;
+    Ok(file.into_inner())
+}
@@ -0,0 +1,156 @@
+use std::io::{self, Read, Result};
+
+use phar::merge::{self, Conflicts, Input};
+use phar::read::{self, index::NameIndexMap};
+use phar::{Compression, Level, Reader, Signature};
+
+mod common;
+
+type Source = Reader<io::Cursor<Vec<u8>>, NameIndexMap>;
+
+fn source(stub: &str, files: &[(&str, &str, Compression)]) -> Result<Source> {
+    let mut file = io::Cursor::new(Vec::new());
+    let writer = phar::create(&mut file, Signature::sha256())
+        .stub(stub.as_bytes())?
+        .alias(stub.as_bytes())?
+        .metadata(&b""[..])?;
+    let mut entries = vec![("src/", "", Compression::None)];
+    entries.extend_from_slice(files);
+    common::write_entries(writer, &entries)?;
+    Reader::read(
+        io::Cursor::new(file.into_inner()),
+        read::Options::builder().build(),
+    )
+}
+
+fn inputs() -> Result<Vec<Input<io::Cursor<Vec<u8>>, NameIndexMap>>> {
+    Ok(vec![
+        Input::new(source(
+            "<?php // a",
+            &[
+                ("src/main.php", "main a", Compression::Zlib(Level::BEST)),
+                ("README", "readme a", Compression::None),
+            ],
+        )?),
+        Input::new(source(
+            "<?php // b",
+            &[
+                ("src/lib.php", "lib b", Compression::Bzip(Level::BEST)),
+                ("README", "readme b", Compression::Zlib(Level::BEST)),
+            ],
+        )?),
+    ])
+}
+
+fn merged(
+    inputs: &mut [Input<io::Cursor<Vec<u8>>, NameIndexMap>],
+    options: merge::Options,
+) -> Result<Source> {
+    let mut output = io::Cursor::new(Vec::new());
+    merge::merge(inputs, &mut output, Signature::sha512(), options)?;
+    let mut phar = Reader::read(
+        io::Cursor::new(output.into_inner()),
+        read::Options::builder().strict(true).build(),
+    )?;
+    phar.verify_crc32()?;
+    Ok(phar)
+}
+
+fn contents(phar: &mut Source) -> Result<Vec<(String, String)>> {
+    let mut contents = Vec::new();
+    phar.for_each_entry(|mut entry| {
+        let mut data = String::new();
+        let _ = entry.open()?.read_to_string(&mut data)?;
+        contents.push((String::from_utf8_lossy(entry.name()).into_owned(), data));
+        Ok(())
+    })?;
+    Ok(contents)
+}
+
+fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+        .iter()
+        .map(|(name, data)| (name.to_string(), data.to_string()))
+        .collect()
+}
+
+#[test]
+fn test_merge_conflicts() -> Result<()> {
+    let err = merged(&mut inputs()?, merge::Options::default()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert!(err.to_string().contains("\"README\" in inputs 0 and 1"));
+
+    let mut phar = merged(
+        &mut inputs()?,
+        merge::Options::builder()
+            .conflicts(Conflicts::KeepFirst)
+            .build(),
+    )?;
+    assert_eq!(
+        contents(&mut phar)?,
+        pairs(&[
+            ("src/", ""),
+            ("src/main.php", "main a"),
+            ("README", "readme a"),
+            ("src/lib.php", "lib b"),
+        ])
+    );
+
+    let mut phar = merged(
+        &mut inputs()?,
+        merge::Options::builder()
+            .conflicts(Conflicts::KeepLast)
+            .stub_from(1)
+            .build(),
+    )?;
+    assert_eq!(
+        contents(&mut phar)?,
+        pairs(&[
+            ("src/", ""),
+            ("src/main.php", "main a"),
+            ("src/lib.php", "lib b"),
+            ("README", "readme b"),
+        ])
+    );
+    assert_eq!(
+        phar.stub_bytes()?.as_ref(),
+        b"<?php // b__HALT_COMPILER(); ?>\r\n"
+    );
+    assert_eq!(phar.alias_bytes()?.as_ref(), b"<?php // a");
+    assert_eq!(
+        phar.signature().map(|signature| signature.algorithm()),
+        Some(phar::SignatureAlgorithm::Sha512)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_merge_prefixes() -> Result<()> {
+    let mut prefixed: Vec<_> = inputs()?
+        .into_iter()
+        .zip(&["libs/a", "/libs/b/"])
+        .map(|(input, prefix)| input.with_prefix(prefix.as_bytes()))
+        .collect();
+    let mut phar = merged(&mut prefixed, merge::Options::default())?;
+    assert_eq!(
+        contents(&mut phar)?,
+        pairs(&[
+            ("libs/a/src/", ""),
+            ("libs/a/src/main.php", "main a"),
+            ("libs/a/README", "readme a"),
+            ("libs/b/src/", ""),
+            ("libs/b/src/lib.php", "lib b"),
+            ("libs/b/README", "readme b"),
+        ])
+    );
+
+    let err = merged(
+        &mut inputs()?,
+        merge::Options::builder().metadata_from(2).build(),
+    )
+    .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+    Ok(())
+}
@@ -1,11 +1,14 @@
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::env;
 use std::fs;
-use std::io::{self, Result};
+use std::io::{self, Read, Result, Seek};
 use std::path::PathBuf;
 
 use phar::{read, Reader};
 
+mod common;
+
 #[test]
 fn test_plain_no_index() -> Result<()> {
     let mut dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
@@ -125,3 +128,1457 @@ fn verify_std_contents<R: io::Read + io::Seek, T: phar::read::index::Iterable>(
     assert_eq!(contents.get(&b"foo"[..]), Some(&b"bar".to_vec()));
     assert_eq!(contents.get(&b"qux"[..]), Some(&b"corge".to_vec()));
 }
+
+#[test]
+fn test_large_stub_signature() -> Result<()> {
+    let mut stub = b"<?php\n".to_vec();
+    stub.resize(1 << 20, b'#');
+
+    let mut file = io::Cursor::new(Vec::new());
+    let writer = phar::create(&mut file, phar::Signature::sha256())
+        .stub(&stub[..])?
+        .metadata(&br#"s:3:"met";"#[..])?;
+    common::write_entries(writer, &[("foo", "bar", phar::Compression::None)])?;
+
+    let mut reader = Reader::<_, read::index::NameHashMap>::read(
+        io::Cursor::new(file.into_inner()),
+        read::Options::builder().build(),
+    )?;
+    let mut expected_stub = stub;
+    expected_stub.extend_from_slice(b"__HALT_COMPILER(); ?>\r\n");
+    assert_eq!(reader.stub_bytes()?.as_ref(), &expected_stub[..]);
+    assert_eq!(reader.metadata_bytes()?.as_ref(), br#"s:3:"met";"#);
+
+    Ok(())
+}
+
+/// Builds a phar equivalent to `tests/data/plain.php` with the writer.
+fn write_std_phar(compression: phar::Compression) -> Result<Vec<u8>> {
+    let mut file = io::Cursor::new(Vec::new());
+    let writer = phar::create(&mut file, phar::Signature::sha256())
+        .stub(&b"<?php "[..])?
+        .metadata(&br#"s:3:"met";"#[..])?;
+    common::write_entries(
+        writer,
+        &[("foo", "bar", compression), ("qux", "corge", compression)],
+    )?;
+    Ok(file.into_inner())
+}
+
+#[test]
+fn test_slice_offset_only() -> Result<()> {
+    let data = write_std_phar(phar::Compression::None)?;
+    let reader = read::SliceReader::<read::index::OffsetOnly>::read(
+        &data,
+        read::Options::builder().build(),
+    )?;
+
+    assert_eq!(reader.stub()?, b"<?php __HALT_COMPILER(); ?>\r\n");
+    assert_eq!(reader.metadata()?, br#"s:3:"met";"#);
+    assert_eq!(reader.alias()?, b"");
+
+    let mut contents = HashMap::new();
+    reader.for_each_file(|name, entry| {
+        let slice = entry.as_slice().expect("entry is not compressed");
+        assert!(data.as_ptr_range().contains(&slice.as_ptr()));
+        contents.insert(name.to_vec(), slice.to_vec());
+        Ok(())
+    })?;
+    assert_eq!(contents.len(), 2);
+    assert_eq!(contents.get(&b"foo"[..]), Some(&b"bar".to_vec()));
+    assert_eq!(contents.get(&b"qux"[..]), Some(&b"corge".to_vec()));
+
+    Ok(())
+}
+
+#[test]
+fn test_slice_zlib_name_map() -> Result<()> {
+    let data = write_std_phar(phar::Compression::Zlib(phar::Level::BEST))?;
+    let reader = read::SliceReader::<read::index::NameHashMap>::read(
+        &data,
+        read::Options::builder().build(),
+    )?;
+
+    let mut contents = HashMap::new();
+    reader.for_each_file(|name, entry| {
+        assert!(entry.as_slice().is_none());
+        contents.insert(name.to_vec(), entry.contents()?.into_owned());
+        Ok(())
+    })?;
+    assert_eq!(contents.len(), 2);
+    assert_eq!(contents.get(&b"foo"[..]), Some(&b"bar".to_vec()));
+    assert_eq!(contents.get(&b"qux"[..]), Some(&b"corge".to_vec()));
+
+    Ok(())
+}
+
+#[test]
+fn test_estimate_compression() -> Result<()> {
+    let level = |level| phar::Level::new(level).expect("valid level");
+    assert_eq!(phar::Level::new(0), None);
+    assert_eq!(phar::Level::new(10), None);
+
+    for (written, estimated) in &[
+        (phar::Compression::None, Some(phar::Compression::None)),
+        (
+            phar::Compression::Bzip(level(3)),
+            Some(phar::Compression::Bzip(level(3))),
+        ),
+        (phar::Compression::Zlib(phar::Level::BEST), None),
+    ] {
+        let data = write_std_phar(*written)?;
+        let slice = read::SliceReader::<read::index::NameHashMap>::read(
+            &data,
+            read::Options::builder().build(),
+        )?;
+        slice.for_each_file(|_, entry| {
+            assert_eq!(entry.compression(), written.method());
+            assert_eq!(entry.estimate_compression(), *estimated);
+            Ok(())
+        })?;
+
+        let mut phar = Reader::<_, read::index::NameHashMap>::read(
+            io::Cursor::new(&data),
+            read::Options::builder().build(),
+        )?;
+        phar.for_each_entry(|mut entry| {
+            assert_eq!(entry.estimate_compression()?, *estimated);
+            Ok(())
+        })?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_open_compressed_archive() -> Result<()> {
+    let plain = write_std_phar(phar::Compression::Zlib(phar::Level::DEFAULT))?;
+    let level = phar::Level::new(3).expect("valid level");
+
+    for compression in &[
+        phar::ArchiveCompression::None,
+        phar::ArchiveCompression::Gzip(phar::Level::BEST),
+        phar::ArchiveCompression::Bzip(level),
+    ] {
+        let mut data = Vec::new();
+        let size = phar::write::compress_archive(&plain[..], &mut data, *compression)?;
+        assert_eq!(size, u64::try_from(plain.len()).expect("usize <= u64"));
+
+        let mut phar = Reader::<_, read::index::NameHashMap>::open(
+            io::Cursor::new(&data),
+            read::Options::builder().strict(true).build(),
+        )?;
+        assert_eq!(phar.archive_compression(), *compression);
+        assert!(phar.signature().expect("signed").is_verified());
+        phar.verify_crc32()?;
+
+        #[cfg(feature = "tempfile")]
+        {
+            let mut phar = Reader::<_, read::index::NameHashMap>::open(
+                io::Cursor::new(&data),
+                read::Options::builder().spill_threshold(16).build(),
+            )?;
+            phar.verify_crc32()?;
+        }
+
+        if *compression != phar::ArchiveCompression::None {
+            let limit = u64::try_from(plain.len() - 1).expect("usize <= u64");
+            assert_eq!(
+                limit_error(Reader::<_, read::index::NameHashMap>::open(
+                    io::Cursor::new(&data),
+                    read::Options::builder().max_archive_size(limit).build(),
+                )),
+                read::LimitExceeded::ArchiveSize { limit }
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_default_archive_size_limit() -> Result<()> {
+    // gzip members are concatenated, so a small file decompresses to over 1 GiB
+    let mut member = Vec::new();
+    let _ = phar::write::compress_archive(
+        io::repeat(0).take(1 << 20),
+        &mut member,
+        phar::ArchiveCompression::Gzip(phar::Level::BEST),
+    )?;
+    let data = member.repeat((1 << 10) + 1);
+
+    assert_eq!(
+        limit_error(Reader::<_, read::index::NameHashMap>::open(
+            io::Cursor::new(&data),
+            read::Options::default(),
+        )),
+        read::LimitExceeded::ArchiveSize { limit: 1 << 30 }
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn test_slice_mmap() -> Result<()> {
+    let mut dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    dir.push("tests/output");
+    let path = dir.join("test_slice_mmap.phar");
+    fs::write(&path, write_std_phar(phar::Compression::None)?)?;
+
+    let file = fs::File::open(&path)?;
+    // safety: the file is not modified by the test after this point
+    let map = unsafe { read::map_file(&file)? };
+    let reader = read::SliceReader::<read::index::NameHashMap>::read(
+        &map,
+        read::Options::builder().build(),
+    )?;
+    assert_eq!(reader.stub()?, b"<?php __HALT_COMPILER(); ?>\r\n");
+
+    Ok(())
+}
+
+fn limit_error<T>(result: Result<T>) -> read::LimitExceeded {
+    let err = match result {
+        Ok(_) => panic!("limit is not enforced"),
+        Err(err) => err,
+    };
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    err.get_ref()
+        .and_then(|err| err.downcast_ref::<read::LimitExceeded>())
+        .expect("error is not LimitExceeded")
+        .clone()
+}
+
+#[test]
+fn test_header_limits() -> Result<()> {
+    let data = write_std_phar(phar::Compression::None)?;
+    let read_with =
+        |options| Reader::<_, read::index::NameHashMap>::read(io::Cursor::new(&data), options);
+
+    assert!(matches!(
+        limit_error(read_with(
+            read::Options::builder().max_manifest_size(16).build()
+        )),
+        read::LimitExceeded::ManifestSize { limit: 16, .. }
+    ));
+    assert_eq!(
+        limit_error(read_with(read::Options::builder().max_entries(1).build())),
+        read::LimitExceeded::Entries { count: 2, limit: 1 }
+    );
+    assert_eq!(
+        limit_error(read_with(read::Options::builder().max_name_len(2).build())),
+        read::LimitExceeded::NameLength { len: 3, limit: 2 }
+    );
+    assert_eq!(
+        limit_error(read_with(
+            read::Options::builder().max_metadata_len(9).build()
+        )),
+        read::LimitExceeded::MetadataLength { len: 10, limit: 9 }
+    );
+
+    let _ = read_with(
+        read::Options::builder()
+            .max_manifest_size(1024)
+            .max_entries(2)
+            .max_name_len(3)
+            .max_metadata_len(10)
+            .build(),
+    )?;
+
+    Ok(())
+}
+
+#[test]
+fn test_decompression_limits() -> Result<()> {
+    let data = common::build(&[(
+        "bomb",
+        vec![0; 1 << 20],
+        phar::Compression::Zlib(phar::Level::BEST),
+    )])?;
+
+    let drain = |options| -> Result<()> {
+        let mut reader =
+            Reader::<_, read::index::OffsetOnly>::read(io::Cursor::new(&data), options)?;
+        reader.for_each_file(|_, read| io::copy(read, &mut io::sink()).map(|_| ()))
+    };
+
+    assert!(matches!(
+        limit_error(drain(
+            read::Options::builder().max_compression_ratio(100).build()
+        )),
+        read::LimitExceeded::CompressionRatio { limit: 100, .. }
+    ));
+    assert_eq!(
+        limit_error(drain(
+            read::Options::builder().max_entry_size(1 << 19).build()
+        )),
+        read::LimitExceeded::EntrySize { limit: 1 << 19 }
+    );
+    drain(read::Options::builder().max_entry_size(1 << 20).build())?;
+
+    let slice = read::SliceReader::<read::index::OffsetOnly>::read(
+        &data,
+        read::Options::builder().max_compression_ratio(100).build(),
+    )?;
+    assert!(matches!(
+        limit_error(slice.for_each_file(|_, entry| entry.contents().map(|_| ()))),
+        read::LimitExceeded::CompressionRatio { limit: 100, .. }
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_validate_consistent() -> Result<()> {
+    for &compression in &[
+        phar::Compression::None,
+        phar::Compression::Zlib(phar::Level::DEFAULT),
+        phar::Compression::Bzip(phar::Level::DEFAULT),
+    ] {
+        let data = write_std_phar(compression)?;
+
+        let mut phar = Reader::<_, read::index::NoIndex>::read(
+            io::Cursor::new(&data),
+            read::Options::builder().strict(true).build(),
+        )?;
+        assert_eq!(phar.validate()?, vec![]);
+
+        let phar = read::SliceReader::<read::index::NoIndex>::read(&data, Default::default())?;
+        assert_eq!(phar.validate()?, vec![]);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_validate_inconsistent() -> Result<()> {
+    let mut data = write_std_phar(phar::Compression::None)?;
+    let manifest_offset =
+        read::SliceReader::<read::index::NoIndex>::read(&data, Default::default())?
+            .stub()?
+            .len();
+    let sig_offset = data.len() - 8 - 32;
+
+    let mut patch = |offset: usize, bytes: &[u8]| {
+        data.get_mut(offset..offset + bytes.len())
+            .expect("offset is within the file")
+            .copy_from_slice(bytes);
+    };
+    // API version 2.1.0
+    patch(manifest_offset + 8, &[0x21, 0]);
+    // clear the signature flag
+    patch(manifest_offset + 10, &0u32.to_le_bytes());
+    // compressed size of "foo" is 4 instead of 3
+    let entry_offset = manifest_offset + 4 + 4 + 2 + 4 + 4 + 4 + 10;
+    patch(entry_offset + 4 + 3 + 8, &4u32.to_le_bytes());
+
+    let to_u64 = |offset: usize| u64::try_from(offset).expect("usize <= u64");
+    let expected = vec![
+        read::Diagnostic {
+            offset: to_u64(manifest_offset + 8),
+            kind: read::DiagnosticKind::ApiVersion {
+                version: phar::ApiVersion {
+                    major: 2,
+                    minor: 1,
+                    release: 0,
+                },
+            },
+        },
+        read::Diagnostic {
+            offset: to_u64(entry_offset),
+            kind: read::DiagnosticKind::UncompressedSize {
+                compressed: 4,
+                original: 3,
+            },
+        },
+        read::Diagnostic {
+            offset: to_u64(sig_offset),
+            kind: read::DiagnosticKind::UndeclaredSignature,
+        },
+        read::Diagnostic {
+            offset: to_u64(sig_offset),
+            kind: read::DiagnosticKind::ContentsEnd {
+                end: to_u64(sig_offset + 1),
+                expected: to_u64(sig_offset),
+            },
+        },
+    ];
+
+    let options = || read::Options::builder().verify_signature(false);
+    let mut phar =
+        Reader::<_, read::index::NoIndex>::read(io::Cursor::new(&data), options().build())?;
+    assert_eq!(phar.validate()?, expected);
+
+    let err = match Reader::<_, read::index::NoIndex>::read(
+        io::Cursor::new(&data),
+        options().strict(true).build(),
+    ) {
+        Ok(_) => panic!("strict mode accepts inconsistent file"),
+        Err(err) => err,
+    };
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    let err = err
+        .get_ref()
+        .and_then(|err| err.downcast_ref::<read::ValidationError>())
+        .expect("error is not ValidationError");
+    assert_eq!(err.diagnostics(), &expected[..]);
+
+    Ok(())
+}
+
+#[test]
+fn test_header_accessors() -> Result<()> {
+    let data = write_std_phar(phar::Compression::Zlib(phar::Level::DEFAULT))?;
+    let sig_start = data.len() - 8 - 32;
+
+    for &verify in &[true, false] {
+        let phar = Reader::<_, read::index::NoIndex>::read(
+            io::Cursor::new(&data),
+            read::Options::builder().verify_signature(verify).build(),
+        )?;
+        assert_eq!(phar.entry_count(), 2);
+        assert_eq!(phar.api_version(), phar::ApiVersion::CURRENT);
+        assert_eq!(phar.api_version().to_string(), "1.1.0");
+        assert_eq!(
+            phar.global_flags(),
+            phar::GlobalFlags::SIGNATURE | phar::GlobalFlags::COMPRESSED_GZ
+        );
+
+        let signature = phar.signature().expect("phar is signed");
+        assert_eq!(signature.algorithm(), phar::SignatureAlgorithm::Sha256);
+        assert_eq!(
+            signature.offset(),
+            u64::try_from(sig_start).expect("usize <= u64")
+        );
+        assert_eq!(
+            data.get(sig_start..data.len() - 8),
+            Some(signature.digest())
+        );
+        assert_eq!(signature.hex_digest().len(), 64);
+        assert_eq!(signature.is_verified(), verify);
+    }
+
+    let unsigned = data
+        .get(..data.len() - 8)
+        .expect("file is longer than 8 bytes");
+    let phar = Reader::<_, read::index::NoIndex>::read(
+        io::Cursor::new(unsigned),
+        read::Options::builder().verify_signature(false).build(),
+    )?;
+    assert_eq!(phar.signature(), None);
+
+    assert_eq!(
+        phar::ApiVersion::from_bytes([0x11, 0x10]),
+        phar::ApiVersion {
+            major: 1,
+            minor: 1,
+            release: 1,
+        }
+    );
+    assert_eq!(phar::ApiVersion::CURRENT.to_bytes(), [0x11, 0]);
+
+    Ok(())
+}
+
+#[test]
+fn test_directory_tree() -> Result<()> {
+    let mut file = io::Cursor::new(Vec::new());
+    let mut writer = phar::create(&mut file, phar::Signature::sha256())
+        .stub(&b"<?php "[..])?
+        .metadata(&b""[..])?;
+    let now = std::time::SystemTime::now();
+    writer.directory(&b"src"[..], &b""[..], now, 0o775)?;
+    writer.entry(
+        &b"src/a.php"[..],
+        &b""[..],
+        now,
+        0o664,
+        phar::Compression::None,
+    )?;
+    writer.directory(&b"src/empty/"[..], &b""[..], now, 0o775)?;
+    writer.entry(
+        &b"README"[..],
+        &b""[..],
+        now,
+        0o664,
+        phar::Compression::None,
+    )?;
+    writer.entry(
+        &b"lib/x/y.php"[..],
+        &b""[..],
+        now,
+        0o664,
+        phar::Compression::None,
+    )?;
+    let mut contents = writer.contents()?;
+    contents.feed(&b"a"[..])?;
+    contents.feed(&b"readme"[..])?;
+    contents.feed(&b"y"[..])?;
+    let data = file.into_inner();
+
+    let mut phar = Reader::<_, read::index::Tree>::read(
+        io::Cursor::new(&data),
+        read::Options::builder().strict(true).build(),
+    )?;
+    assert_eq!(phar.entry_count(), 5);
+
+    let list = |path: &[u8]| {
+        phar.index().read_dir(path).map(|entries| {
+            entries
+                .map(|entry| {
+                    (
+                        String::from_utf8_lossy(entry.name()).into_owned(),
+                        entry.is_dir(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        })
+    };
+    let owned = |entries: &[(&str, bool)]| {
+        Some(
+            entries
+                .iter()
+                .map(|&(name, is_dir)| (name.to_owned(), is_dir))
+                .collect::<Vec<_>>(),
+        )
+    };
+    assert_eq!(
+        list(b""),
+        owned(&[("README", false), ("lib", true), ("src", true)])
+    );
+    assert_eq!(list(b"/src/"), owned(&[("a.php", false), ("empty", true)]));
+    assert_eq!(list(b"src/empty"), owned(&[]));
+    assert_eq!(list(b"lib/x"), owned(&[("y.php", false)]));
+    assert_eq!(list(b"README"), None);
+    assert_eq!(list(b"nope"), None);
+
+    let mut files = HashMap::new();
+    phar.for_each_file(|name, read| {
+        let mut buf = Vec::new();
+        let _ = read.read_to_end(&mut buf)?;
+        files.insert(name.to_vec(), buf);
+        Ok(())
+    })?;
+    assert_eq!(files.len(), 3);
+    assert_eq!(files.get(&b"README"[..]), Some(&b"readme".to_vec()));
+
+    let mut dirs = Vec::new();
+    phar.for_each_entry(|entry| {
+        if entry.is_dir() {
+            dirs.push((entry.name().to_vec(), entry.info().flags() & 0o777));
+        }
+        Ok(())
+    })?;
+    assert_eq!(
+        dirs,
+        vec![(b"src/".to_vec(), 0o775), (b"src/empty/".to_vec(), 0o775)]
+    );
+
+    let mut offset_only = 0;
+    Reader::<_, read::index::OffsetOnly>::read(io::Cursor::new(&data), Default::default())?
+        .for_each_file(|name, _| {
+            assert!(!name.ends_with(b"/"));
+            offset_only += 1;
+            Ok(())
+        })?;
+    assert_eq!(offset_only, 3);
+
+    Ok(())
+}
+
+fn query_fixture() -> Result<Vec<u8>> {
+    let none = phar::Compression::None;
+    common::build(&[
+        ("src/", "", none),
+        ("src/a.php", "a", none),
+        ("src/lib/b.php", "b", none),
+        ("src/lib/c.txt", "c", none),
+        ("srcx.php", "x", none),
+        ("README", "readme", none),
+    ])
+}
+
+fn test_queries<I: read::index::Iterable>(data: &[u8]) -> Result<()> {
+    let mut phar = Reader::<_, I>::read(io::Cursor::new(data), read::Options::builder().build())?;
+
+    let mut names = Vec::new();
+    phar.for_each_prefix(b"src/", |entry| {
+        names.push(String::from_utf8_lossy(entry.name()).into_owned());
+        Ok(())
+    })?;
+    names.sort();
+    assert_eq!(names, vec!["src/a.php", "src/lib/b.php", "src/lib/c.txt"]);
+
+    let glob = read::Glob::new(b"**/*.php")?;
+    let mut matched = Vec::new();
+    phar.for_each_glob(&glob, |mut entry| {
+        let mut contents = String::new();
+        let _ = entry.open()?.read_to_string(&mut contents)?;
+        matched.push((String::from_utf8_lossy(entry.name()).into_owned(), contents));
+        Ok(())
+    })?;
+    matched.sort();
+    assert_eq!(
+        matched,
+        vec![
+            ("src/a.php".to_owned(), "a".to_owned()),
+            ("src/lib/b.php".to_owned(), "b".to_owned()),
+            ("srcx.php".to_owned(), "x".to_owned()),
+        ]
+    );
+
+    let slice = read::SliceReader::<I>::read(data, read::Options::builder().build())?;
+    let glob = read::Glob::new(b"src/*/*")?;
+    let mut matched = Vec::new();
+    slice.for_each_glob(&glob, |name, entry| {
+        matched.push((name.to_vec(), entry.contents()?.into_owned()));
+        Ok(())
+    })?;
+    matched.sort();
+    assert_eq!(
+        matched,
+        vec![
+            (b"src/lib/b.php".to_vec(), b"b".to_vec()),
+            (b"src/lib/c.txt".to_vec(), b"c".to_vec()),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_prefix_and_glob_queries() -> Result<()> {
+    let data = query_fixture()?;
+    test_queries::<read::index::OffsetOnly>(&data)?;
+    test_queries::<read::index::NameHashMap>(&data)?;
+    test_queries::<read::index::NameBTreeMap>(&data)?;
+    test_queries::<read::index::MetadataBTreeMap>(&data)?;
+    test_queries::<read::index::Tree>(&data)?;
+    Ok(())
+}
+
+/// Counts the bytes read from the inner stream.
+struct CountingRead<R> {
+    inner: R,
+    count: std::rc::Rc<std::cell::Cell<usize>>,
+}
+
+impl<R: Read> Read for CountingRead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.count.set(self.count.get() + len);
+        Ok(len)
+    }
+}
+
+impl<R: io::Seek> io::Seek for CountingRead<R> {
+    fn seek(&mut self, pos: io::SeekFrom) -> Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[test]
+fn test_query_does_not_open_contents() -> Result<()> {
+    let data = query_fixture()?;
+    let count = std::rc::Rc::new(std::cell::Cell::new(0));
+    let mut phar = Reader::<_, read::index::NameBTreeMap>::read(
+        CountingRead {
+            inner: io::Cursor::new(&data),
+            count: count.clone(),
+        },
+        read::Options::builder().build(),
+    )?;
+
+    count.set(0);
+    let mut sizes = Vec::new();
+    phar.for_each_prefix(b"src/lib/", |entry| {
+        sizes.push(entry.compressed_size());
+        Ok(())
+    })?;
+    assert_eq!(sizes, vec![1, 1]);
+    assert_eq!(count.get(), 0);
+
+    phar.for_each_prefix(b"src/lib/c", |mut entry| {
+        let mut contents = Vec::new();
+        let _ = entry.open()?.read_to_end(&mut contents)?;
+        assert_eq!(contents, b"c");
+        Ok(())
+    })?;
+    assert_eq!(count.get(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_entry_info_without_contents() -> Result<()> {
+    let data = query_fixture()?;
+    let count = std::rc::Rc::new(std::cell::Cell::new(0));
+    let mut phar = Reader::<_, read::index::NameHashMap>::read(
+        CountingRead {
+            inner: io::Cursor::new(&data),
+            count: count.clone(),
+        },
+        read::Options::builder().build(),
+    )?;
+
+    count.set(0);
+    let mut entries = HashMap::new();
+    phar.for_each_entry(|entry| {
+        let _ = entries.insert(entry.name().to_vec(), (entry.is_dir(), entry.info()));
+        Ok(())
+    })?;
+    assert_eq!(count.get(), 0);
+    assert_eq!(entries.len(), 6);
+
+    let (is_dir, info) = entries[&b"src/"[..]];
+    assert!(is_dir);
+    assert_eq!(info.original_size(), 0);
+
+    let (is_dir, info) = entries[&b"README"[..]];
+    assert!(!is_dir);
+    assert_eq!(info.original_size(), 6);
+    assert_eq!(info.crc32(), 0x8735_86f3);
+    assert_eq!(info.timestamp(), 2000);
+    assert_eq!(info.modified(), common::time());
+    assert_eq!(info.compression(), phar::CompressionMethod::None);
+
+    let mut names = phar.names()?;
+    names.sort();
+    assert_eq!(
+        names,
+        vec![
+            b"README".to_vec(),
+            b"src/".to_vec(),
+            b"src/a.php".to_vec(),
+            b"src/lib/b.php".to_vec(),
+            b"src/lib/c.txt".to_vec(),
+            b"srcx.php".to_vec(),
+        ]
+    );
+    assert_eq!(count.get(), 0);
+
+    let slice = read::SliceReader::<read::index::OffsetOnly>::read(
+        &data,
+        read::Options::builder().build(),
+    )?;
+    let mut sizes = Vec::new();
+    slice.for_each_entry(|name, entry| {
+        sizes.push((name.to_vec(), entry.info().original_size()));
+        Ok(())
+    })?;
+    assert_eq!(sizes.len(), 6);
+    assert!(sizes.contains(&(b"srcx.php".to_vec(), 1)));
+
+    Ok(())
+}
+
+#[test]
+fn test_manifest_order() -> Result<()> {
+    use read::index::RandomAccess;
+
+    let data = query_fixture()?;
+    let manifest_order = vec![
+        b"src/".to_vec(),
+        b"src/a.php".to_vec(),
+        b"src/lib/b.php".to_vec(),
+        b"src/lib/c.txt".to_vec(),
+        b"srcx.php".to_vec(),
+        b"README".to_vec(),
+    ];
+
+    let mut phar = Reader::<_, read::index::NameIndexMap>::read(
+        io::Cursor::new(&data),
+        read::Options::builder().build(),
+    )?;
+    assert_eq!(phar.names()?, manifest_order);
+    let range = phar.index().read_file(b"srcx.php").expect("file exists");
+    let range = usize::try_from(range.start).unwrap()..usize::try_from(range.end).unwrap();
+    assert_eq!(&data[range], b"x");
+    assert!(phar.index().read_file(b"missing").is_none());
+
+    let mut phar = Reader::<_, read::index::MetadataIndexMap>::read(
+        io::Cursor::new(&data),
+        read::Options::builder().build(),
+    )?;
+    assert_eq!(phar.names()?, manifest_order);
+
+    let mut phar = Reader::<_, read::index::NameHashMap>::read(
+        io::Cursor::new(&data),
+        read::Options::builder().build(),
+    )?;
+    let mut positions = Vec::new();
+    phar.for_each_entry(|entry| {
+        positions.push((entry.info().position(), entry.name().to_vec()));
+        Ok(())
+    })?;
+    positions.sort();
+    let names: Vec<_> = positions.into_iter().map(|(_, name)| name).collect();
+    assert_eq!(names, manifest_order);
+
+    Ok(())
+}
+
+/// Builds a phar with the entries `dup` (contents "1"), `b` (contents "2") and `dup` (contents "3").
+///
+/// The signature is invalidated.
+fn duplicate_fixture() -> Result<Vec<u8>> {
+    let none = phar::Compression::None;
+    let mut data = common::build(&[("dup", "1", none), ("b", "2", none), ("dupx", "3", none)])?;
+
+    // rename `dupx` to `dup` by shortening its name length and moving the following bytes back,
+    // which is not possible through the writer
+    let pos = data
+        .windows(4)
+        .position(|window| window == b"dupx")
+        .expect("name exists");
+    data.remove(pos + 3);
+    let len_pos = pos - 4;
+    data[len_pos] = 3;
+    let terminator = b"__HALT_COMPILER(); ?>\r\n";
+    let manifest_size_pos = data
+        .windows(terminator.len())
+        .position(|window| window == terminator)
+        .expect("stub terminator exists")
+        + terminator.len();
+    let size_bytes = &mut data[manifest_size_pos..manifest_size_pos + 4];
+    let manifest_size =
+        u32::from_le_bytes([size_bytes[0], size_bytes[1], size_bytes[2], size_bytes[3]]);
+    size_bytes.copy_from_slice(&(manifest_size - 1).to_le_bytes());
+    Ok(data)
+}
+
+#[test]
+fn test_duplicate_names() -> Result<()> {
+    let data = duplicate_fixture()?;
+    let options = |policy| {
+        read::Options::builder()
+            .verify_signature(false)
+            .duplicate_names(policy)
+            .build()
+    };
+    fn contents<I: read::index::Iterable>(
+        data: &[u8],
+        options: read::Options,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let mut phar = Reader::<_, I>::read(io::Cursor::new(data), options)?;
+        let mut files = Vec::new();
+        phar.for_each_file(|name, contents| {
+            let mut buf = Vec::new();
+            let _ = contents.read_to_end(&mut buf)?;
+            files.push((name.to_vec(), buf));
+            Ok(())
+        })?;
+        Ok(files)
+    }
+    let file = |name: &[u8], contents: &[u8]| (name.to_vec(), contents.to_vec());
+
+    assert_eq!(
+        contents::<read::index::OffsetOnly>(
+            &data,
+            read::Options::builder().verify_signature(false).build()
+        )?,
+        vec![file(b"dup", b"1"), file(b"b", b"2"), file(b"dup", b"3")],
+        "OffsetOnly does not track names by default"
+    );
+    assert_eq!(
+        contents::<read::index::NameIndexMap>(
+            &data,
+            read::Options::builder().verify_signature(false).build()
+        )?,
+        vec![file(b"b", b"2"), file(b"dup", b"3")],
+        "the last duplicate is kept by default"
+    );
+
+    let err = contents::<read::index::OffsetOnly>(&data, options(read::DuplicateNames::Error))
+        .expect_err("duplicates are rejected");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(
+        Reader::<_, read::index::NoIndex>::read(
+            io::Cursor::new(&data),
+            options(read::DuplicateNames::Error)
+        )
+        .is_ok(),
+        "NoIndex does not scan entries"
+    );
+
+    assert_eq!(
+        contents::<read::index::OffsetOnly>(&data, options(read::DuplicateNames::KeepFirst))?,
+        vec![file(b"dup", b"1"), file(b"b", b"2")]
+    );
+    assert_eq!(
+        contents::<read::index::OffsetOnly>(&data, options(read::DuplicateNames::KeepLast))?,
+        vec![file(b"b", b"2"), file(b"dup", b"3")]
+    );
+    assert_eq!(
+        contents::<read::index::OffsetOnly>(&data, options(read::DuplicateNames::KeepAll))?,
+        vec![file(b"dup", b"1"), file(b"b", b"2"), file(b"dup", b"3")]
+    );
+
+    assert_eq!(
+        contents::<read::index::NameIndexMap>(&data, options(read::DuplicateNames::KeepFirst))?,
+        vec![file(b"dup", b"1"), file(b"b", b"2")]
+    );
+    assert_eq!(
+        contents::<read::index::NameIndexMap>(&data, options(read::DuplicateNames::KeepLast))?,
+        vec![file(b"b", b"2"), file(b"dup", b"3")]
+    );
+    assert_eq!(
+        contents::<read::index::MetadataBTreeMap>(&data, options(read::DuplicateNames::KeepFirst))?,
+        vec![file(b"b", b"2"), file(b"dup", b"1")]
+    );
+    assert_eq!(
+        contents::<read::index::Tree>(&data, options(read::DuplicateNames::KeepLast))?,
+        vec![file(b"b", b"2"), file(b"dup", b"3")]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_normalized_lookup() -> Result<()> {
+    use read::index::RandomAccess;
+
+    let data = query_fixture()?;
+    let name_map = Reader::<_, read::index::NameBTreeMap>::read(
+        io::Cursor::new(&data),
+        read::Options::builder().build(),
+    )?;
+    let tree = Reader::<_, read::index::Tree>::read(
+        io::Cursor::new(&data),
+        read::Options::builder().build(),
+    )?;
+    let expected = name_map.index().read_file(b"src/lib/b.php");
+    assert!(expected.is_some());
+    assert_eq!(name_map.index().read_file(b"/src/lib//./b.php"), expected);
+    assert_eq!(tree.index().read_file(b"/src/lib/b.php"), expected);
+    assert_eq!(name_map.index().read_file(b"src/../src/lib/b.php"), None);
+
+    Ok(())
+}
+
+#[test]
+fn test_validate_inaccessible_name() -> Result<()> {
+    let mut data = query_fixture()?;
+    // rename `README` to `/EADME`, which PHP would look up as `EADME`
+    let pos = data
+        .windows(6)
+        .position(|window| window == b"README")
+        .expect("name exists");
+    data[pos] = b'/';
+
+    let mut phar = Reader::<_, read::index::NoIndex>::read(
+        io::Cursor::new(&data),
+        read::Options::builder().verify_signature(false).build(),
+    )?;
+    let diagnostics = phar.validate()?;
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+        diagnostics[0].kind,
+        read::DiagnosticKind::InaccessibleName {
+            name: b"/EADME".to_vec(),
+            error: None,
+        }
+    );
+    assert_eq!(usize::try_from(diagnostics[0].offset).unwrap(), pos - 4);
+
+    Ok(())
+}
+
+fn seek_fixture(compression: phar::Compression) -> Result<(Vec<u8>, Vec<u8>)> {
+    let mut state = 1u32;
+    let expected: Vec<u8> = (0..1 << 16)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            // keep the contents compressible but not trivially so
+            b"abcdefgh"[usize::try_from(state >> 29).unwrap()]
+        })
+        .collect();
+
+    let data = common::build(&[("data", &expected, compression)])?;
+    Ok((data, expected))
+}
+
+fn verify_seeks<S: io::Seek + Read>(mut reader: S, expected: &[u8]) -> Result<()> {
+    use io::SeekFrom;
+
+    let mut read_at = |pos: SeekFrom, len: usize| -> Result<Vec<u8>> {
+        let _ = reader.seek(pos)?;
+        let mut buf = vec![0; len];
+        reader.read_exact(&mut buf)?;
+        Ok(buf)
+    };
+
+    assert_eq!(read_at(SeekFrom::Start(5000), 100)?, &expected[5000..5100]);
+    assert_eq!(read_at(SeekFrom::Start(10), 100)?, &expected[10..110]);
+    assert_eq!(read_at(SeekFrom::Current(-50), 100)?, &expected[60..160]);
+    assert_eq!(
+        read_at(SeekFrom::Current(30000), 10)?,
+        &expected[30160..30170]
+    );
+    assert_eq!(
+        read_at(SeekFrom::End(-16), 16)?,
+        &expected[expected.len() - 16..]
+    );
+
+    assert_eq!(
+        reader.seek(SeekFrom::End(10))?,
+        u64::try_from(expected.len() + 10).unwrap()
+    );
+    assert_eq!(reader.read(&mut [0; 16])?, 0);
+    assert_eq!(
+        reader
+            .seek(SeekFrom::Current(-(1 << 20)))
+            .unwrap_err()
+            .kind(),
+        io::ErrorKind::InvalidInput
+    );
+
+    let _ = reader.seek(SeekFrom::Start(0))?;
+    let mut all = Vec::new();
+    let _ = reader.read_to_end(&mut all)?;
+    assert_eq!(all, expected);
+
+    Ok(())
+}
+
+#[test]
+fn test_seekable_entries() -> Result<()> {
+    for &compression in &[
+        phar::Compression::None,
+        phar::Compression::Zlib(phar::Level::DEFAULT),
+        phar::Compression::Bzip(phar::Level::DEFAULT),
+    ] {
+        let (data, expected) = seek_fixture(compression)?;
+
+        let mut phar = Reader::<_, read::index::NameHashMap>::read(
+            io::Cursor::new(&data),
+            read::Options::builder().build(),
+        )?;
+        phar.for_each_entry(|mut entry| {
+            verify_seeks(entry.open_seekable()?, &expected)?;
+            verify_seeks(entry.open_seekable()?.with_cache_size(1 << 10), &expected)?;
+            verify_seeks(
+                entry.open_seekable()?.with_checkpoint_interval(1 << 12),
+                &expected,
+            )
+        })?;
+
+        let slice = read::SliceReader::<read::index::OffsetOnly>::read(
+            &data,
+            read::Options::builder().build(),
+        )?;
+        slice.for_each_file(|_, entry| verify_seeks(entry.read_seekable()?, &expected))?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_seek_cache() -> Result<()> {
+    let (data, expected) = seek_fixture(phar::Compression::Zlib(phar::Level::DEFAULT))?;
+    let count = std::rc::Rc::new(std::cell::Cell::new(0));
+    let mut phar = Reader::<_, read::index::OffsetOnly>::read(
+        CountingRead {
+            inner: io::Cursor::new(&data),
+            count: count.clone(),
+        },
+        read::Options::builder().build(),
+    )?;
+
+    let mut seek_back = |cache_size: usize| -> Result<usize> {
+        let mut restarted = 0;
+        phar.for_each_entry(|mut entry| {
+            let mut reader = entry.open_seekable()?.with_cache_size(cache_size);
+            let mut buf = vec![0; 8192];
+            reader.read_exact(&mut buf)?;
+            count.set(0);
+            let _ = reader.seek(io::SeekFrom::Current(-1000))?;
+            reader.read_exact(&mut buf[..1000])?;
+            assert_eq!(&buf[..1000], &expected[7192..8192]);
+            restarted = count.get();
+            Ok(())
+        })?;
+        Ok(restarted)
+    };
+    assert_eq!(seek_back(1 << 10)?, 0);
+    assert!(seek_back(0)? > 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_seek_checkpoints() -> Result<()> {
+    let (data, expected) = seek_fixture(phar::Compression::Zlib(phar::Level::DEFAULT))?;
+    let count = std::rc::Rc::new(std::cell::Cell::new(0));
+    let mut phar = Reader::<_, read::index::OffsetOnly>::read(
+        CountingRead {
+            inner: io::Cursor::new(&data),
+            count: count.clone(),
+        },
+        read::Options::builder().build(),
+    )?;
+
+    let mut seek_back = |interval: u64| -> Result<usize> {
+        let mut restarted = 0;
+        phar.for_each_entry(|mut entry| {
+            let mut reader = entry.open_seekable()?.with_checkpoint_interval(interval);
+            let _ = io::copy(&mut reader, &mut io::sink())?;
+            count.set(0);
+            let mut buf = vec![0; 1000];
+            let _ = reader.seek(io::SeekFrom::Start(60000))?;
+            reader.read_exact(&mut buf)?;
+            assert_eq!(&buf[..], &expected[60000..61000]);
+            let _ = reader.seek(io::SeekFrom::Start(100))?;
+            reader.read_exact(&mut buf)?;
+            assert_eq!(&buf[..], &expected[100..1100]);
+            let _ = reader.seek(io::SeekFrom::Start(50000))?;
+            reader.read_exact(&mut buf)?;
+            assert_eq!(&buf[..], &expected[50000..51000]);
+            restarted = count.get();
+            Ok(())
+        })?;
+        Ok(restarted)
+    };
+    let without = seek_back(0)?;
+    let with = seek_back(1 << 13)?;
+    assert!(with < without, "{} < {}", with, without);
+
+    Ok(())
+}
+
+#[test]
+fn test_seek_limits() -> Result<()> {
+    let (data, _) = seek_fixture(phar::Compression::Zlib(phar::Level::DEFAULT))?;
+    let mut phar = Reader::<_, read::index::OffsetOnly>::read(
+        io::Cursor::new(&data),
+        read::Options::builder().max_entry_size(1 << 15).build(),
+    )?;
+    let err = phar
+        .for_each_entry(|mut entry| {
+            let mut reader = entry.open_seekable()?;
+            let _ = reader.seek(io::SeekFrom::Start(1 << 15))?;
+            reader.read(&mut [0; 16]).map(|_| ())
+        })
+        .unwrap_err();
+    assert_eq!(
+        limit_error::<()>(Err(err)),
+        read::LimitExceeded::EntrySize { limit: 1 << 15 }
+    );
+    Ok(())
+}
+
+#[test]
+fn test_shared_reader() -> Result<()> {
+    let mut path = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    path.push("tests/output/test_shared_reader.phar");
+    fs::write(&path, query_fixture()?)?;
+
+    let phar = std::sync::Arc::new(read::SharedReader::<_, read::index::NameBTreeMap>::read(
+        fs::File::open(&path)?,
+        read::Options::builder().build(),
+    )?);
+    assert_eq!(phar.stub()?.as_ref(), b"<?php __HALT_COMPILER(); ?>\r\n");
+    assert_eq!(phar.entry_count(), 6);
+    assert!(phar.validate()?.is_empty());
+
+    let threads: Vec<_> = ["src/a.php", "src/lib/b.php", "src/lib/c.txt", "README"]
+        .iter()
+        .map(|&name| {
+            let phar = phar.clone();
+            std::thread::spawn(move || -> Result<Vec<u8>> {
+                let mut contents = Vec::new();
+                for _ in 0..100 {
+                    contents.clear();
+                    phar.for_each_prefix(name.as_bytes(), |mut entry| {
+                        if entry.name() == name.as_bytes() {
+                            let _ = entry.open()?.read_to_end(&mut contents)?;
+                        }
+                        Ok(())
+                    })?;
+                }
+                Ok(contents)
+            })
+        })
+        .collect();
+    let contents = threads
+        .into_iter()
+        .map(|thread| thread.join().expect("thread panicked"))
+        .collect::<Result<Vec<_>>>()?;
+    assert_eq!(contents, vec![&b"a"[..], b"b", b"c", b"readme"]);
+
+    let mut names = Vec::new();
+    phar.for_each_file(|name, _| {
+        names.push(String::from_utf8_lossy(name).into_owned());
+        Ok(())
+    })?;
+    assert_eq!(
+        names,
+        vec![
+            "README",
+            "src/a.php",
+            "src/lib/b.php",
+            "src/lib/c.txt",
+            "srcx.php"
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_read_at_cursor() -> Result<()> {
+    let data = b"0123456789".to_vec();
+    let mut cursor = read::ReadAtCursor::new(&data);
+    let _ = cursor.seek(io::SeekFrom::End(-3))?;
+    let mut buf = Vec::new();
+    let _ = cursor.read_to_end(&mut buf)?;
+    assert_eq!(buf, b"789");
+
+    let mut other = read::ReadAtCursor::new(&data);
+    let mut byte = [0u8];
+    other.read_exact(&mut byte)?;
+    assert_eq!(&byte, b"0");
+    assert_eq!(cursor.stream_position()?, 10);
+    assert_eq!(cursor.read(&mut byte)?, 0);
+    assert!(cursor.seek(io::SeekFrom::Current(-11)).is_err());
+    Ok(())
+}
+
+/// Returns an empty directory for test output.
+fn output_dir(name: &str) -> Result<PathBuf> {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    Ok(dir)
+}
+
+fn verify_extracted(dir: &std::path::Path) -> Result<()> {
+    assert!(dir.join("src/lib").is_dir());
+    assert_eq!(fs::read(dir.join("src/a.php"))?, b"a");
+    assert_eq!(fs::read(dir.join("src/lib/b.php"))?, b"b");
+    assert_eq!(fs::read(dir.join("src/lib/c.txt"))?, b"c");
+    assert_eq!(fs::read(dir.join("srcx.php"))?, b"x");
+    assert_eq!(fs::read(dir.join("README"))?, b"readme");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(dir.join("README"))?.permissions().mode();
+        assert_eq!(mode & 0o777, 0o664);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_extract_to() -> Result<()> {
+    let data = query_fixture()?;
+
+    let dir = output_dir("test_extract_to")?;
+    let mut phar = Reader::<_, read::index::NameHashMap>::read(
+        io::Cursor::new(&data),
+        read::Options::builder().build(),
+    )?;
+    phar.verify_crc32()?;
+    phar.extract_to(&dir)?;
+    verify_extracted(&dir)?;
+
+    let dir = output_dir("test_extract_to_shared")?;
+    let phar = read::SharedReader::<_, read::index::OffsetOnly>::read(
+        &data[..],
+        read::Options::builder().build(),
+    )?;
+    phar.verify_crc32()?;
+    phar.extract_to(&dir)?;
+    verify_extracted(&dir)?;
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_extract_over_symlinks() -> Result<()> {
+    use std::os::unix::fs::symlink;
+
+    let data = query_fixture()?;
+    let extract = |dir: &std::path::Path| -> Result<()> {
+        let mut phar = Reader::<_, read::index::NameHashMap>::read(
+            io::Cursor::new(&data),
+            read::Options::builder().build(),
+        )?;
+        phar.extract_to(dir)
+    };
+
+    let root = output_dir("test_extract_over_symlinks")?;
+    let outside = root.join("outside");
+    fs::create_dir_all(&outside)?;
+    fs::write(outside.join("README"), "outside")?;
+
+    let dir = root.join("file_link");
+    fs::create_dir_all(&dir)?;
+    symlink(outside.join("README"), dir.join("README"))?;
+    extract(&dir)?;
+    verify_extracted(&dir)?;
+    assert!(!fs::symlink_metadata(dir.join("README"))?
+        .file_type()
+        .is_symlink());
+    assert_eq!(fs::read(outside.join("README"))?, b"outside");
+
+    let dir = root.join("dir_link");
+    fs::create_dir_all(&dir)?;
+    symlink(&outside, dir.join("src"))?;
+    let err = extract(&dir).expect_err("extracting through a linked directory is rejected");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert!(!outside.join("a.php").exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_extract_to_with_observer() -> Result<()> {
+    use phar::progress::{EntryEvent, Observer, Totals};
+
+    #[derive(Default)]
+    struct Counts {
+        declared: Vec<Vec<u8>>,
+        done: Vec<(Vec<u8>, usize, usize, u64)>,
+        totals: Option<(usize, u64)>,
+    }
+
+    impl Observer for Counts {
+        fn entry_declared(&mut self, name: &[u8]) {
+            self.declared.push(name.to_vec());
+        }
+
+        fn entry_done(&mut self, event: &EntryEvent<'_>) {
+            self.done.push((
+                event.name.to_vec(),
+                event.index,
+                event.total,
+                event.bytes_written,
+            ));
+        }
+
+        fn finished(&mut self, totals: &Totals) {
+            self.totals = Some((totals.entries, totals.bytes_written));
+        }
+    }
+
+    let data = query_fixture()?;
+    let dir = output_dir("test_extract_to_with_observer")?;
+    let phar = read::SharedReader::<_, read::index::NameIndexMap>::read(
+        &data[..],
+        read::Options::builder().build(),
+    )?;
+    let mut counts = Counts::default();
+    phar.extract_to_with(&dir, &mut counts)?;
+    verify_extracted(&dir)?;
+
+    let files: Vec<_> = counts
+        .declared
+        .iter()
+        .filter(|name| !name.ends_with(b"/"))
+        .collect();
+    assert!(counts.declared.len() > files.len());
+    assert_eq!(counts.done.len(), files.len());
+    for (i, (name, index, total, bytes_written)) in counts.done.iter().enumerate() {
+        assert_eq!(name, files[i]);
+        assert_eq!((*index, *total), (i, files.len()));
+        assert_eq!(
+            *bytes_written,
+            fs::metadata(dir.join(std::str::from_utf8(name).unwrap()))?.len()
+        );
+    }
+    let bytes_written = counts.done.iter().map(|done| done.3).sum();
+    assert_eq!(counts.totals, Some((files.len(), bytes_written)));
+
+    Ok(())
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn test_par_extract_to() -> Result<()> {
+    let (data, expected) = seek_fixture(phar::Compression::Bzip(phar::Level::DEFAULT))?;
+
+    let dir = output_dir("test_par_extract_to")?;
+    let phar = read::SharedReader::<_, read::index::NameHashMap>::read(
+        &data[..],
+        read::Options::builder().build(),
+    )?;
+    phar.par_verify_crc32()?;
+    phar.par_extract_to(&dir)?;
+    assert_eq!(fs::read(dir.join("data"))?, expected);
+
+    let dir = output_dir("test_par_extract_to_query")?;
+    let data = query_fixture()?;
+    let phar = read::SharedReader::<_, read::index::MetadataBTreeMap>::read(
+        &data[..],
+        read::Options::builder().build(),
+    )?;
+    phar.par_extract_to(&dir)?;
+    verify_extracted(&dir)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_crc32_mismatch() -> Result<()> {
+    let mut data = query_fixture()?;
+    let contents = data
+        .windows(10)
+        .position(|window| window == b"abcxreadme")
+        .expect("contents exist");
+    // corrupt `README` and `src/a.php`; the latter comes first in the manifest
+    data[contents + 9] = b'E';
+    data[contents] = b'A';
+
+    let options = || read::Options::builder().verify_signature(false).build();
+    let mismatch = |result: Result<()>| {
+        let err = result.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        err.to_string()
+    };
+    let expected = "CRC32 mismatch in entry \"src/a.php\"";
+
+    let mut phar = Reader::<_, read::index::NameHashMap>::read(io::Cursor::new(&data), options())?;
+    assert!(mismatch(phar.verify_crc32()).starts_with(expected));
+
+    let shared = read::SharedReader::<_, read::index::NameHashMap>::read(&data[..], options())?;
+    assert!(mismatch(shared.verify_crc32()).starts_with(expected));
+    #[cfg(feature = "rayon")]
+    for _ in 0..10 {
+        assert!(mismatch(shared.par_verify_crc32()).starts_with(expected));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_extract_rejects_escaping_names() -> Result<()> {
+    let mut data = query_fixture()?;
+    let pos = data
+        .windows(8)
+        .position(|window| window == b"srcx.php")
+        .expect("name exists");
+    data[pos..pos + 8].copy_from_slice(b"../x.php");
+
+    let dir = output_dir("test_extract_rejects_escaping_names")?;
+    let mut phar = Reader::<_, read::index::OffsetOnly>::read(
+        io::Cursor::new(&data),
+        read::Options::builder().verify_signature(false).build(),
+    )?;
+    let err = phar.extract_to(dir.join("out")).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(!dir.join("x.php").exists());
+
+    // `..\x.php` escapes the output directory on Windows
+    data[pos..pos + 8].copy_from_slice(b"..\\x.php");
+    let mut phar = Reader::<_, read::index::OffsetOnly>::read(
+        io::Cursor::new(&data),
+        read::Options::builder().verify_signature(false).build(),
+    )?;
+    let err = phar.extract_to(dir.join("out")).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(!dir.join("x.php").exists());
+    assert!(!dir.join("out").join("..\\x.php").exists());
+
+    Ok(())
+}
@@ -0,0 +1,384 @@
+use std::io::{self, Read, Result};
+
+use phar::read::{self, index::NameIndexMap, Glob};
+use phar::repack::{self, Rule};
+use phar::{Compression, Level, Reader, Signature};
+
+mod common;
+
+type Source = Reader<io::Cursor<Vec<u8>>, NameIndexMap>;
+
+const FILES: &[(&str, &str, Compression)] = &[
+    ("src/", "", Compression::None),
+    (
+        "src/main.php",
+        "<?php echo 'main';",
+        Compression::Zlib(Level::BEST),
+    ),
+    (
+        "src/lib.php",
+        "<?php echo 'lib';",
+        Compression::Bzip(Level::BEST),
+    ),
+    ("README", "readme", Compression::None),
+];
+
+fn source() -> Result<Source> {
+    let mut file = io::Cursor::new(Vec::new());
+    let writer = phar::create(&mut file, Signature::sha1())
+        .stub(&b"<?php // stub"[..])?
+        .alias(&b"app.phar"[..])?
+        .metadata(&b"global"[..])?;
+    common::write_entries(writer, FILES)?;
+    Reader::read(
+        io::Cursor::new(file.into_inner()),
+        read::Options::builder().build(),
+    )
+}
+
+fn repacked(signature: Signature, options: repack::Options) -> Result<Source> {
+    let mut output = io::Cursor::new(Vec::new());
+    repack::repack(&mut source()?, &mut output, signature, options)?;
+    let mut phar = Reader::read(
+        io::Cursor::new(output.into_inner()),
+        read::Options::builder().strict(true).build(),
+    )?;
+    phar.verify_crc32()?;
+    Ok(phar)
+}
+
+/// The name, compression bit, contents, metadata, timestamp and permissions of an entry
+type Fields = (String, u32, String, Vec<u8>, i32, u32);
+
+fn entries(phar: &mut Source) -> Result<Vec<Fields>> {
+    let mut entries = Vec::new();
+    phar.for_each_entry(|mut entry| {
+        let info = entry.info();
+        let mut data = String::new();
+        let _ = entry.open()?.read_to_string(&mut data)?;
+        entries.push((
+            String::from_utf8_lossy(entry.name()).into_owned(),
+            info.flags() & 0x3000,
+            data,
+            entry.metadata()?,
+            info.timestamp(),
+            info.flags() & 0x1FF,
+        ));
+        Ok(())
+    })?;
+    Ok(entries)
+}
+
+#[test]
+fn test_repack_compression() -> Result<()> {
+    let mut phar = repacked(
+        Signature::sha256(),
+        repack::Options::builder()
+            .compression(Compression::None)
+            .rules(vec![Rule::new(
+                Glob::new(b"**/*.php")?,
+                Compression::Zlib(Level::FASTEST),
+            )])
+            .build(),
+    )?;
+    assert_eq!(
+        entries(&mut phar)?,
+        vec![
+            ("src/".into(), 0, "".into(), b"src/".to_vec(), 2000, 0o755),
+            (
+                "src/main.php".into(),
+                0x1000,
+                "<?php echo 'main';".into(),
+                b"src/main.php".to_vec(),
+                2000,
+                0o664
+            ),
+            (
+                "src/lib.php".into(),
+                0x1000,
+                "<?php echo 'lib';".into(),
+                b"src/lib.php".to_vec(),
+                2000,
+                0o664
+            ),
+            (
+                "README".into(),
+                0,
+                "readme".into(),
+                b"README".to_vec(),
+                2000,
+                0o664
+            ),
+        ]
+    );
+    assert_eq!(
+        phar.stub_bytes()?.as_ref(),
+        b"<?php // stub__HALT_COMPILER(); ?>\r\n"
+    );
+    assert_eq!(phar.alias_bytes()?.as_ref(), b"app.phar");
+    assert_eq!(phar.metadata_bytes()?.as_ref(), b"global");
+    assert_eq!(
+        phar.signature().map(|signature| signature.algorithm()),
+        Some(phar::SignatureAlgorithm::Sha256)
+    );
+    Ok(())
+}
+
+#[test]
+fn test_repack_keeps_compression() -> Result<()> {
+    let mut expected = source()?;
+    let mut phar = repacked(Signature::sha512(), repack::Options::default())?;
+    assert_eq!(entries(&mut phar)?, entries(&mut expected)?);
+
+    let mut phar = repacked(
+        Signature::md5(),
+        repack::Options::builder()
+            .rules(vec![Rule::new(
+                Glob::new(b"README")?,
+                Compression::Bzip(Level::BEST),
+            )])
+            .build(),
+    )?;
+    let compressions: Vec<_> = entries(&mut phar)?
+        .into_iter()
+        .map(|(name, compression, ..)| (name, compression))
+        .collect();
+    assert_eq!(
+        compressions,
+        vec![
+            ("src/".into(), 0),
+            ("src/main.php".into(), 0x1000),
+            ("src/lib.php".into(), 0x2000),
+            ("README".into(), 0x2000),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_repack_crc32_mismatch() -> Result<()> {
+    let mut bytes = common::build(&[("foo", "corrupted", Compression::None)])?;
+    let offset = bytes
+        .windows(9)
+        .position(|window| window == b"corrupted")
+        .expect("contents are stored uncompressed");
+    bytes[offset] = b'C';
+
+    let mut input: Source = Reader::read(
+        io::Cursor::new(bytes),
+        read::Options::builder().verify_signature(false).build(),
+    )?;
+    let err = repack::repack(
+        &mut input,
+        io::Cursor::new(Vec::new()),
+        Signature::sha1(),
+        repack::Options::builder()
+            .compression(Compression::Zlib(Level::DEFAULT))
+            .build(),
+    )
+    .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(err.to_string().contains("CRC32 mismatch in entry \"foo\""));
+    Ok(())
+}
+
+/// Repacks the source phar into `format` with a SHA-256 signature.
+#[cfg(any(feature = "tar", feature = "zip"))]
+fn converted(format: repack::Format) -> Result<Vec<u8>> {
+    let mut output = io::Cursor::new(Vec::new());
+    repack::repack(
+        &mut source()?,
+        &mut output,
+        Signature::sha256(),
+        repack::Options::builder().format(format).build(),
+    )?;
+    Ok(output.into_inner())
+}
+
+/// Builds a native phar from a tar or zip based phar.
+#[cfg(any(feature = "tar", feature = "zip"))]
+fn rebuilt(source: &mut impl phar::write::Source, dir: &phar::write::PharDir) -> Result<Source> {
+    let mut file = io::Cursor::new(Vec::new());
+    phar::create(&mut file, Signature::sha256())
+        .stub(dir.stub.as_deref().unwrap_or_default())?
+        .alias(&dir.alias[..])?
+        .metadata(&dir.metadata[..])?
+        .build_from_source(source, Compression::None)?;
+    Reader::read(
+        io::Cursor::new(file.into_inner()),
+        read::Options::builder().strict(true).build(),
+    )
+}
+
+/// Returns the expected contents of `.phar/signature.bin` for the SHA-256 hash of `parts`.
+#[cfg(any(feature = "tar", feature = "zip"))]
+fn signature_file(parts: &[&[u8]]) -> Result<Vec<u8>> {
+    use std::io::Write;
+
+    let mut signature = Signature::sha256();
+    for part in parts {
+        signature.write().write_all(part)?;
+    }
+    let mut contents = vec![3, 0, 0, 0, 32, 0, 0, 0];
+    contents.extend_from_slice(&signature.finalize());
+    Ok(contents)
+}
+
+#[cfg(any(feature = "tar", feature = "zip"))]
+fn expected_phar_dir() -> phar::write::PharDir {
+    phar::write::PharDir {
+        stub: Some(b"<?php // stub".to_vec()),
+        alias: b"app.phar".to_vec(),
+        metadata: b"global".to_vec(),
+        signature: Some(phar::SignatureAlgorithm::Sha256),
+    }
+}
+
+#[cfg(feature = "tar")]
+#[test]
+fn test_repack_tar() -> Result<()> {
+    use phar::write::{Source as _, TarSource};
+
+    let data = converted(repack::Format::Tar)?;
+
+    let mut archive = tar::Archive::new(&data[..]);
+    let mut files = Vec::new();
+    let mut signature_position = None;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+        if name == ".phar/signature.bin" {
+            signature_position = Some(entry.raw_header_position());
+        }
+        let mode = entry.header().mode()?;
+        let mut contents = Vec::new();
+        let _ = entry.read_to_end(&mut contents)?;
+        files.push((name, mode, contents));
+    }
+    let signature_position = signature_position.expect("the archive is signed") as usize;
+
+    let expected: Vec<(String, u32, Vec<u8>)> = vec![
+        (
+            ".phar/stub.php".into(),
+            0o644,
+            b"<?php // stub__HALT_COMPILER(); ?>\r\n".to_vec(),
+        ),
+        (".phar/alias.txt".into(), 0o644, b"app.phar".to_vec()),
+        (".phar/.metadata.bin".into(), 0o644, b"global".to_vec()),
+        ("src/".into(), 0o755, vec![]),
+        (
+            ".phar/.metadata/src/.metadata.bin".into(),
+            0o644,
+            b"src/".to_vec(),
+        ),
+        ("src/main.php".into(), 0o664, b"<?php echo 'main';".to_vec()),
+        (
+            ".phar/.metadata/src/main.php/.metadata.bin".into(),
+            0o644,
+            b"src/main.php".to_vec(),
+        ),
+        ("src/lib.php".into(), 0o664, b"<?php echo 'lib';".to_vec()),
+        (
+            ".phar/.metadata/src/lib.php/.metadata.bin".into(),
+            0o644,
+            b"src/lib.php".to_vec(),
+        ),
+        ("README".into(), 0o664, b"readme".to_vec()),
+        (
+            ".phar/.metadata/README/.metadata.bin".into(),
+            0o644,
+            b"README".to_vec(),
+        ),
+        (
+            ".phar/signature.bin".into(),
+            0o644,
+            signature_file(&[&data[..signature_position]])?,
+        ),
+    ];
+    assert_eq!(files, expected);
+
+    let mut tar = TarSource::new(io::Cursor::new(data));
+    assert_eq!(tar.phar_dir()?, expected_phar_dir());
+    assert_eq!(tar.list()?.len(), FILES.len());
+
+    let mut phar = rebuilt(&mut tar, &expected_phar_dir())?;
+    let uncompressed: Vec<_> = entries(&mut source()?)?
+        .into_iter()
+        .map(|(name, _, data, metadata, timestamp, mode)| {
+            (name, 0, data, metadata, timestamp, mode)
+        })
+        .collect();
+    assert_eq!(entries(&mut phar)?, uncompressed);
+    Ok(())
+}
+
+#[cfg(feature = "tar")]
+#[test]
+fn test_repack_tar_rejects_compression() -> Result<()> {
+    let err = repack::repack(
+        &mut source()?,
+        io::Cursor::new(Vec::new()),
+        Signature::sha256(),
+        repack::Options::builder()
+            .format(repack::Format::Tar)
+            .rules(vec![Rule::new(
+                Glob::new(b"**/*.php")?,
+                Compression::Zlib(Level::DEFAULT),
+            )])
+            .build(),
+    )
+    .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    Ok(())
+}
+
+#[cfg(feature = "zip")]
+#[test]
+fn test_repack_zip() -> Result<()> {
+    use phar::write::{Source as _, ZipSource};
+
+    let data = converted(repack::Format::Zip)?;
+
+    let mut archive = zip::ZipArchive::new(io::Cursor::new(&data[..]))?;
+    assert_eq!(archive.comment(), b"global");
+    let mut stub = Vec::new();
+    let _ = archive.by_name(".phar/stub.php")?.read_to_end(&mut stub)?;
+    assert_eq!(stub, b"<?php // stub__HALT_COMPILER(); ?>\r\n");
+    {
+        let file = archive.by_name("src/main.php")?;
+        assert_eq!(file.compression(), zip::CompressionMethod::Deflated);
+        assert_eq!(file.comment(), "src/main.php");
+        assert_eq!(file.unix_mode(), Some(0o100_664));
+    }
+
+    // PHP signs the local files, the central directory and the archive comment
+    // before the signature entry
+    let central_start = archive.by_index_raw(0)?.central_header_start() as usize;
+    let mut signature = archive.by_name(".phar/signature.bin")?;
+    let local_end = signature.header_start() as usize;
+    let central_end = signature.central_header_start() as usize;
+    let mut signature_contents = Vec::new();
+    let _ = signature.read_to_end(&mut signature_contents)?;
+    assert_eq!(
+        signature_contents,
+        signature_file(&[
+            &data[..local_end],
+            &data[central_start..central_end],
+            b"global"
+        ])?
+    );
+    drop(signature);
+
+    let mut zip = ZipSource::new(io::Cursor::new(data))?;
+    assert_eq!(zip.phar_dir()?, expected_phar_dir());
+    assert_eq!(zip.list()?.len(), FILES.len());
+
+    let mut phar = rebuilt(&mut zip, &expected_phar_dir())?;
+    // zip dates start from 1980
+    let clamped: Vec<_> = entries(&mut source()?)?
+        .into_iter()
+        .map(|(name, _, data, metadata, _, mode)| (name, 0, data, metadata, 315_532_800, mode))
+        .collect();
+    assert_eq!(entries(&mut phar)?, clamped);
+    Ok(())
+}
@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Result};
+use std::time::{Duration, SystemTime};
+
+use phar::read::{self, index::NameIndexMap};
+use phar::write::{BuildOptions, Source, SourceEntry};
+use phar::{Compression, CompressionMethod, Level, Reader, Signature};
+
+type Output = Reader<io::Cursor<Vec<u8>>, NameIndexMap>;
+
+fn build<S: Source>(source: &mut S, options: impl Into<BuildOptions>) -> Result<Output> {
+    let mut file = io::Cursor::new(Vec::new());
+    phar::create(&mut file, Signature::sha256())
+        .stub(&b"<?php "[..])?
+        .metadata(&b""[..])?
+        .build_from_source(source, options)?;
+    let mut phar = Reader::read(
+        io::Cursor::new(file.into_inner()),
+        read::Options::builder().strict(true).build(),
+    )?;
+    phar.verify_crc32()?;
+    Ok(phar)
+}
+
+/// The name, contents, metadata, timestamp, permissions and compression of an entry
+type Fields = (String, String, Vec<u8>, i32, u32, CompressionMethod);
+
+fn entries(phar: &mut Output) -> Result<Vec<Fields>> {
+    let mut entries = Vec::new();
+    phar.for_each_entry(|mut entry| {
+        let info = entry.info();
+        let mut data = String::new();
+        let _ = entry.open()?.read_to_string(&mut data)?;
+        entries.push((
+            String::from_utf8_lossy(entry.name()).into_owned(),
+            data,
+            entry.metadata()?,
+            info.timestamp(),
+            info.flags() & 0x1FF,
+            info.compression(),
+        ));
+        Ok(())
+    })?;
+    Ok(entries)
+}
+
+#[test]
+fn test_hash_map_source() -> Result<()> {
+    let mut files = HashMap::new();
+    let _ = files.insert("src/b.php".to_string(), b"<?php echo 'b';".to_vec());
+    let _ = files.insert("./src/a.php".to_string(), b"<?php echo 'a';".to_vec());
+
+    let mut phar = build(&mut files, Compression::Zlib(Level::DEFAULT))?;
+    assert_eq!(
+        entries(&mut phar)?,
+        vec![
+            (
+                "src/a.php".into(),
+                "<?php echo 'a';".into(),
+                vec![],
+                0,
+                0o644,
+                CompressionMethod::Zlib
+            ),
+            (
+                "src/b.php".into(),
+                "<?php echo 'b';".into(),
+                vec![],
+                0,
+                0o644,
+                CompressionMethod::Zlib
+            ),
+        ]
+    );
+    Ok(())
+}
+
+/// Files generated on demand
+struct Generated(usize);
+
+impl Source for Generated {
+    type Id = usize;
+
+    fn list(&mut self) -> Result<Vec<SourceEntry<usize>>> {
+        Ok((0..self.0)
+            .map(|i| {
+                let mut entry = SourceEntry::file(format!("gen/{}.php", i), i);
+                entry.mode = 0o600;
+                entry.metadata = format!("i:{};", i).into_bytes();
+                if i == 0 {
+                    entry.compression = Some(Compression::Bzip(Level::BEST));
+                }
+                entry
+            })
+            .collect())
+    }
+
+    fn open(&mut self, i: &usize) -> Result<Box<dyn Read + '_>> {
+        Ok(Box::new(io::Cursor::new(format!("<?php return {};", i))))
+    }
+}
+
+#[test]
+fn test_custom_source() -> Result<()> {
+    let mut phar = build(&mut Generated(2), Compression::None)?;
+    assert_eq!(
+        entries(&mut phar)?,
+        vec![
+            (
+                "gen/0.php".into(),
+                "<?php return 0;".into(),
+                b"i:0;".to_vec(),
+                0,
+                0o600,
+                CompressionMethod::Bzip
+            ),
+            (
+                "gen/1.php".into(),
+                "<?php return 1;".into(),
+                b"i:1;".to_vec(),
+                0,
+                0o600,
+                CompressionMethod::None
+            ),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_reader_source() -> Result<()> {
+    let mut file = io::Cursor::new(Vec::new());
+    let mut writer = phar::create(&mut file, Signature::sha1())
+        .stub(&b"<?php "[..])?
+        .metadata(&b""[..])?;
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+    writer.directory(&b"src"[..], &b"dir"[..], time, 0o755)?;
+    writer.entry(
+        &b"src/a.php"[..],
+        &b"meta"[..],
+        time,
+        0o640,
+        Compression::Bzip(Level::BEST),
+    )?;
+    let mut contents = writer.contents()?;
+    contents.feed(&b"<?php echo 'a';"[..])?;
+    let mut input: Output = Reader::read(
+        io::Cursor::new(file.into_inner()),
+        read::Options::builder().build(),
+    )?;
+
+    let mut phar = build(&mut input, Compression::Zlib(Level::BEST))?;
+    assert_eq!(
+        entries(&mut phar)?,
+        vec![
+            (
+                "src/".into(),
+                "".into(),
+                b"dir".to_vec(),
+                1000,
+                0o755,
+                CompressionMethod::None
+            ),
+            (
+                "src/a.php".into(),
+                "<?php echo 'a';".into(),
+                b"meta".to_vec(),
+                1000,
+                0o640,
+                CompressionMethod::Zlib
+            ),
+        ]
+    );
+    Ok(())
+}
+
+#[cfg(feature = "tar")]
+#[test]
+fn test_tar_source() -> Result<()> {
+    use phar::write::TarSource;
+
+    let mut builder = tar::Builder::new(Vec::new());
+    let mut append = |name: &str, entry_type, mode, data: &[u8]| {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(entry_type);
+        header.set_mode(mode);
+        header.set_mtime(2000);
+        header.set_size(data.len() as u64);
+        builder.append_data(&mut header, name, data)
+    };
+    append("src/", tar::EntryType::Directory, 0o755, b"")?;
+    append(
+        "src/a.php",
+        tar::EntryType::Regular,
+        0o640,
+        b"<?php echo 'a';",
+    )?;
+    // long names are stored in extra GNU entries before the header
+    let long_name = format!("src/{}.php", "x".repeat(120));
+    append(&long_name, tar::EntryType::Regular, 0o644, b"long")?;
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Symlink);
+    header.set_size(0);
+    builder.append_link(&mut header, "src/link.php", "a.php")?;
+    let data = builder.into_inner()?;
+
+    let mut phar = build(
+        &mut TarSource::new(io::Cursor::new(data)),
+        Compression::None,
+    )?;
+    assert_eq!(
+        entries(&mut phar)?,
+        vec![
+            (
+                "src/".into(),
+                "".into(),
+                vec![],
+                2000,
+                0o755,
+                CompressionMethod::None
+            ),
+            (
+                "src/a.php".into(),
+                "<?php echo 'a';".into(),
+                vec![],
+                2000,
+                0o640,
+                CompressionMethod::None
+            ),
+            (
+                long_name,
+                "long".into(),
+                vec![],
+                2000,
+                0o644,
+                CompressionMethod::None
+            ),
+        ]
+    );
+    Ok(())
+}
+
+#[cfg(feature = "zip")]
+#[test]
+fn test_zip_source() -> Result<()> {
+    use std::io::Write;
+
+    use phar::write::ZipSource;
+    use zip::write::FileOptions;
+    use zip::{CompressionMethod as ZipCompression, DateTime};
+
+    let time = DateTime::from_date_and_time(2001, 9, 9, 1, 46, 40).expect("valid date");
+    let mut writer = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+    writer.add_directory("src/", FileOptions::default().last_modified_time(time))?;
+    writer.start_file(
+        "src/a.php",
+        FileOptions::default()
+            .compression_method(ZipCompression::Deflated)
+            .last_modified_time(time)
+            .unix_permissions(0o640),
+    )?;
+    writer.write_all(b"<?php echo 'a';")?;
+    writer.add_symlink("src/link.php", "a.php", FileOptions::default())?;
+    let data = writer.finish()?.into_inner();
+
+    let mut phar = build(
+        &mut ZipSource::new(io::Cursor::new(data))?,
+        Compression::Zlib(Level::DEFAULT),
+    )?;
+    assert_eq!(
+        entries(&mut phar)?,
+        vec![
+            (
+                "src/".into(),
+                "".into(),
+                vec![],
+                1_000_000_000,
+                0o755,
+                CompressionMethod::None
+            ),
+            (
+                "src/a.php".into(),
+                "<?php echo 'a';".into(),
+                vec![],
+                1_000_000_000,
+                0o640,
+                CompressionMethod::Zlib
+            ),
+        ]
+    );
+    Ok(())
+}
@@ -4,7 +4,7 @@ use std::io;
 use std::path::PathBuf;
 use std::time::SystemTime;
 
-use phar::{Compression, Signature};
+use phar::{Compression, Level, Signature};
 
 #[test]
 pub fn test_plain() -> io::Result<()> {
@@ -60,14 +60,14 @@ pub fn test_zlib() -> io::Result<()> {
         &b""[..],
         SystemTime::now(),
         0o664,
-        Compression::Zlib(9),
+        Compression::Zlib(Level::BEST),
     )?;
     writer.entry(
         &b"qux"[..],
         &b""[..],
         SystemTime::now(),
         0o664,
-        Compression::Zlib(9),
+        Compression::Zlib(Level::BEST),
     )?;
     let mut contents = writer.contents()?;
     contents.feed(&b"bar"[..])?;
@@ -95,14 +95,14 @@ pub fn test_bzip() -> io::Result<()> {
         &b""[..],
         SystemTime::now(),
         0o664,
-        Compression::Bzip(9),
+        Compression::Bzip(Level::BEST),
     )?;
     writer.entry(
         &b"qux"[..],
         &b""[..],
         SystemTime::now(),
         0o664,
-        Compression::Bzip(9),
+        Compression::Bzip(Level::BEST),
     )?;
     let mut contents = writer.contents()?;
     contents.feed(&b"bar"[..])?;
@@ -130,14 +130,14 @@ pub fn test_mixed() -> io::Result<()> {
         &b""[..],
         SystemTime::now(),
         0o664,
-        Compression::Zlib(9),
+        Compression::Zlib(Level::BEST),
     )?;
     writer.entry(
         &b"qux"[..],
         &b""[..],
         SystemTime::now(),
         0o664,
-        Compression::Bzip(9),
+        Compression::Bzip(Level::BEST),
     )?;
     let mut contents = writer.contents()?;
     contents.feed(&b"bar"[..])?;
@@ -145,3 +145,677 @@ pub fn test_mixed() -> io::Result<()> {
 
     Ok(())
 }
+
+#[test]
+pub fn test_build_directories() -> io::Result<()> {
+    let src = env::temp_dir().join(format!(
+        "phar-test-build-directories-{}",
+        std::process::id()
+    ));
+    fs::create_dir_all(src.join("lib"))?;
+    fs::create_dir_all(src.join("empty"))?;
+    fs::write(src.join("lib/foo.php"), b"<?php")?;
+
+    let build = |options: phar::write::BuildOptions| -> io::Result<Vec<u8>> {
+        let mut file = io::Cursor::new(Vec::new());
+        phar::create(&mut file, Signature::sha256())
+            .stub(&b"<?php "[..])?
+            .metadata(&b""[..])?
+            .build_from_directory(&src, options)?;
+        Ok(file.into_inner())
+    };
+    let list = |data: Vec<u8>| -> io::Result<Vec<(Vec<u8>, bool)>> {
+        let phar = phar::Reader::<_, phar::read::index::Tree>::read(
+            io::Cursor::new(data),
+            phar::read::Options::builder().strict(true).build(),
+        )?;
+        Ok(phar
+            .index()
+            .read_dir(b"")
+            .expect("root exists")
+            .map(|entry| (entry.name().to_vec(), entry.is_dir()))
+            .collect())
+    };
+
+    let with_dirs = list(build(
+        phar::write::BuildOptions::builder()
+            .compression(Compression::Zlib(Level::DEFAULT))
+            .directories(true)
+            .build(),
+    )?)?;
+    let without_dirs = list(build(Compression::None.into())?)?;
+
+    fs::remove_dir_all(&src)?;
+
+    assert_eq!(
+        with_dirs,
+        vec![(b"empty".to_vec(), true), (b"lib".to_vec(), true)]
+    );
+    assert_eq!(without_dirs, vec![(b"lib".to_vec(), true)]);
+
+    Ok(())
+}
+
+#[test]
+pub fn test_reject_duplicate_names() -> io::Result<()> {
+    let mut file = io::Cursor::new(Vec::new());
+    let mut writer = phar::create(&mut file, Signature::sha256())
+        .stub(&b"<?php "[..])?
+        .metadata(&b""[..])?;
+    let now = SystemTime::now();
+    writer.entry(&b"a.php"[..], &b""[..], now, 0o664, Compression::None)?;
+    writer.directory(&b"lib"[..], &b""[..], now, 0o775)?;
+
+    let err = writer
+        .entry(&b"a.php"[..], &b""[..], now, 0o664, Compression::None)
+        .expect_err("duplicate file name is rejected");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    let err = writer
+        .directory(&b"lib/"[..], &b""[..], now, 0o775)
+        .expect_err("duplicate directory name is rejected");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+    Ok(())
+}
+
+#[test]
+pub fn test_normalize_names() -> io::Result<()> {
+    let mut file = io::Cursor::new(Vec::new());
+    let mut writer = phar::create(&mut file, Signature::sha256())
+        .stub(&b"<?php "[..])?
+        .metadata(&b""[..])?;
+    let now = SystemTime::now();
+    writer.entry(
+        &b"/src//./a.php"[..],
+        &b""[..],
+        now,
+        0o664,
+        Compression::None,
+    )?;
+    writer.directory(&b"./lib"[..], &b""[..], now, 0o775)?;
+
+    let err = writer
+        .entry(&b"src/a.php"[..], &b""[..], now, 0o664, Compression::None)
+        .expect_err("duplicate normalized name is rejected");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    let err = writer
+        .entry(&b"../b.php"[..], &b""[..], now, 0o664, Compression::None)
+        .expect_err("parent directory is rejected");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+    let mut contents = writer.contents()?;
+    contents.feed(&b"a"[..])?;
+    let data = file.into_inner();
+
+    let mut phar = phar::Reader::<_, phar::read::index::NameIndexMap>::read(
+        io::Cursor::new(data),
+        phar::read::Options::builder().strict(true).build(),
+    )?;
+    assert_eq!(phar.names()?, vec![b"src/a.php".to_vec(), b"lib/".to_vec()]);
+
+    Ok(())
+}
+
+#[test]
+pub fn test_copy_raw_entries() -> io::Result<()> {
+    use io::Read;
+
+    let mut file = io::Cursor::new(Vec::new());
+    let mut writer = phar::create(&mut file, Signature::sha256())
+        .stub(&b"<?php "[..])?
+        .metadata(&b""[..])?;
+    let time = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+    writer.directory(&b"src"[..], &b"s:3:\"dir\";"[..], time, 0o755)?;
+    writer.entry(
+        &b"src/a.php"[..],
+        &b""[..],
+        time,
+        0o644,
+        Compression::Zlib(Level::BEST),
+    )?;
+    writer.entry(
+        &b"src/b.php"[..],
+        &b"i:1;"[..],
+        time,
+        0o600,
+        Compression::Bzip(Level::BEST),
+    )?;
+    writer.entry(&b"README"[..], &b""[..], time, 0o664, Compression::None)?;
+    let mut contents = writer.contents()?;
+    contents.feed(&b"<?php echo 'a';"[..])?;
+    contents.feed(&b"<?php echo 'b';"[..])?;
+    contents.feed(&b"readme"[..])?;
+    let source = file.into_inner();
+
+    // only copy the `src` directory
+    let mut phar = phar::Reader::<_, phar::read::index::NameIndexMap>::read(
+        io::Cursor::new(&source),
+        phar::read::Options::builder().build(),
+    )?;
+    let mut file = io::Cursor::new(Vec::new());
+    let mut writer = phar::create(&mut file, Signature::sha256())
+        .stub(&b"<?php "[..])?
+        .metadata(&b""[..])?;
+    phar.for_each_entry(|mut entry| {
+        if entry.name().starts_with(b"src") {
+            let metadata = entry.metadata()?;
+            writer.raw_entry(entry.name(), &metadata[..], entry.info().into())?;
+        }
+        Ok(())
+    })?;
+    let mut contents = writer.contents()?;
+    phar.for_each_prefix(b"src/", |mut entry| contents.feed_raw(entry.open_raw()?))?;
+    let copied = file.into_inner();
+
+    let mut copy = phar::Reader::<_, phar::read::index::NameIndexMap>::read(
+        io::Cursor::new(&copied),
+        phar::read::Options::builder().strict(true).build(),
+    )?;
+    copy.verify_crc32()?;
+    let mut entries = Vec::new();
+    copy.for_each_entry(|mut entry| {
+        let mut contents = String::new();
+        let _ = entry.open()?.read_to_string(&mut contents)?;
+        entries.push((
+            String::from_utf8_lossy(entry.name()).into_owned(),
+            entry.metadata()?,
+            entry.info().flags(),
+            entry.info().modified(),
+            contents,
+        ));
+        Ok(())
+    })?;
+    assert_eq!(
+        entries,
+        vec![
+            (
+                "src/".into(),
+                b"s:3:\"dir\";".to_vec(),
+                0o755,
+                time,
+                String::new()
+            ),
+            (
+                "src/a.php".into(),
+                Vec::new(),
+                0o644 | 0x1000,
+                time,
+                "<?php echo 'a';".into()
+            ),
+            (
+                "src/b.php".into(),
+                b"i:1;".to_vec(),
+                0o600 | 0x2000,
+                time,
+                "<?php echo 'b';".into()
+            ),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+pub fn test_raw_entry_flags() -> io::Result<()> {
+    let mut file = io::Cursor::new(Vec::new());
+    let mut writer = phar::create(&mut file, Signature::sha256())
+        .stub(&b"<?php "[..])?
+        .metadata(&b""[..])?;
+    let raw = |flags| phar::write::RawEntry {
+        original_size: 0,
+        timestamp: 0,
+        crc32: 0,
+        flags,
+    };
+    for &flags in &[0o644 | 0x3000, 0o644 | 0x4000, 0x10000] {
+        let err = writer
+            .raw_entry(&b"a"[..], &b""[..], raw(flags))
+            .expect_err("invalid flags are rejected");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+    writer.raw_entry(&b"a"[..], &b""[..], raw(0o644 | 0x2000))?;
+
+    let sized = phar::write::RawEntry {
+        original_size: 1,
+        ..raw(0o755)
+    };
+    let checksummed = phar::write::RawEntry {
+        crc32: 1,
+        ..raw(0o755)
+    };
+    for &dir in &[sized, checksummed] {
+        let err = writer
+            .raw_entry(&b"dir/"[..], &b""[..], dir)
+            .expect_err("directories cannot have contents");
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+    writer.raw_entry(&b"dir/"[..], &b""[..], raw(0o755))?;
+    Ok(())
+}
+
+#[test]
+pub fn test_feed_raw_mismatch() -> io::Result<()> {
+    let mut file = io::Cursor::new(Vec::new());
+    let mut writer = phar::create(&mut file, Signature::sha256())
+        .stub(&b"<?php "[..])?
+        .metadata(&b""[..])?;
+    writer.entry(
+        &b"a"[..],
+        &b""[..],
+        SystemTime::now(),
+        0o664,
+        Compression::None,
+    )?;
+    let mut contents = writer.contents()?;
+    let err = contents
+        .feed_raw(&b"a"[..])
+        .expect_err("entry was not added with raw_entry");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    Ok(())
+}
+
+#[cfg(feature = "comp-zopfli")]
+#[test]
+pub fn test_zopfli() -> io::Result<()> {
+    use std::io::Read;
+
+    let data = "<?php echo 'Hello, world!';\n".repeat(100);
+    let compressed_size = |compression| -> io::Result<u64> {
+        let mut file = io::Cursor::new(Vec::new());
+        let mut writer = phar::create(&mut file, Signature::sha256())
+            .stub(&b"<?php "[..])?
+            .metadata(&b""[..])?;
+        writer.entry(
+            &b"a.php"[..],
+            &b""[..],
+            SystemTime::now(),
+            0o664,
+            compression,
+        )?;
+        writer.contents()?.feed(data.as_bytes())?;
+
+        let mut phar = phar::Reader::<_, phar::read::index::NameIndexMap>::read(
+            io::Cursor::new(file.into_inner()),
+            phar::read::Options::builder().strict(true).build(),
+        )?;
+        phar.verify_crc32()?;
+        let mut size = 0;
+        phar.for_each_entry(|mut entry| {
+            assert_eq!(entry.info().compression(), compression.method());
+            let mut contents = String::new();
+            let _ = entry.open()?.read_to_string(&mut contents)?;
+            assert_eq!(contents, data);
+            size = entry.compressed_size();
+            Ok(())
+        })?;
+        Ok(size)
+    };
+
+    assert!(
+        compressed_size(Compression::Zopfli)? <= compressed_size(Compression::Zlib(Level::BEST))?
+    );
+    Ok(())
+}
+
+#[test]
+pub fn test_encoder_settings() -> io::Result<()> {
+    use std::io::Read;
+
+    let data: String = (0..20000)
+        .map(|i| format!("{} ", i * 7919 % 10007))
+        .collect();
+    let roundtrip = |compression| -> io::Result<()> {
+        let mut file = io::Cursor::new(Vec::new());
+        let mut writer = phar::create(&mut file, Signature::sha256())
+            .stub(&b"<?php "[..])?
+            .metadata(&b""[..])?;
+        writer.entry(
+            &b"a.txt"[..],
+            &b""[..],
+            SystemTime::now(),
+            0o664,
+            compression,
+        )?;
+        writer.contents()?.feed(data.as_bytes())?;
+
+        let mut phar = phar::Reader::<_, phar::read::index::NameIndexMap>::read(
+            io::Cursor::new(file.into_inner()),
+            phar::read::Options::builder().strict(true).build(),
+        )?;
+        phar.verify_crc32()?;
+        phar.for_each_entry(|mut entry| {
+            assert_eq!(entry.info().compression(), compression.method());
+            assert!(entry.compressed_size() < data.len() as u64);
+            let mut contents = String::new();
+            let _ = entry.open()?.read_to_string(&mut contents)?;
+            assert_eq!(contents, data);
+            Ok(())
+        })
+    };
+
+    roundtrip(Compression::BzipWorkFactor {
+        level: Level::FASTEST,
+        work_factor: 100,
+    })?;
+    let err = roundtrip(Compression::BzipWorkFactor {
+        level: Level::FASTEST,
+        work_factor: 251,
+    })
+    .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+    let window = |window_bits| Compression::ZlibWindow {
+        level: Level::BEST,
+        window_bits,
+    };
+    if cfg!(any(feature = "comp-zlib-c", feature = "comp-zlib-rs")) {
+        roundtrip(window(9))?;
+        roundtrip(window(15))?;
+        let err = roundtrip(window(16)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    } else {
+        let err = roundtrip(window(15)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+    Ok(())
+}
+
+#[test]
+pub fn test_observer() -> io::Result<()> {
+    use std::sync::mpsc;
+
+    use phar::progress::{EntryEvent, Observer, Totals};
+
+    struct Events(mpsc::Sender<String>);
+
+    impl Observer for Events {
+        fn entry_declared(&mut self, name: &[u8]) {
+            let _ = self
+                .0
+                .send(format!("declared {}", String::from_utf8_lossy(name)));
+        }
+
+        fn entry_done(&mut self, event: &EntryEvent<'_>) {
+            let _ = self.0.send(format!(
+                "done {} {}/{} {} -> {}",
+                String::from_utf8_lossy(event.name),
+                event.index,
+                event.total,
+                event.bytes_read,
+                event.bytes_written
+            ));
+        }
+
+        fn finished(&mut self, totals: &Totals) {
+            let _ = self.0.send(format!(
+                "finished {} {} -> {}",
+                totals.entries, totals.bytes_read, totals.bytes_written
+            ));
+        }
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    let mut file = io::Cursor::new(Vec::new());
+    let mut writer = phar::create(&mut file, Signature::sha256())
+        .stub(&b"<?php "[..])?
+        .metadata(&b""[..])?
+        .with_observer(Events(sender));
+    let now = SystemTime::now();
+    writer.entry(&b"./a.php"[..], &b""[..], now, 0o664, Compression::None)?;
+    writer.directory(&b"lib"[..], &b""[..], now, 0o775)?;
+    writer.entry(
+        &b"lib/b.php"[..],
+        &b""[..],
+        now,
+        0o664,
+        Compression::Zlib(Level::BEST),
+    )?;
+    let mut contents = writer.contents()?;
+    contents.feed(&b"a"[..])?;
+    let data = "b".repeat(1000);
+    contents.feed(data.as_bytes())?;
+    drop(contents);
+
+    let mut phar = phar::Reader::<_, phar::read::index::NameIndexMap>::read(
+        io::Cursor::new(file.into_inner()),
+        phar::read::Options::builder().strict(true).build(),
+    )?;
+    let mut compressed_size = 0;
+    phar.for_each_entry(|entry| {
+        if entry.name() == b"lib/b.php" {
+            compressed_size = entry.compressed_size();
+        }
+        Ok(())
+    })?;
+
+    let events: Vec<_> = receiver.try_iter().collect();
+    assert_eq!(
+        events,
+        vec![
+            "declared a.php".to_string(),
+            "declared lib/".to_string(),
+            "declared lib/b.php".to_string(),
+            "done a.php 0/2 1 -> 1".to_string(),
+            format!("done lib/b.php 1/2 1000 -> {}", compressed_size),
+            format!("finished 2 1001 -> {}", compressed_size + 1),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+pub fn test_plan() -> io::Result<()> {
+    use phar::write::{BuildOptions, Plan};
+
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("test_plan");
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    fs::create_dir_all(dir.join("src/empty"))?;
+    fs::write(dir.join("src/a.php"), "<?php echo 'a';\n".repeat(50))?;
+    fs::write(dir.join("README"), "readme")?;
+
+    let files = [
+        ("src", dir.join("src")),
+        ("src/empty", dir.join("src/empty")),
+        ("./src/a.php", dir.join("src/a.php")),
+        ("README", dir.join("README")),
+    ];
+    let mut plan = Plan::from_path_iter(
+        files.iter().map(|(name, path)| Ok((name, path))),
+        BuildOptions::builder()
+            .compression(Compression::Zlib(Level::BEST))
+            .directories(true)
+            .build(),
+    )?;
+    let names: Vec<_> = plan
+        .entries()
+        .iter()
+        .map(|entry| (entry.name.clone(), entry.is_dir, entry.size))
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            (b"src/".to_vec(), true, 0),
+            (b"src/empty/".to_vec(), true, 0),
+            (b"src/a.php".to_vec(), false, 800),
+            (b"README".to_vec(), false, 6),
+        ]
+    );
+
+    plan.retain(|entry| entry.name != b"src/empty/");
+    for entry in plan.entries_mut() {
+        if entry.name == b"README" {
+            entry.compression = Compression::None;
+        }
+    }
+    let estimate = plan.entries()[1].estimate_size()?;
+    assert!(estimate < 800);
+    assert_eq!(plan.entries()[2].estimate_size()?, 6);
+
+    let estimated_size = plan.estimate_size(b"<?php ", b"app.phar", b"", &Signature::sha256())?;
+    let contents_size = estimate + 6;
+    assert_eq!(
+        plan.size_with_contents(
+            b"<?php ",
+            b"app.phar",
+            b"",
+            &Signature::sha256(),
+            contents_size
+        ),
+        estimated_size
+    );
+    let mut file = io::Cursor::new(Vec::new());
+    phar::create(&mut file, Signature::sha256())
+        .stub(&b"<?php "[..])?
+        .alias(&b"app.phar"[..])?
+        .metadata(&b""[..])?
+        .build_from_plan(&plan)?;
+    let data = file.into_inner();
+    assert_eq!(estimated_size, data.len() as u64);
+
+    let mut phar = phar::Reader::<_, phar::read::index::NameIndexMap>::read(
+        io::Cursor::new(data),
+        phar::read::Options::builder().strict(true).build(),
+    )?;
+    phar.verify_crc32()?;
+    let mut entries = Vec::new();
+    phar.for_each_entry(|entry| {
+        entries.push((
+            String::from_utf8_lossy(entry.name()).into_owned(),
+            entry.info().compression(),
+            entry.compressed_size(),
+        ));
+        Ok(())
+    })?;
+    assert_eq!(
+        entries,
+        vec![
+            ("src/".into(), phar::CompressionMethod::None, 0),
+            ("src/a.php".into(), phar::CompressionMethod::Zlib, estimate),
+            ("README".into(), phar::CompressionMethod::None, 6),
+        ]
+    );
+
+    let duplicates = [("a", dir.join("README")), ("./a", dir.join("README"))];
+    let err = Plan::from_path_iter(
+        duplicates.iter().map(|(name, path)| Ok((name, path))),
+        Compression::None,
+    )
+    .expect_err("duplicate names are rejected");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+pub fn test_symlinks() -> io::Result<()> {
+    use std::os::unix::fs::symlink;
+
+    use std::iter;
+
+    use phar::write::{walk_directory, BuildOptions, Plan, Symlinks};
+
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("test_symlinks");
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    let root = dir.join("root");
+    fs::create_dir_all(root.join("sub"))?;
+    fs::write(root.join("a.php"), "a")?;
+    fs::write(root.join("sub/b.php"), "b")?;
+    symlink("a.php", root.join("link.php"))?;
+    symlink("sub", root.join("sublink"))?;
+    fs::write(dir.join("outside.txt"), "outside")?;
+    let escaping = dir.join("escaping");
+    fs::create_dir_all(&escaping)?;
+    symlink("../outside.txt", escaping.join("escape.txt"))?;
+    let cyclic = dir.join("cyclic");
+    fs::create_dir_all(cyclic.join("sub"))?;
+    symlink("..", cyclic.join("sub/up"))?;
+
+    let names = |root: &std::path::Path, symlinks| -> io::Result<Vec<String>> {
+        let mut names = Vec::new();
+        for pair in walk_directory(root, symlinks) {
+            let (name, path) = pair?;
+            if path.is_file() {
+                names.push(name.to_string_lossy().into_owned());
+            }
+        }
+        names.sort();
+        Ok(names)
+    };
+
+    assert_eq!(
+        names(&root, Symlinks::Contained)?,
+        vec!["a.php", "link.php", "sub/b.php", "sublink/b.php"]
+    );
+    assert_eq!(names(&root, Symlinks::Skip)?, vec!["a.php", "sub/b.php"]);
+    let err = names(&root, Symlinks::Error).expect_err("links are rejected");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+    assert_eq!(names(&escaping, Symlinks::Follow)?, vec!["escape.txt"]);
+    let err = names(&escaping, Symlinks::Contained).expect_err("escaping link is rejected");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert!(err.to_string().contains("outside"));
+
+    let err = names(&cyclic, Symlinks::Follow).expect_err("cycle is detected");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert!(err.to_string().contains("cycle"));
+
+    let mut file = io::Cursor::new(Vec::new());
+    let err = phar::create(&mut file, Signature::sha256())
+        .stub(&b"<?php "[..])?
+        .metadata(&b""[..])?
+        .build_from_directory(&escaping, Compression::None)
+        .expect_err("escaping link is rejected by default");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+    let plan_names = |name: &str, path: PathBuf, symlinks| -> io::Result<Vec<Vec<u8>>> {
+        let plan = Plan::from_path_iter(
+            iter::once(Ok((name, path))),
+            BuildOptions::builder().symlinks(symlinks).build(),
+        )?;
+        Ok(plan
+            .entries()
+            .iter()
+            .map(|entry| entry.name.clone())
+            .collect())
+    };
+    assert_eq!(
+        plan_names("lib/link.php", root.join("link.php"), Symlinks::Contained)?,
+        vec![b"lib/link.php".to_vec()]
+    );
+    let err = plan_names(
+        "escape.txt",
+        escaping.join("escape.txt"),
+        Symlinks::Contained,
+    )
+    .expect_err("escaping link is rejected by default");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(
+        plan_names("escape.txt", escaping.join("escape.txt"), Symlinks::Follow)?,
+        vec![b"escape.txt".to_vec()]
+    );
+    assert!(plan_names("escape.txt", escaping.join("escape.txt"), Symlinks::Skip)?.is_empty());
+    let err = plan_names("link.php", root.join("link.php"), Symlinks::Error)
+        .expect_err("links are rejected");
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+    let mut file = io::Cursor::new(Vec::new());
+    phar::create(&mut file, Signature::sha256())
+        .stub(&b"<?php "[..])?
+        .metadata(&b""[..])?
+        .build_from_directory(
+            &root,
+            BuildOptions::builder().symlinks(Symlinks::Skip).build(),
+        )?;
+    let mut phar = phar::Reader::<_, phar::read::index::NameHashMap>::read(
+        io::Cursor::new(file.into_inner()),
+        phar::read::Options::builder().strict(true).build(),
+    )?;
+    let mut names = phar.names()?;
+    names.sort();
+    assert_eq!(names, vec![b"a.php".to_vec(), b"sub/b.php".to_vec()]);
+    Ok(())
+}
@@ -1,5 +1,3 @@
-use std::io;
-
 use byte_unit::Byte;
 use yew::prelude::*;
 
@@ -32,10 +30,7 @@ impl Component for Comp {
                         log::debug!("Fetched result {:?}", result);
                         match result {
                             Ok(bytes) => {
-                                let reader = RawReader::read(
-                                    io::Cursor::new(bytes),
-                                    phar::read::Options::default(),
-                                );
+                                let reader = RawReader::read(bytes, phar::read::Options::default());
                                 match reader {
                                     Ok(reader) => {
                                         link.send_message(Msg::ReadDone(Reader::new(reader)));
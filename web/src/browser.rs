@@ -1,3 +1,5 @@
+use std::io::Read;
+
 use yew::prelude::*;
 
 use crate::file_list;
@@ -21,11 +23,11 @@ impl Component for Comp {
         match msg {
             Msg::OpenStub | Msg::OpenMetadata => {
                 let contents = {
-                    let mut reader = ctx.props().reader.borrow();
+                    let reader = ctx.props().reader.get();
 
                     match msg {
-                        Msg::OpenStub => reader.stub_bytes().unwrap().as_ref().to_vec(),
-                        Msg::OpenMetadata => reader.metadata_bytes().unwrap().as_ref().to_vec(),
+                        Msg::OpenStub => reader.stub().unwrap().into_owned(),
+                        Msg::OpenMetadata => reader.metadata().unwrap().into_owned(),
                         _ => unreachable!(),
                     }
                 };
@@ -46,16 +48,14 @@ impl Component for Comp {
                 let mut contents = None;
 
                 {
-                    let mut reader = ctx.props().reader.borrow();
+                    let reader = ctx.props().reader.get();
 
-                    let mut found = false;
                     reader
-                        .for_each_file(|name, read| {
-                            if !found && name == path {
+                        .for_each_prefix(&path, |mut entry| {
+                            if contents.is_none() && entry.name() == path {
                                 let mut buf = Vec::new();
-                                read.read_to_end(&mut buf).unwrap();
+                                entry.open()?.read_to_end(&mut buf)?;
                                 contents = Some(buf);
-                                found = true;
                             }
                             Ok(())
                         })
@@ -1,5 +1,3 @@
-use std::collections::BTreeMap;
-
 use yew::prelude::*;
 
 use crate::Reader;
@@ -72,45 +70,30 @@ impl Component for Comp {
             }
         });
 
-        let mut names = BTreeMap::new();
+        let mut names = Vec::new();
 
         {
-            let mut reader = ctx.props().reader.borrow();
-
-            reader.for_each_file(|name, _contents| {
-                let name = match name.strip_prefix(&self.dir[..]) {
-                    Some(name) => name,
-                    None => return Ok(()),
-                };
-
-                if let Some(offset) = name.iter().position(|&ch| ch == b'/') {
-                    let dir = &name[..=offset];
-                    let path: Vec<u8> = [&self.dir[..], dir].concat();
-
-                    names.entry(path).or_insert_with_key(|path| {
-                        let path = path.clone();
-
-                        html! {
-                            <li onclick={ctx.link().callback(move |_| Msg::ChangeDir(path.clone()))}>
-                                <a href="javascript:void(0)">{ String::from_utf8_lossy(dir) }</a>
-                            </li>
-                        }
+            let reader = ctx.props().reader.get();
+
+            for entry in reader.index().read_dir(&self.dir).into_iter().flatten() {
+                let name = entry.name();
+                let mut path: Vec<u8> = [&self.dir[..], name].concat();
+
+                if entry.is_dir() {
+                    path.push(b'/');
+                    names.push(html! {
+                        <li onclick={ctx.link().callback(move |_| Msg::ChangeDir(path.clone()))}>
+                            <a href="javascript:void(0)">{ format!("{}/", String::from_utf8_lossy(name)) }</a>
+                        </li>
                     });
                 } else {
-                    let path = [&self.dir[..], name].concat();
-
-                    names.entry(path).or_insert_with_key(|path| {
-                        let path = path.clone();
-                        html! {
-                            <li onclick={ctx.link().callback(move |_| Msg::OpenFile(path.clone()))}>
-                                <a href="javascript:void(0)">{ String::from_utf8_lossy(name) }</a>
-                            </li>
-                        }
+                    names.push(html! {
+                        <li onclick={ctx.link().callback(move |_| Msg::OpenFile(path.clone()))}>
+                            <a href="javascript:void(0)">{ String::from_utf8_lossy(name) }</a>
+                        </li>
                     });
                 }
-
-                Ok(())
-            }).unwrap();
+            }
         }
 
         html! {
@@ -126,7 +109,7 @@ impl Component for Comp {
                 { for parents }
                 <p class="menu-label">{ format!("Files under /{}", String::from_utf8_lossy(&self.dir)) }</p>
                 <ul class="menu-list">
-                    { for names.into_values() }
+                    { for names }
                 </ul>
             </>
         }
@@ -1,8 +1,4 @@
-use std::{
-    cell::{self, RefCell},
-    io,
-    rc::Rc,
-};
+use std::rc::Rc;
 
 use phar::read::index;
 use wasm_bindgen::prelude::*;
@@ -19,22 +15,22 @@ pub fn main() {
     yew::start_app::<app::Comp>();
 }
 
-pub type RawReader = phar::Reader<io::Cursor<Vec<u8>>, index::MetadataBTreeMap>;
+pub type RawReader = phar::read::SharedReader<Vec<u8>, index::Tree>;
 
 #[derive(Clone)]
 pub struct Reader {
-    inner: Rc<RefCell<RawReader>>,
+    inner: Rc<RawReader>,
 }
 
 impl Reader {
     pub fn new(reader: RawReader) -> Self {
         Self {
-            inner: Rc::new(RefCell::new(reader)),
+            inner: Rc::new(reader),
         }
     }
 
-    pub fn borrow(&self) -> cell::RefMut<RawReader> {
-        self.inner.borrow_mut()
+    pub fn get(&self) -> &RawReader {
+        &self.inner
     }
 }
 